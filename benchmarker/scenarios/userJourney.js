@@ -44,7 +44,7 @@ export default function () {
   const {
     session_token: sessionToken,
     dispatcher_id: dispatcherId,
-    area_id: areaId,
+    area_ids: areaIds,
   } = user;
 
   // 全ての依頼を取得する
@@ -61,8 +61,8 @@ export default function () {
   // 全ての依頼の中を表示する上で必要な画像を取得する
   getUserImages(sessionToken, allOrders);
 
-  // 対応待ちの依頼のみを取得する
-  const pendingOrders = getPendingOrders(sessionToken, areaId);
+  // 対応待ちの依頼のみを取得する (複数のエリアを担当していれば最初のエリア)
+  const pendingOrders = getPendingOrders(sessionToken, areaIds[0]);
 
   if (!pendingOrders || !pendingOrders[0]) {
     console.log(
@@ -0,0 +1,111 @@
+use crate::domains::auth_service::AuthService;
+use crate::domains::dto::user::{
+    AssignAreasRequestDto, AuditLogQueryDto, ChangeRoleRequestDto, SearchUsersQueryDto,
+};
+use crate::errors::AppError;
+use crate::infrastructure::password_reset_notifier::LocalPasswordResetNotifier;
+use crate::models::user::Principal;
+use crate::repositories::auth_repository::AuthRepositoryImpl;
+use actix_web::{web, HttpResponse};
+
+pub async fn search_users_handler(
+    service: web::Data<AuthService<AuthRepositoryImpl, LocalPasswordResetNotifier>>,
+    query: web::Query<SearchUsersQueryDto>,
+) -> Result<HttpResponse, AppError> {
+    let users = service
+        .search_users(
+            query.username_prefix.as_deref(),
+            query.role.as_deref(),
+            query.page.unwrap_or(0),
+            query.page_size.unwrap_or(20),
+        )
+        .await?;
+    Ok(HttpResponse::Ok().json(users))
+}
+
+pub async fn change_user_role_handler(
+    service: web::Data<AuthService<AuthRepositoryImpl, LocalPasswordResetNotifier>>,
+    principal: Principal,
+    path: web::Path<i32>,
+    req: web::Json<ChangeRoleRequestDto>,
+) -> Result<HttpResponse, AppError> {
+    let user = service
+        .change_user_role(
+            &principal,
+            path.into_inner(),
+            &req.role,
+            req.area_ids.as_deref(),
+        )
+        .await?;
+    Ok(HttpResponse::Ok().json(user))
+}
+
+pub async fn assign_dispatcher_areas_handler(
+    service: web::Data<AuthService<AuthRepositoryImpl, LocalPasswordResetNotifier>>,
+    principal: Principal,
+    path: web::Path<i32>,
+    req: web::Json<AssignAreasRequestDto>,
+) -> Result<HttpResponse, AppError> {
+    let user = service
+        .assign_dispatcher_areas(&principal, path.into_inner(), &req.area_ids)
+        .await?;
+    Ok(HttpResponse::Ok().json(user))
+}
+
+pub async fn disable_user_handler(
+    service: web::Data<AuthService<AuthRepositoryImpl, LocalPasswordResetNotifier>>,
+    principal: Principal,
+    path: web::Path<i32>,
+) -> Result<HttpResponse, AppError> {
+    let user = service
+        .set_user_disabled(&principal, path.into_inner(), true)
+        .await?;
+    Ok(HttpResponse::Ok().json(user))
+}
+
+pub async fn enable_user_handler(
+    service: web::Data<AuthService<AuthRepositoryImpl, LocalPasswordResetNotifier>>,
+    principal: Principal,
+    path: web::Path<i32>,
+) -> Result<HttpResponse, AppError> {
+    let user = service
+        .set_user_disabled(&principal, path.into_inner(), false)
+        .await?;
+    Ok(HttpResponse::Ok().json(user))
+}
+
+pub async fn reset_user_totp_handler(
+    service: web::Data<AuthService<AuthRepositoryImpl, LocalPasswordResetNotifier>>,
+    principal: Principal,
+    path: web::Path<i32>,
+) -> Result<HttpResponse, AppError> {
+    let user = service
+        .reset_user_totp(&principal, path.into_inner())
+        .await?;
+    Ok(HttpResponse::Ok().json(user))
+}
+
+pub async fn impersonate_user_handler(
+    service: web::Data<AuthService<AuthRepositoryImpl, LocalPasswordResetNotifier>>,
+    principal: Principal,
+    path: web::Path<i32>,
+) -> Result<HttpResponse, AppError> {
+    let response = service
+        .impersonate_user(&principal, path.into_inner())
+        .await?;
+    Ok(HttpResponse::Ok().json(response))
+}
+
+pub async fn get_audit_logs_handler(
+    service: web::Data<AuthService<AuthRepositoryImpl, LocalPasswordResetNotifier>>,
+    query: web::Query<AuditLogQueryDto>,
+) -> Result<HttpResponse, AppError> {
+    let logs = service
+        .get_admin_audit_logs(
+            query.target_user_id,
+            query.page.unwrap_or(0),
+            query.page_size.unwrap_or(20),
+        )
+        .await?;
+    Ok(HttpResponse::Ok().json(logs))
+}
@@ -1,11 +1,21 @@
 use crate::domains::auth_service::AuthService;
-use crate::domains::dto::auth::{LoginRequestDto, LogoutRequestDto, RegisterRequestDto};
+use crate::domains::dto::auth::{
+    ChangePasswordRequestDto, ClearLoginLockoutQueryDto, LoginRequestDto, LogoutRequestDto,
+    ProfileImageQueryDto, RegisterRequestDto, RequestPasswordResetRequestDto,
+    ResetPasswordRequestDto, TotpChallengeRequestDto, TotpLoginRequestDto,
+};
 use crate::errors::AppError;
+use crate::infrastructure::password_reset_notifier::LocalPasswordResetNotifier;
+use crate::infrastructure::profile_image::ProfileImageFormat;
+use crate::middlewares::auth_middleware::extract_session_token;
+use crate::models::user::Principal;
 use crate::repositories::auth_repository::AuthRepositoryImpl;
-use actix_web::{web, HttpResponse};
+use actix_multipart::Multipart;
+use actix_web::{http::header, web, HttpRequest, HttpResponse};
+use futures_util::TryStreamExt;
 
 pub async fn register_handler(
-    service: web::Data<AuthService<AuthRepositoryImpl>>,
+    service: web::Data<AuthService<AuthRepositoryImpl, LocalPasswordResetNotifier>>,
     req: web::Json<RegisterRequestDto>,
 ) -> Result<HttpResponse, AppError> {
     match service
@@ -17,18 +27,52 @@ pub async fn register_handler(
     }
 }
 
+// nginx が付ける X-Real-IP を優先する (X-Forwarded-For はクライアントが偽装できる)
+fn client_address(req: &HttpRequest) -> String {
+    req.headers()
+        .get("X-Real-IP")
+        .and_then(|value| value.to_str().ok())
+        .map(|value| value.to_string())
+        .or_else(|| req.peer_addr().map(|addr| addr.ip().to_string()))
+        .unwrap_or_else(|| "unknown".to_string())
+}
+
 pub async fn login_handler(
-    service: web::Data<AuthService<AuthRepositoryImpl>>,
+    service: web::Data<AuthService<AuthRepositoryImpl, LocalPasswordResetNotifier>>,
+    http_req: HttpRequest,
     req: web::Json<LoginRequestDto>,
 ) -> Result<HttpResponse, AppError> {
-    match service.login_user(&req.username, &req.password).await {
+    match service
+        .login_user(&req.username, &req.password, &client_address(&http_req))
+        .await
+    {
         Ok(response) => Ok(HttpResponse::Ok().json(response)),
         Err(err) => Err(err),
     }
 }
 
+pub async fn totp_login_handler(
+    service: web::Data<AuthService<AuthRepositoryImpl, LocalPasswordResetNotifier>>,
+    req: web::Json<TotpLoginRequestDto>,
+) -> Result<HttpResponse, AppError> {
+    let response = service
+        .verify_totp_login(&req.challenge_token, &req.code)
+        .await?;
+    Ok(HttpResponse::Ok().json(response))
+}
+
+pub async fn totp_login_enrollment_handler(
+    service: web::Data<AuthService<AuthRepositoryImpl, LocalPasswordResetNotifier>>,
+    req: web::Json<TotpChallengeRequestDto>,
+) -> Result<HttpResponse, AppError> {
+    let enrollment = service
+        .begin_totp_enrollment_for_login(&req.challenge_token)
+        .await?;
+    Ok(HttpResponse::Ok().json(enrollment))
+}
+
 pub async fn logout_handler(
-    service: web::Data<AuthService<AuthRepositoryImpl>>,
+    service: web::Data<AuthService<AuthRepositoryImpl, LocalPasswordResetNotifier>>,
     req: web::Json<LogoutRequestDto>,
 ) -> Result<HttpResponse, AppError> {
     match service.logout_user(&req.session_token).await {
@@ -37,13 +81,200 @@ pub async fn logout_handler(
     }
 }
 
+const DEFAULT_PROFILE_IMAGE_SIZE: u32 = 500;
+
+// Accept に image/webp があれば WebP、image/jpeg だけが明示されていれば JPEG を返す
+// どちらもなければ、これまでどおり PNG を返す
+fn negotiate_profile_image_format(req: &HttpRequest) -> ProfileImageFormat {
+    let accept = req
+        .headers()
+        .get(header::ACCEPT)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default();
+    let accepts = |media_type: &str| {
+        accept.split(',').any(|media_range| {
+            let mut params = media_range.split(';').map(str::trim);
+            params.next() == Some(media_type)
+                && params.all(|param| !matches!(param, "q=0" | "q=0.0" | "q=0.00" | "q=0.000"))
+        })
+    };
+
+    if accepts("image/webp") {
+        ProfileImageFormat::WebP
+    } else if accepts("image/jpeg") {
+        ProfileImageFormat::Jpeg
+    } else {
+        ProfileImageFormat::Png
+    }
+}
+
 pub async fn user_profile_image_handler(
-    service: web::Data<AuthService<AuthRepositoryImpl>>,
+    service: web::Data<AuthService<AuthRepositoryImpl, LocalPasswordResetNotifier>>,
     path: web::Path<i32>,
+    query: web::Query<ProfileImageQueryDto>,
+    req: HttpRequest,
 ) -> Result<HttpResponse, AppError> {
     let user_id = path.into_inner();
-    let profile_image_byte = service.get_resized_profile_image_byte(user_id).await?;
-    Ok(HttpResponse::Ok()
-        .content_type("image/png")
-        .body(profile_image_byte))
+    let variant = service
+        .get_profile_image_variant(
+            user_id,
+            query.size.unwrap_or(DEFAULT_PROFILE_IMAGE_SIZE),
+            negotiate_profile_image_format(&req),
+        )
+        .await?;
+
+    let not_modified = req
+        .headers()
+        .get(header::IF_NONE_MATCH)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|etags| {
+            etags
+                .split(',')
+                .any(|etag| etag.trim() == variant.etag || etag.trim() == "*")
+        });
+
+    let mut response = if not_modified {
+        HttpResponse::NotModified()
+    } else {
+        HttpResponse::Ok()
+    };
+    response
+        .insert_header((header::ETAG, variant.etag.clone()))
+        // URL は画像を変えても同じなので、毎回 ETag で再検証させる
+        .insert_header((header::CACHE_CONTROL, "no-cache"))
+        .insert_header((header::VARY, "Accept"));
+
+    if not_modified {
+        return Ok(response.finish());
+    }
+    Ok(response
+        .content_type(variant.content_type)
+        .body(variant.bytes))
+}
+
+// multipart の image フィールドを受け取る
+pub async fn update_profile_image_handler(
+    service: web::Data<AuthService<AuthRepositoryImpl, LocalPasswordResetNotifier>>,
+    principal: Principal,
+    path: web::Path<i32>,
+    mut payload: Multipart,
+) -> Result<HttpResponse, AppError> {
+    let max_upload_bytes = service.max_profile_image_upload_bytes();
+    let mut image: Option<Vec<u8>> = None;
+
+    while let Some(mut field) = payload.try_next().await.map_err(|_| AppError::BadRequest)? {
+        if field.name() != Some("image") {
+            continue;
+        }
+        let mut bytes = Vec::new();
+        while let Some(chunk) = field.try_next().await.map_err(|_| AppError::BadRequest)? {
+            if bytes.len() + chunk.len() > max_upload_bytes {
+                return Err(AppError::BadRequest);
+            }
+            bytes.extend_from_slice(&chunk);
+        }
+        image = Some(bytes);
+    }
+
+    let image = image.ok_or(AppError::BadRequest)?;
+    let response = service
+        .update_profile_image(&principal, path.into_inner(), image.into())
+        .await?;
+    Ok(HttpResponse::Ok().json(response))
+}
+
+pub async fn refresh_session_handler(
+    service: web::Data<AuthService<AuthRepositoryImpl, LocalPasswordResetNotifier>>,
+    req: HttpRequest,
+) -> Result<HttpResponse, AppError> {
+    let session_token = extract_session_token(&req).ok_or(AppError::Unauthorized)?;
+    let response = service.refresh_session(&session_token).await?;
+    Ok(HttpResponse::Ok().json(response))
+}
+
+pub async fn get_sessions_handler(
+    service: web::Data<AuthService<AuthRepositoryImpl, LocalPasswordResetNotifier>>,
+    principal: Principal,
+    req: HttpRequest,
+) -> Result<HttpResponse, AppError> {
+    let session_token = extract_session_token(&req).ok_or(AppError::Unauthorized)?;
+    let sessions = service
+        .get_sessions(principal.user_id, &session_token)
+        .await?;
+    Ok(HttpResponse::Ok().json(sessions))
+}
+
+pub async fn revoke_session_handler(
+    service: web::Data<AuthService<AuthRepositoryImpl, LocalPasswordResetNotifier>>,
+    principal: Principal,
+    path: web::Path<i32>,
+) -> Result<HttpResponse, AppError> {
+    service
+        .revoke_session(principal.user_id, path.into_inner())
+        .await?;
+    Ok(HttpResponse::NoContent().finish())
+}
+
+pub async fn revoke_all_sessions_handler(
+    service: web::Data<AuthService<AuthRepositoryImpl, LocalPasswordResetNotifier>>,
+    principal: Principal,
+) -> Result<HttpResponse, AppError> {
+    service.revoke_all_sessions(principal.user_id).await?;
+    Ok(HttpResponse::NoContent().finish())
+}
+
+pub async fn get_login_lockouts_handler(
+    service: web::Data<AuthService<AuthRepositoryImpl, LocalPasswordResetNotifier>>,
+) -> Result<HttpResponse, AppError> {
+    Ok(HttpResponse::Ok().json(service.get_login_lockouts()))
+}
+
+pub async fn clear_login_lockout_handler(
+    service: web::Data<AuthService<AuthRepositoryImpl, LocalPasswordResetNotifier>>,
+    principal: Principal,
+    query: web::Query<ClearLoginLockoutQueryDto>,
+) -> Result<HttpResponse, AppError> {
+    service
+        .clear_login_lockout(&principal, query.kind, &query.key)
+        .await?;
+    Ok(HttpResponse::NoContent().finish())
+}
+
+pub async fn change_password_handler(
+    service: web::Data<AuthService<AuthRepositoryImpl, LocalPasswordResetNotifier>>,
+    principal: Principal,
+    http_req: HttpRequest,
+    req: web::Json<ChangePasswordRequestDto>,
+) -> Result<HttpResponse, AppError> {
+    let session_token = extract_session_token(&http_req).ok_or(AppError::Unauthorized)?;
+    service
+        .change_password(
+            principal.user_id,
+            &session_token,
+            &req.old_password,
+            &req.new_password,
+        )
+        .await?;
+    Ok(HttpResponse::NoContent().finish())
+}
+
+pub async fn request_password_reset_handler(
+    service: web::Data<AuthService<AuthRepositoryImpl, LocalPasswordResetNotifier>>,
+    http_req: HttpRequest,
+    req: web::Json<RequestPasswordResetRequestDto>,
+) -> Result<HttpResponse, AppError> {
+    service
+        .request_password_reset(&req.username, &client_address(&http_req))
+        .await?;
+    Ok(HttpResponse::Accepted().finish())
+}
+
+pub async fn reset_password_handler(
+    service: web::Data<AuthService<AuthRepositoryImpl, LocalPasswordResetNotifier>>,
+    req: web::Json<ResetPasswordRequestDto>,
+) -> Result<HttpResponse, AppError> {
+    service
+        .reset_password(&req.reset_token, &req.new_password)
+        .await?;
+    Ok(HttpResponse::NoContent().finish())
 }
@@ -0,0 +1,82 @@
+use std::fmt::Write;
+
+use crate::domains::auth_service::AuthService;
+use crate::infrastructure::password_reset_notifier::LocalPasswordResetNotifier;
+use crate::repositories::auth_repository::AuthRepositoryImpl;
+use actix_web::{web, HttpResponse};
+
+// Prometheus のテキスト形式で返す
+pub async fn metrics_handler(
+    auth_service: web::Data<AuthService<AuthRepositoryImpl, LocalPasswordResetNotifier>>,
+) -> HttpResponse {
+    let session_cache = auth_service.session_cache_stats();
+    let cpu_pool = auth_service.cpu_pool_stats();
+
+    let mut body = String::new();
+    write_metric(
+        &mut body,
+        "session_cache_hits_total",
+        "counter",
+        "Session lookups served from the in-process cache",
+        session_cache.hits,
+    );
+    write_metric(
+        &mut body,
+        "session_cache_misses_total",
+        "counter",
+        "Session lookups that fell through to the database",
+        session_cache.misses,
+    );
+    write_metric(
+        &mut body,
+        "session_cache_entries",
+        "gauge",
+        "Sessions currently held in the in-process cache",
+        session_cache.entries,
+    );
+    write_metric(
+        &mut body,
+        "cpu_pool_threads",
+        "gauge",
+        "Threads running password hashing and image processing",
+        cpu_pool.threads,
+    );
+    write_metric(
+        &mut body,
+        "cpu_pool_queue_capacity",
+        "gauge",
+        "Jobs the CPU pool accepts before rejecting with 503",
+        cpu_pool.queue_capacity,
+    );
+    write_metric(
+        &mut body,
+        "cpu_pool_queue_depth",
+        "gauge",
+        "Jobs waiting for a CPU pool thread",
+        cpu_pool.queued,
+    );
+    write_metric(
+        &mut body,
+        "cpu_pool_running",
+        "gauge",
+        "Jobs currently running on the CPU pool",
+        cpu_pool.running,
+    );
+    write_metric(
+        &mut body,
+        "cpu_pool_rejected_total",
+        "counter",
+        "Jobs rejected because the CPU pool queue was full",
+        cpu_pool.rejected,
+    );
+
+    HttpResponse::Ok()
+        .content_type("text/plain; version=0.0.4")
+        .body(body)
+}
+
+fn write_metric(body: &mut String, name: &str, metric_type: &str, help: &str, value: u64) {
+    let _ = writeln!(body, "# HELP {} {}", name, help);
+    let _ = writeln!(body, "# TYPE {} {}", name, metric_type);
+    let _ = writeln!(body, "{} {}", name, value);
+}
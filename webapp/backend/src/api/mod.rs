@@ -1,6 +1,12 @@
+pub mod admin_handler;
 pub mod auth_handler;
 pub mod health_check_handler;
 pub mod map_handler;
+pub mod metrics_handler;
 pub mod order_handler;
 pub mod result_handler;
+pub mod shift_handler;
+pub mod sse;
 pub mod tow_truck_handler;
+pub mod user_handler;
+pub mod webhook_handler;
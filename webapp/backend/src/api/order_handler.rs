@@ -1,14 +1,16 @@
+use crate::api::sse::{sse_message, sse_response};
 use crate::domains::dto::order::{
-    ClientOrderRequestDto, DispatcherOrderRequestDto, UpdateOrderStatusRequestDto,
+    ClientOrderRequestDto, DispatcherOrderRequestDto, PaginatedOrderQueryDto,
+    UpdateOrderStatusRequestDto,
 };
 use crate::domains::order_service::OrderService;
 use crate::errors::AppError;
+use crate::models::user::Principal;
 use crate::repositories::auth_repository::AuthRepositoryImpl;
 use crate::repositories::map_repository::MapRepositoryImpl;
 use crate::repositories::order_repository::OrderRepositoryImpl;
 use crate::repositories::tow_truck_repository::TowTruckRepositoryImpl;
-use actix_web::{web, HttpResponse};
-use serde::Deserialize;
+use actix_web::{web, web::Bytes, HttpResponse};
 
 pub async fn update_order_status_handler(
     service: web::Data<
@@ -19,9 +21,13 @@ pub async fn update_order_status_handler(
             MapRepositoryImpl,
         >,
     >,
+    principal: Principal,
     req: web::Json<UpdateOrderStatusRequestDto>,
 ) -> Result<HttpResponse, AppError> {
-    match service.update_order_status(req.order_id, &req.status).await {
+    match service
+        .update_order_status(&principal, req.order_id, &req.status)
+        .await
+    {
         Ok(_) => Ok(HttpResponse::Ok().finish()),
         Err(err) => Err(err),
     }
@@ -36,24 +42,15 @@ pub async fn get_order_handler(
             MapRepositoryImpl,
         >,
     >,
+    principal: Principal,
     path: web::Path<i32>,
 ) -> Result<HttpResponse, AppError> {
-    match service.get_order_by_id(path.into_inner()).await {
+    match service.get_order_by_id(&principal, path.into_inner()).await {
         Ok(order) => Ok(HttpResponse::Ok().json(order)),
         Err(err) => Err(err),
     }
 }
 
-#[derive(Deserialize, Debug)]
-pub struct PaginatedOrderQuery {
-    page: Option<i32>,
-    page_size: Option<i32>,
-    sort_by: Option<String>,
-    sort_order: Option<String>,
-    status: Option<String>,
-    area: Option<i32>,
-}
-
 pub async fn get_paginated_orders_handler(
     service: web::Data<
         OrderService<
@@ -63,17 +60,11 @@ pub async fn get_paginated_orders_handler(
             MapRepositoryImpl,
         >,
     >,
-    query: web::Query<PaginatedOrderQuery>,
+    principal: Principal,
+    query: web::Query<PaginatedOrderQueryDto>,
 ) -> Result<HttpResponse, AppError> {
     match service
-        .get_paginated_orders(
-            query.page.unwrap_or(0),
-            query.page_size.unwrap_or(10),
-            query.sort_by.clone(),
-            query.sort_order.clone(),
-            query.status.clone(),
-            query.area,
-        )
+        .get_paginated_orders(&principal, query.into_inner())
         .await
     {
         Ok(orders) => Ok(HttpResponse::Ok().json(orders)),
@@ -90,10 +81,17 @@ pub async fn create_client_order_handler(
             MapRepositoryImpl,
         >,
     >,
+    principal: Principal,
     req: web::Json<ClientOrderRequestDto>,
 ) -> Result<HttpResponse, AppError> {
     match service
-        .create_client_order(req.client_id, req.node_id, req.car_value)
+        .create_client_order(
+            principal.user_id,
+            req.node_id,
+            req.car_value,
+            req.required_vehicle_class.clone(),
+            req.sponsor.clone(),
+        )
         .await
     {
         Ok(_) => Ok(HttpResponse::Created().finish()),
@@ -110,18 +108,102 @@ pub async fn create_dispatcher_order_handler(
             MapRepositoryImpl,
         >,
     >,
+    principal: Principal,
     req: web::Json<DispatcherOrderRequestDto>,
 ) -> Result<HttpResponse, AppError> {
-    match service
-        .create_dispatcher_order(
-            req.order_id,
-            req.dispatcher_id,
-            req.tow_truck_id,
-            req.order_time,
-        )
-        .await
-    {
-        Ok(_) => Ok(HttpResponse::Ok().finish()),
-        Err(err) => Err(err),
-    }
+    let offer = service
+        .create_dispatcher_order(&principal, req.order_id, req.tow_truck_id)
+        .await?;
+    Ok(HttpResponse::Ok().json(offer))
+}
+
+pub async fn get_order_offers_handler(
+    service: web::Data<
+        OrderService<
+            OrderRepositoryImpl,
+            TowTruckRepositoryImpl,
+            AuthRepositoryImpl,
+            MapRepositoryImpl,
+        >,
+    >,
+    principal: Principal,
+    path: web::Path<i32>,
+) -> Result<HttpResponse, AppError> {
+    let offers = service
+        .get_order_offers(&principal, path.into_inner())
+        .await?;
+    Ok(HttpResponse::Ok().json(offers))
+}
+
+pub async fn get_order_offer_inbox_handler(
+    service: web::Data<
+        OrderService<
+            OrderRepositoryImpl,
+            TowTruckRepositoryImpl,
+            AuthRepositoryImpl,
+            MapRepositoryImpl,
+        >,
+    >,
+    principal: Principal,
+) -> Result<HttpResponse, AppError> {
+    let offers = service.get_order_offer_inbox(principal.user_id).await?;
+    Ok(HttpResponse::Ok().json(offers))
+}
+
+pub async fn accept_order_offer_handler(
+    service: web::Data<
+        OrderService<
+            OrderRepositoryImpl,
+            TowTruckRepositoryImpl,
+            AuthRepositoryImpl,
+            MapRepositoryImpl,
+        >,
+    >,
+    principal: Principal,
+    path: web::Path<i32>,
+) -> Result<HttpResponse, AppError> {
+    service
+        .accept_order_offer(path.into_inner(), principal.user_id)
+        .await?;
+    Ok(HttpResponse::Ok().finish())
+}
+
+pub async fn decline_order_offer_handler(
+    service: web::Data<
+        OrderService<
+            OrderRepositoryImpl,
+            TowTruckRepositoryImpl,
+            AuthRepositoryImpl,
+            MapRepositoryImpl,
+        >,
+    >,
+    principal: Principal,
+    path: web::Path<i32>,
+) -> Result<HttpResponse, AppError> {
+    service
+        .decline_order_offer(path.into_inner(), principal.user_id)
+        .await?;
+    Ok(HttpResponse::Ok().finish())
+}
+
+// ログイン中のクライアント自身の依頼に関するイベントだけを送る
+pub async fn stream_orders_handler(
+    service: web::Data<
+        OrderService<
+            OrderRepositoryImpl,
+            TowTruckRepositoryImpl,
+            AuthRepositoryImpl,
+            MapRepositoryImpl,
+        >,
+    >,
+    principal: Principal,
+) -> Result<HttpResponse, AppError> {
+    let user_id = principal.user_id;
+    let receiver = service.subscribe_order_events();
+
+    Ok(sse_response(
+        Bytes::from_static(b": connected\n\n"),
+        receiver,
+        move |event| (event.client_id == user_id).then(|| sse_message(&event.event_type, event)),
+    ))
 }
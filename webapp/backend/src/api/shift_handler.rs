@@ -0,0 +1,61 @@
+use crate::domains::shift_service::ShiftService;
+use crate::errors::AppError;
+use crate::models::user::Principal;
+use crate::repositories::shift_repository::ShiftRepositoryImpl;
+use crate::repositories::tow_truck_repository::TowTruckRepositoryImpl;
+use actix_web::{web, HttpResponse};
+use serde::Deserialize;
+
+pub async fn start_shift_handler(
+    service: web::Data<ShiftService<ShiftRepositoryImpl, TowTruckRepositoryImpl>>,
+    principal: Principal,
+) -> Result<HttpResponse, AppError> {
+    let shift = service.start_shift(principal.user_id).await?;
+    Ok(HttpResponse::Created().json(shift))
+}
+
+pub async fn end_shift_handler(
+    service: web::Data<ShiftService<ShiftRepositoryImpl, TowTruckRepositoryImpl>>,
+    principal: Principal,
+) -> Result<HttpResponse, AppError> {
+    service.end_shift(principal.user_id).await?;
+    Ok(HttpResponse::Ok().finish())
+}
+
+#[derive(Deserialize, Debug)]
+pub struct ShiftHistoryQuery {
+    // ドライバーは自分の履歴しか見られないので省略できる
+    driver_id: Option<i32>,
+    page: Option<i32>,
+    page_size: Option<i32>,
+}
+
+pub async fn get_shift_history_handler(
+    service: web::Data<ShiftService<ShiftRepositoryImpl, TowTruckRepositoryImpl>>,
+    principal: Principal,
+    query: web::Query<ShiftHistoryQuery>,
+) -> Result<HttpResponse, AppError> {
+    let shifts = service
+        .get_shift_history(
+            &principal,
+            query.driver_id,
+            query.page.unwrap_or(0),
+            query.page_size.unwrap_or(10),
+        )
+        .await?;
+    Ok(HttpResponse::Ok().json(shifts))
+}
+
+#[derive(Deserialize, Debug)]
+pub struct OnDutyQuery {
+    area: Option<i32>,
+}
+
+pub async fn get_on_duty_drivers_handler(
+    service: web::Data<ShiftService<ShiftRepositoryImpl, TowTruckRepositoryImpl>>,
+    principal: Principal,
+    query: web::Query<OnDutyQuery>,
+) -> Result<HttpResponse, AppError> {
+    let drivers = service.get_on_duty_drivers(&principal, query.area).await?;
+    Ok(HttpResponse::Ok().json(drivers))
+}
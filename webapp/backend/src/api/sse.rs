@@ -0,0 +1,49 @@
+use actix_web::{web::Bytes, HttpResponse};
+use futures_util::stream::{self, StreamExt};
+use serde::Serialize;
+use tokio::sync::broadcast::{self, error::RecvError};
+
+pub fn sse_message<T: Serialize>(event: &str, data: &T) -> Bytes {
+    let data = serde_json::to_string(data).unwrap_or_default();
+    Bytes::from(format!("event: {}\ndata: {}\n\n", event, data))
+}
+
+// initial を送ったあと、receiver に届いたイベントのうち to_message が Some を返すものを送り続ける
+pub fn sse_response<T, F>(
+    initial: Bytes,
+    receiver: broadcast::Receiver<T>,
+    to_message: F,
+) -> HttpResponse
+where
+    T: Clone + 'static,
+    F: Fn(&T) -> Option<Bytes> + 'static,
+{
+    let events = stream::unfold(
+        (receiver, to_message),
+        |(mut receiver, to_message)| async move {
+            loop {
+                match receiver.recv().await {
+                    Ok(event) => {
+                        if let Some(message) = to_message(&event) {
+                            return Some((message, (receiver, to_message)));
+                        }
+                    }
+                    // 取りこぼしは諦めて次のイベントから送る
+                    Err(RecvError::Lagged(_)) => continue,
+                    Err(RecvError::Closed) => return None,
+                }
+            }
+        },
+    );
+
+    let body = stream::once(async move { initial })
+        .chain(events)
+        .map(Ok::<_, actix_web::Error>);
+
+    HttpResponse::Ok()
+        .content_type("text/event-stream")
+        .insert_header(("Cache-Control", "no-cache"))
+        // nginx にバッファリングさせない
+        .insert_header(("X-Accel-Buffering", "no"))
+        .streaming(body)
+}
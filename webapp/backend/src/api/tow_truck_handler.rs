@@ -1,9 +1,14 @@
+use crate::api::sse::{sse_message, sse_response};
 use crate::domains::tow_truck_service::TowTruckService;
 use crate::errors::AppError;
+use crate::models::user::{Principal, Role};
+use crate::repositories::auth_repository::AuthRepositoryImpl;
 use crate::repositories::order_repository::OrderRepositoryImpl;
 use crate::repositories::tow_truck_repository::TowTruckRepositoryImpl;
 use crate::{
-    domains::dto::tow_truck::UpdateLocationRequestDto,
+    domains::dto::tow_truck::{
+        CreateTowTruckRequestDto, UpdateLocationRequestDto, UpdateTowTruckRequestDto,
+    },
     repositories::map_repository::MapRepositoryImpl,
 };
 use actix_web::{web, HttpResponse};
@@ -19,12 +24,19 @@ pub struct PaginatedTowTruckQuery {
 
 pub async fn get_paginated_tow_trucks_handler(
     service: web::Data<
-        TowTruckService<TowTruckRepositoryImpl, OrderRepositoryImpl, MapRepositoryImpl>,
+        TowTruckService<
+            TowTruckRepositoryImpl,
+            OrderRepositoryImpl,
+            MapRepositoryImpl,
+            AuthRepositoryImpl,
+        >,
     >,
+    principal: Principal,
     query: web::Query<PaginatedTowTruckQuery>,
 ) -> Result<HttpResponse, AppError> {
     let tow_trucks = service
         .get_all_tow_trucks(
+            &principal,
             query.page.unwrap_or(0),
             query.page_size.unwrap_or(-1),
             query.status.clone(),
@@ -37,12 +49,18 @@ pub async fn get_paginated_tow_trucks_handler(
 
 pub async fn get_tow_truck_handler(
     service: web::Data<
-        TowTruckService<TowTruckRepositoryImpl, OrderRepositoryImpl, MapRepositoryImpl>,
+        TowTruckService<
+            TowTruckRepositoryImpl,
+            OrderRepositoryImpl,
+            MapRepositoryImpl,
+            AuthRepositoryImpl,
+        >,
     >,
+    principal: Principal,
     path: web::Path<i32>,
 ) -> Result<HttpResponse, AppError> {
     let id = path.into_inner();
-    match service.get_tow_truck_by_id(id).await {
+    match service.get_tow_truck_by_id(&principal, id).await {
         Ok(Some(tow_truck)) => Ok(HttpResponse::Ok().json(tow_truck)),
         Ok(None) => Ok(HttpResponse::NotFound().finish()),
         Err(err) => Err(err),
@@ -51,13 +69,22 @@ pub async fn get_tow_truck_handler(
 
 pub async fn update_location_handler(
     service: web::Data<
-        TowTruckService<TowTruckRepositoryImpl, OrderRepositoryImpl, MapRepositoryImpl>,
+        TowTruckService<
+            TowTruckRepositoryImpl,
+            OrderRepositoryImpl,
+            MapRepositoryImpl,
+            AuthRepositoryImpl,
+        >,
     >,
+    principal: Principal,
     req: web::Json<UpdateLocationRequestDto>,
 ) -> Result<HttpResponse, AppError> {
-    service
-        .update_location(req.tow_truck_id, req.node_id)
-        .await?;
+    // 自分が運転しているレッカー車の位置だけを更新できる (admin はレッカー車を持たないので更新できない)
+    let tow_truck_id = match principal.role {
+        Role::Driver => principal.tow_truck_id.ok_or(AppError::Forbidden)?,
+        _ => return Err(AppError::Forbidden),
+    };
+    service.update_location(tow_truck_id, req.node_id).await?;
     Ok(HttpResponse::Ok().finish())
 }
 
@@ -68,12 +95,18 @@ pub struct TowTruckQuery {
 
 pub async fn get_nearest_available_tow_trucks_handler(
     service: web::Data<
-        TowTruckService<TowTruckRepositoryImpl, OrderRepositoryImpl, MapRepositoryImpl>,
+        TowTruckService<
+            TowTruckRepositoryImpl,
+            OrderRepositoryImpl,
+            MapRepositoryImpl,
+            AuthRepositoryImpl,
+        >,
     >,
+    principal: Principal,
     query: web::Query<TowTruckQuery>,
 ) -> Result<HttpResponse, AppError> {
     match service
-        .get_nearest_available_tow_trucks(query.order_id)
+        .get_nearest_available_tow_trucks(&principal, query.order_id)
         .await
     {
         Ok(Some(tow_truck)) => Ok(HttpResponse::Ok().json(tow_truck)),
@@ -81,3 +114,145 @@ pub async fn get_nearest_available_tow_trucks_handler(
         Err(err) => Err(err),
     }
 }
+
+#[derive(Deserialize, Debug)]
+pub struct StaleTowTruckQuery {
+    area: Option<i32>,
+}
+
+pub async fn get_stale_tow_trucks_handler(
+    service: web::Data<
+        TowTruckService<
+            TowTruckRepositoryImpl,
+            OrderRepositoryImpl,
+            MapRepositoryImpl,
+            AuthRepositoryImpl,
+        >,
+    >,
+    principal: Principal,
+    query: web::Query<StaleTowTruckQuery>,
+) -> Result<HttpResponse, AppError> {
+    let tow_trucks = service.get_stale_tow_trucks(&principal, query.area).await?;
+    Ok(HttpResponse::Ok().json(tow_trucks))
+}
+
+pub async fn create_tow_truck_handler(
+    service: web::Data<
+        TowTruckService<
+            TowTruckRepositoryImpl,
+            OrderRepositoryImpl,
+            MapRepositoryImpl,
+            AuthRepositoryImpl,
+        >,
+    >,
+    req: web::Json<CreateTowTruckRequestDto>,
+) -> Result<HttpResponse, AppError> {
+    let tow_truck = service
+        .create_tow_truck(
+            req.driver_id,
+            req.area_id,
+            req.node_id,
+            req.vehicle_class.clone(),
+        )
+        .await?;
+    Ok(HttpResponse::Created().json(tow_truck))
+}
+
+pub async fn update_tow_truck_handler(
+    service: web::Data<
+        TowTruckService<
+            TowTruckRepositoryImpl,
+            OrderRepositoryImpl,
+            MapRepositoryImpl,
+            AuthRepositoryImpl,
+        >,
+    >,
+    path: web::Path<i32>,
+    req: web::Json<UpdateTowTruckRequestDto>,
+) -> Result<HttpResponse, AppError> {
+    let tow_truck = service
+        .update_tow_truck(
+            path.into_inner(),
+            req.driver_id,
+            req.area_id,
+            req.node_id,
+            req.vehicle_class.clone(),
+        )
+        .await?;
+    Ok(HttpResponse::Ok().json(tow_truck))
+}
+
+pub async fn retire_tow_truck_handler(
+    service: web::Data<
+        TowTruckService<
+            TowTruckRepositoryImpl,
+            OrderRepositoryImpl,
+            MapRepositoryImpl,
+            AuthRepositoryImpl,
+        >,
+    >,
+    path: web::Path<i32>,
+) -> Result<HttpResponse, AppError> {
+    service.retire_tow_truck(path.into_inner()).await?;
+    Ok(HttpResponse::NoContent().finish())
+}
+
+#[derive(Deserialize, Debug)]
+pub struct GeofenceEventQuery {
+    area: i32,
+    page: Option<i32>,
+    page_size: Option<i32>,
+}
+
+pub async fn get_geofence_events_handler(
+    service: web::Data<
+        TowTruckService<
+            TowTruckRepositoryImpl,
+            OrderRepositoryImpl,
+            MapRepositoryImpl,
+            AuthRepositoryImpl,
+        >,
+    >,
+    principal: Principal,
+    query: web::Query<GeofenceEventQuery>,
+) -> Result<HttpResponse, AppError> {
+    let events = service
+        .get_geofence_events(
+            &principal,
+            query.area,
+            query.page.unwrap_or(0),
+            query.page_size.unwrap_or(10),
+        )
+        .await?;
+    Ok(HttpResponse::Ok().json(events))
+}
+
+#[derive(Deserialize, Debug)]
+pub struct TowTruckStreamQuery {
+    area: i32,
+}
+
+pub async fn stream_tow_trucks_handler(
+    service: web::Data<
+        TowTruckService<
+            TowTruckRepositoryImpl,
+            OrderRepositoryImpl,
+            MapRepositoryImpl,
+            AuthRepositoryImpl,
+        >,
+    >,
+    principal: Principal,
+    query: web::Query<TowTruckStreamQuery>,
+) -> Result<HttpResponse, AppError> {
+    let area = query.area;
+    let (snapshot, receiver) = service.subscribe_tow_trucks(&principal, area).await?;
+
+    Ok(sse_response(
+        sse_message("snapshot", &snapshot),
+        receiver,
+        move |event| {
+            (event.tow_truck.area_id == area)
+                .then(|| sse_message(&event.event_type, &event.tow_truck))
+        },
+    ))
+}
@@ -0,0 +1,73 @@
+use crate::domains::auth_service::AuthService;
+use crate::domains::dto::user::{
+    ConfirmTotpRequestDto, DeleteMeRequestDto, DisableTotpRequestDto, UpdateMeRequestDto,
+};
+use crate::errors::AppError;
+use crate::infrastructure::password_reset_notifier::LocalPasswordResetNotifier;
+use crate::models::user::Principal;
+use crate::repositories::auth_repository::AuthRepositoryImpl;
+use actix_web::{web, HttpResponse};
+
+pub async fn get_me_handler(
+    service: web::Data<AuthService<AuthRepositoryImpl, LocalPasswordResetNotifier>>,
+    principal: Principal,
+) -> Result<HttpResponse, AppError> {
+    let me = service.get_me(&principal).await?;
+    Ok(HttpResponse::Ok().json(me))
+}
+
+pub async fn update_me_handler(
+    service: web::Data<AuthService<AuthRepositoryImpl, LocalPasswordResetNotifier>>,
+    principal: Principal,
+    req: web::Json<UpdateMeRequestDto>,
+) -> Result<HttpResponse, AppError> {
+    let me = service
+        .update_me(&principal, req.username.as_deref())
+        .await?;
+    Ok(HttpResponse::Ok().json(me))
+}
+
+pub async fn delete_me_handler(
+    service: web::Data<AuthService<AuthRepositoryImpl, LocalPasswordResetNotifier>>,
+    principal: Principal,
+    req: web::Json<DeleteMeRequestDto>,
+) -> Result<HttpResponse, AppError> {
+    service.delete_me(&principal, &req.password).await?;
+    Ok(HttpResponse::NoContent().finish())
+}
+
+pub async fn get_totp_status_handler(
+    service: web::Data<AuthService<AuthRepositoryImpl, LocalPasswordResetNotifier>>,
+    principal: Principal,
+) -> Result<HttpResponse, AppError> {
+    let status = service.get_totp_status(&principal).await?;
+    Ok(HttpResponse::Ok().json(status))
+}
+
+pub async fn begin_totp_enrollment_handler(
+    service: web::Data<AuthService<AuthRepositoryImpl, LocalPasswordResetNotifier>>,
+    principal: Principal,
+) -> Result<HttpResponse, AppError> {
+    let enrollment = service.begin_totp_enrollment(&principal).await?;
+    Ok(HttpResponse::Ok().json(enrollment))
+}
+
+pub async fn confirm_totp_enrollment_handler(
+    service: web::Data<AuthService<AuthRepositoryImpl, LocalPasswordResetNotifier>>,
+    principal: Principal,
+    req: web::Json<ConfirmTotpRequestDto>,
+) -> Result<HttpResponse, AppError> {
+    let recovery_codes = service
+        .confirm_totp_enrollment(&principal, &req.code)
+        .await?;
+    Ok(HttpResponse::Ok().json(recovery_codes))
+}
+
+pub async fn disable_totp_handler(
+    service: web::Data<AuthService<AuthRepositoryImpl, LocalPasswordResetNotifier>>,
+    principal: Principal,
+    req: web::Json<DisableTotpRequestDto>,
+) -> Result<HttpResponse, AppError> {
+    service.disable_totp(&principal, &req.password).await?;
+    Ok(HttpResponse::NoContent().finish())
+}
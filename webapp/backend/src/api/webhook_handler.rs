@@ -0,0 +1,61 @@
+use crate::domains::dto::webhook::RegisterWebhookRequestDto;
+use crate::domains::webhook_service::WebhookService;
+use crate::errors::AppError;
+use crate::infrastructure::http_client::HttpClient;
+use crate::repositories::webhook_repository::WebhookRepositoryImpl;
+use actix_web::{web, HttpResponse};
+use serde::Deserialize;
+
+pub async fn register_webhook_handler(
+    service: web::Data<WebhookService<WebhookRepositoryImpl, HttpClient>>,
+    req: web::Json<RegisterWebhookRequestDto>,
+) -> Result<HttpResponse, AppError> {
+    let webhook = service
+        .register(&req.url, &req.event_types, req.sponsor.as_deref())
+        .await?;
+    Ok(HttpResponse::Created().json(webhook))
+}
+
+pub async fn get_webhooks_handler(
+    service: web::Data<WebhookService<WebhookRepositoryImpl, HttpClient>>,
+) -> Result<HttpResponse, AppError> {
+    let webhooks = service.get_subscriptions().await?;
+    Ok(HttpResponse::Ok().json(webhooks))
+}
+
+pub async fn delete_webhook_handler(
+    service: web::Data<WebhookService<WebhookRepositoryImpl, HttpClient>>,
+    path: web::Path<i32>,
+) -> Result<HttpResponse, AppError> {
+    service.deactivate(path.into_inner()).await?;
+    Ok(HttpResponse::NoContent().finish())
+}
+
+#[derive(Deserialize, Debug)]
+pub struct PaginatedDeliveryQuery {
+    page: Option<i32>,
+    page_size: Option<i32>,
+}
+
+pub async fn get_webhook_deliveries_handler(
+    service: web::Data<WebhookService<WebhookRepositoryImpl, HttpClient>>,
+    path: web::Path<i32>,
+    query: web::Query<PaginatedDeliveryQuery>,
+) -> Result<HttpResponse, AppError> {
+    let deliveries = service
+        .get_deliveries(
+            path.into_inner(),
+            query.page.unwrap_or(0),
+            query.page_size.unwrap_or(20),
+        )
+        .await?;
+    Ok(HttpResponse::Ok().json(deliveries))
+}
+
+pub async fn replay_webhook_delivery_handler(
+    service: web::Data<WebhookService<WebhookRepositoryImpl, HttpClient>>,
+    path: web::Path<i32>,
+) -> Result<HttpResponse, AppError> {
+    let delivery = service.replay(path.into_inner()).await?;
+    Ok(HttpResponse::Accepted().json(delivery))
+}
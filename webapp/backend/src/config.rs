@@ -0,0 +1,209 @@
+use std::env;
+use std::str::FromStr;
+
+use crate::models::user::Role;
+
+#[derive(Debug, Clone)]
+pub struct Config {
+    // 位置情報の更新がこの秒数途絶えたレッカー車を offline とみなす
+    pub stale_tow_truck_threshold_secs: i64,
+    pub stale_tow_truck_check_interval_secs: u64,
+    pub order_offer_check_interval_secs: u64,
+    pub dispatch: DispatchConfig,
+    pub webhook: WebhookConfig,
+    pub session: SessionConfig,
+    pub login_throttle: LoginThrottleConfig,
+    pub cpu_pool: CpuPoolConfig,
+    pub argon2: Argon2Config,
+    pub password_reset: PasswordResetConfig,
+    pub profile_image: ProfileImageConfig,
+    pub totp: TotpConfig,
+}
+
+// 配車候補の絞り込みに関する設定
+#[derive(Debug, Clone)]
+pub struct DispatchConfig {
+    // この金額以上の車の依頼には flatbed 以上のレッカー車を割り当てる
+    pub flatbed_car_value_threshold: f64,
+    // 担当エリア外にいるレッカー車を担当エリアの配車候補に含めるか
+    pub out_of_area_tow_trucks_dispatchable: bool,
+    // ドライバーがこの秒数以内に応答しなければ次に近いレッカー車へ打診する
+    pub order_offer_timeout_secs: i64,
+}
+
+// Webhook の配信に関する設定
+#[derive(Debug, Clone)]
+pub struct WebhookConfig {
+    // この回数失敗した配信は failed として諦める
+    pub max_attempts: i32,
+    // n 回目の失敗後は retry_base_secs * 2^(n-1) 秒待って再送する
+    pub retry_base_secs: i64,
+    pub delivery_interval_secs: u64,
+    pub request_timeout_secs: u64,
+    // 同時に送信する配信の数
+    pub delivery_concurrency: usize,
+}
+
+// セッションの有効期限に関する設定
+#[derive(Debug, Clone)]
+pub struct SessionConfig {
+    // 作成からこの秒数を過ぎたセッションは使われていても無効
+    pub absolute_ttl_secs: i64,
+    // 最後に使われてからこの秒数を過ぎたセッションは無効
+    pub idle_ttl_secs: i64,
+    // admin がなりすましで発行したセッションは、作成からこの秒数で無効
+    pub impersonation_ttl_secs: i64,
+    pub purge_interval_secs: u64,
+    // 検証済みセッションをこの秒数だけメモリに保持する
+    pub cache_ttl_secs: u64,
+    pub cache_capacity: u64,
+}
+
+// ログイン試行の制限に関する設定
+#[derive(Debug, Clone)]
+pub struct LoginThrottleConfig {
+    // 同じユーザー名・接続元からこの回数続けて失敗したらロックする
+    pub max_failures: u32,
+    // 最初のロック時間。以降は失敗するたびに倍になり max_lockout_secs で頭打ちになる
+    pub base_lockout_secs: i64,
+    pub max_lockout_secs: i64,
+    // 最後の失敗からこの秒数経てば失敗回数を数え直す
+    pub reset_after_secs: i64,
+    pub capacity: u64,
+}
+
+// パスワードハッシュや画像処理を実行するスレッドプールの設定
+#[derive(Debug, Clone)]
+pub struct CpuPoolConfig {
+    pub threads: usize,
+    // 実行待ちがこの数を超えたら 503 を返す
+    pub queue_capacity: usize,
+}
+
+// パスワードハッシュ (Argon2id) のコスト
+// 変更すると、古いコストのハッシュはログイン成功時に作り直される
+#[derive(Debug, Clone)]
+pub struct Argon2Config {
+    pub memory_kib: u32,
+    pub iterations: u32,
+    pub parallelism: u32,
+}
+
+// パスワード再設定トークンに関する設定
+#[derive(Debug, Clone)]
+pub struct PasswordResetConfig {
+    pub token_ttl_secs: i64,
+    // 開発用の通知先ファイル (空ならログに出す)
+    pub outbox_path: String,
+}
+
+// プロフィール画像のアップロードに関する設定
+#[derive(Debug, Clone)]
+pub struct ProfileImageConfig {
+    pub max_upload_bytes: usize,
+    // 縦横ともにこの範囲に収まらない画像は受け付けない
+    pub min_dimension: u32,
+    pub max_dimension: u32,
+    // 保存する正方形画像の一辺の上限
+    pub stored_dimension: u32,
+    // サイズ違いの画像をメモリに保持する上限 (バイト)
+    pub variant_cache_bytes: u64,
+}
+
+// 2 段階認証 (TOTP) に関する設定
+#[derive(Debug, Clone)]
+pub struct TotpConfig {
+    // 認証アプリに表示される発行者名
+    pub issuer: String,
+    // 登録を必須にするロール (未登録ならログイン時に登録させる)
+    pub required_roles: Vec<Role>,
+    // パスワード確認後、この秒数以内にコードを入力する
+    pub challenge_ttl_secs: u64,
+    // 1 回のログインでコードを間違えられる回数
+    pub challenge_max_attempts: u32,
+    // 端末の時計のずれを前後何ステップ (30 秒) まで許容するか
+    pub skew_steps: i64,
+    pub recovery_code_count: usize,
+}
+
+impl Config {
+    pub fn from_env() -> Self {
+        Config {
+            stale_tow_truck_threshold_secs: env_or("STALE_TOW_TRUCK_THRESHOLD_SECS", 300),
+            stale_tow_truck_check_interval_secs: env_or("STALE_TOW_TRUCK_CHECK_INTERVAL_SECS", 30),
+            order_offer_check_interval_secs: env_or("ORDER_OFFER_CHECK_INTERVAL_SECS", 5),
+            dispatch: DispatchConfig {
+                flatbed_car_value_threshold: env_or("FLATBED_CAR_VALUE_THRESHOLD", 8000.0),
+                out_of_area_tow_trucks_dispatchable: env_or(
+                    "OUT_OF_AREA_TOW_TRUCKS_DISPATCHABLE",
+                    true,
+                ),
+                order_offer_timeout_secs: env_or("ORDER_OFFER_TIMEOUT_SECS", 60),
+            },
+            webhook: WebhookConfig {
+                max_attempts: env_or("WEBHOOK_MAX_ATTEMPTS", 8),
+                retry_base_secs: env_or("WEBHOOK_RETRY_BASE_SECS", 10),
+                delivery_interval_secs: env_or("WEBHOOK_DELIVERY_INTERVAL_SECS", 5),
+                request_timeout_secs: env_or("WEBHOOK_REQUEST_TIMEOUT_SECS", 10),
+                delivery_concurrency: env_or("WEBHOOK_DELIVERY_CONCURRENCY", 8),
+            },
+            session: SessionConfig {
+                absolute_ttl_secs: env_or("SESSION_ABSOLUTE_TTL_SECS", 7 * 24 * 60 * 60),
+                idle_ttl_secs: env_or("SESSION_IDLE_TTL_SECS", 24 * 60 * 60),
+                impersonation_ttl_secs: env_or("SESSION_IMPERSONATION_TTL_SECS", 30 * 60),
+                purge_interval_secs: env_or("SESSION_PURGE_INTERVAL_SECS", 60 * 60),
+                cache_ttl_secs: env_or("SESSION_CACHE_TTL_SECS", 60),
+                cache_capacity: env_or("SESSION_CACHE_CAPACITY", 10_000),
+            },
+            login_throttle: LoginThrottleConfig {
+                max_failures: env_or("LOGIN_THROTTLE_MAX_FAILURES", 5),
+                base_lockout_secs: env_or("LOGIN_THROTTLE_BASE_LOCKOUT_SECS", 30),
+                max_lockout_secs: env_or("LOGIN_THROTTLE_MAX_LOCKOUT_SECS", 60 * 60),
+                reset_after_secs: env_or("LOGIN_THROTTLE_RESET_AFTER_SECS", 15 * 60),
+                capacity: env_or("LOGIN_THROTTLE_CAPACITY", 100_000),
+            },
+            cpu_pool: CpuPoolConfig {
+                threads: env_or(
+                    "CPU_POOL_THREADS",
+                    std::thread::available_parallelism().map_or(2, |n| n.get()),
+                ),
+                queue_capacity: env_or("CPU_POOL_QUEUE_CAPACITY", 64),
+            },
+            argon2: Argon2Config {
+                memory_kib: env_or("ARGON2_MEMORY_KIB", 19 * 1024),
+                iterations: env_or("ARGON2_ITERATIONS", 2),
+                parallelism: env_or("ARGON2_PARALLELISM", 1),
+            },
+            password_reset: PasswordResetConfig {
+                token_ttl_secs: env_or("PASSWORD_RESET_TOKEN_TTL_SECS", 60 * 60),
+                outbox_path: env_or("PASSWORD_RESET_OUTBOX_PATH", String::new()),
+            },
+            profile_image: ProfileImageConfig {
+                max_upload_bytes: env_or("PROFILE_IMAGE_MAX_UPLOAD_BYTES", 5 * 1024 * 1024),
+                min_dimension: env_or("PROFILE_IMAGE_MIN_DIMENSION", 32),
+                max_dimension: env_or("PROFILE_IMAGE_MAX_DIMENSION", 4096),
+                stored_dimension: env_or("PROFILE_IMAGE_STORED_DIMENSION", 1024),
+                variant_cache_bytes: env_or("PROFILE_IMAGE_VARIANT_CACHE_BYTES", 64 * 1024 * 1024),
+            },
+            totp: TotpConfig {
+                issuer: env_or("TOTP_ISSUER", "42Tokyo".to_string()),
+                // カンマ区切り (例: "admin,dispatcher")
+                required_roles: env_or("TOTP_REQUIRED_ROLES", String::new())
+                    .split(',')
+                    .filter_map(|role| role.trim().parse().ok())
+                    .collect(),
+                challenge_ttl_secs: env_or("TOTP_CHALLENGE_TTL_SECS", 5 * 60),
+                challenge_max_attempts: env_or("TOTP_CHALLENGE_MAX_ATTEMPTS", 5),
+                skew_steps: env_or("TOTP_SKEW_STEPS", 1),
+                recovery_code_count: env_or("TOTP_RECOVERY_CODE_COUNT", 10),
+            },
+        }
+    }
+}
+
+fn env_or<T: FromStr>(key: &str, default: T) -> T {
+    env::var(key)
+        .ok()
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(default)
+}
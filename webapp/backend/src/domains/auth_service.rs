@@ -1,55 +1,292 @@
-use std::io::BufWriter;
-use std::path::{Path, PathBuf};
+use std::sync::Arc;
 
 use actix_web::web::Bytes;
-use image::codecs::png::PngEncoder;
-use image::ImageEncoder;
-use image::ImageReader;
-
-use fast_image_resize::images::Image;
-use fast_image_resize::{IntoImageView, Resizer};
+use chrono::{DateTime, Duration, Utc};
+use moka::{future::Cache, ops::compute::Op};
 
+use crate::config::{
+    Argon2Config, Config, PasswordResetConfig, ProfileImageConfig, SessionConfig, TotpConfig,
+};
 use crate::errors::AppError;
-use crate::models::user::{Dispatcher, Session, User};
-use crate::utils::{generate_session_token, hash_password, verify_password};
+use crate::infrastructure::cpu_pool::{CpuPool, CpuPoolStats};
+use crate::infrastructure::login_throttle::{LoginThrottle, LoginThrottleKey, LoginThrottleKind};
+use crate::infrastructure::profile_image::{
+    normalize_profile_image, remove_profile_image, render_profile_image_variant,
+    store_profile_image, ProfileImageFormat, ProfileImageSource, ProfileImageVariant,
+    DEFAULT_PROFILE_IMAGE, PROFILE_IMAGE_SIZES,
+};
+use crate::infrastructure::session_cache::{SessionCache, SessionCacheStats};
+use crate::infrastructure::totp::{
+    base32_encode, generate_recovery_code, generate_totp_secret, normalize_recovery_code,
+    totp_provisioning_uri, verify_totp,
+};
+use crate::models::user::{
+    AdminAuditEntry, AdminAuditLog, Dispatcher, Principal, Role, Session, SessionPrincipalRow, User,
+};
+use crate::utils::{
+    generate_secret, generate_session_token, hash_password, password_needs_rehash, sha256_hex,
+    verify_password,
+};
 
-use super::dto::auth::LoginResponseDto;
+use super::dto::auth::{
+    LoginLockoutDto, LoginResponseDto, LoginResultDto, ProfileImageDto, RefreshSessionResponseDto,
+    SessionDto, TotpChallengeDto, TotpEnrollmentDto, TotpLoginResponseDto, TotpRecoveryCodesDto,
+    TotpStatusDto,
+};
+use super::dto::user::{AdminUserDto, AuditLogDto, MeDto};
 
 pub trait AuthRepository {
     async fn create_user(&self, username: &str, password: &str, role: &str)
         -> Result<(), AppError>;
     async fn find_user_by_id(&self, id: i32) -> Result<Option<User>, AppError>;
     async fn find_user_by_username(&self, username: &str) -> Result<Option<User>, AppError>;
-    async fn create_dispatcher(&self, user_id: i32, area_id: i32) -> Result<(), AppError>;
+    // current_hash のままのときだけ書き換える (同時にパスワードが変更された場合は何もしない)
+    async fn update_user_password_hash(
+        &self,
+        user_id: i32,
+        current_hash: &str,
+        new_hash: &str,
+    ) -> Result<(), AppError>;
+    async fn create_dispatcher(&self, user_id: i32, area_ids: &[i32]) -> Result<(), AppError>;
     async fn find_dispatcher_by_id(&self, id: i32) -> Result<Option<Dispatcher>, AppError>;
     async fn find_dispatcher_by_user_id(
         &self,
         user_id: i32,
     ) -> Result<Option<Dispatcher>, AppError>;
-    async fn find_profile_image_name_by_user_id(
+    async fn authenticate_user(&self, username: &str, password: &str) -> Result<User, AppError>;
+    async fn find_area_ids_by_dispatcher_id(
+        &self,
+        dispatcher_id: i32,
+    ) -> Result<Vec<i32>, AppError>;
+    // 他のユーザーが使っているユーザー名なら Conflict
+    async fn update_username(&self, user_id: i32, username: &str) -> Result<(), AppError>;
+    // 退会済みのユーザーは含めない
+    async fn search_users(
+        &self,
+        username_prefix: Option<&str>,
+        role: Option<&str>,
+        page: i32,
+        page_size: i32,
+    ) -> Result<Vec<User>, AppError>;
+    // 以下の admin の操作は、監査ログも同じトランザクションで書き込む
+    // area_ids があれば担当エリアも丸ごと入れ替える
+    async fn update_user_role(
+        &self,
+        user_id: i32,
+        role: &str,
+        area_ids: Option<&[i32]>,
+        audit: &AdminAuditEntry,
+    ) -> Result<(), AppError>;
+    // 担当エリアを丸ごと入れ替える (dispatchers の行がなければ作る)
+    async fn replace_dispatcher_areas(
+        &self,
+        user_id: i32,
+        area_ids: &[i32],
+        audit: &AdminAuditEntry,
+    ) -> Result<(), AppError>;
+    async fn count_areas_by_ids(&self, area_ids: &[i32]) -> Result<i64, AppError>;
+    // 無効にするときはセッションもすべて削除する
+    async fn set_user_disabled(
+        &self,
+        user_id: i32,
+        disabled: bool,
+        audit: &AdminAuditEntry,
+    ) -> Result<(), AppError>;
+    // 監査ログの admin をなりすましたセッションの持ち主として記録する
+    async fn create_impersonation_session(
+        &self,
+        user_id: i32,
+        session_token: &str,
+        audit: &AdminAuditEntry,
+    ) -> Result<(), AppError>;
+    async fn create_admin_audit_log(&self, audit: &AdminAuditEntry) -> Result<(), AppError>;
+    async fn get_admin_audit_logs(
+        &self,
+        target_user_id: Option<i32>,
+        page: i32,
+        page_size: i32,
+    ) -> Result<Vec<AdminAuditLog>, AppError>;
+    // ユーザーと依頼の個人情報を匿名化し、セッションと再設定トークンを削除する
+    // 進行中の依頼があれば何もせずに false を返す
+    async fn anonymize_user(
         &self,
         user_id: i32,
+        username: &str,
+        password: &str,
+    ) -> Result<bool, AppError>;
+    // 変更前のファイル名を返す (ユーザーがいなければ None)
+    async fn update_profile_image_name(
+        &self,
+        user_id: i32,
+        profile_image_name: &str,
     ) -> Result<Option<String>, AppError>;
-    async fn authenticate_user(&self, username: &str, password: &str) -> Result<User, AppError>;
+    async fn count_users_by_profile_image(&self, profile_image_name: &str)
+        -> Result<i64, AppError>;
     async fn create_session(&self, user_id: i32, session_token: &str) -> Result<(), AppError>;
     async fn delete_session(&self, session_token: &str) -> Result<(), AppError>;
-    async fn find_session_by_session_token(&self, session_token: &str)
-        -> Result<Session, AppError>;
+    // 期限内で有効なセッションだけを返す
+    // なりすましのセッションは absolute_ttl_secs の代わりに impersonation_ttl_secs で期限を切る
+    async fn find_active_session_by_session_token(
+        &self,
+        session_token: &str,
+        absolute_ttl_secs: i64,
+        idle_ttl_secs: i64,
+        impersonation_ttl_secs: i64,
+    ) -> Result<Option<Session>, AppError>;
+    // 期限内で有効なセッションのうち、退会や無効化されていないユーザーのものだけを返す
+    async fn find_session_principal(
+        &self,
+        session_token: &str,
+        absolute_ttl_secs: i64,
+        idle_ttl_secs: i64,
+        impersonation_ttl_secs: i64,
+    ) -> Result<Option<SessionPrincipalRow>, AppError>;
+    async fn touch_session(&self, session_id: i32) -> Result<(), AppError>;
+    async fn get_active_sessions_by_user_id(
+        &self,
+        user_id: i32,
+        absolute_ttl_secs: i64,
+        idle_ttl_secs: i64,
+        impersonation_ttl_secs: i64,
+    ) -> Result<Vec<Session>, AppError>;
+    async fn delete_session_by_id(&self, user_id: i32, session_id: i32) -> Result<bool, AppError>;
+    async fn delete_sessions_by_user_id(&self, user_id: i32) -> Result<u64, AppError>;
+    async fn delete_expired_sessions(
+        &self,
+        absolute_ttl_secs: i64,
+        idle_ttl_secs: i64,
+        impersonation_ttl_secs: i64,
+    ) -> Result<u64, AppError>;
     async fn find_users_by_ids(&self, ids: &[i32]) -> Result<Vec<User>, AppError>;
     async fn find_dispatchers_by_ids(
         &self,
         dispatcher_ids: &[i32],
     ) -> Result<Vec<Dispatcher>, AppError>;
+    async fn find_tow_truck_id_by_driver_id(&self, driver_id: i32)
+        -> Result<Option<i32>, AppError>;
+    async fn update_user_password(&self, user_id: i32, password: &str) -> Result<(), AppError>;
+    // keep_session_token 以外のセッションを削除する
+    async fn delete_other_sessions_by_user_id(
+        &self,
+        user_id: i32,
+        keep_session_token: &str,
+    ) -> Result<u64, AppError>;
+    // ユーザーがいなければ何も作らない (ユーザーの有無で処理を変えないため、ユーザー名で作る)
+    async fn create_password_reset_token(
+        &self,
+        username: &str,
+        token_hash: &str,
+    ) -> Result<bool, AppError>;
+    // 未使用かつ期限内のトークンなら、そのユーザーの未使用トークンをすべて使用済みにして
+    // パスワードを書き換え、ユーザーの id を返す
+    async fn reset_password_with_token(
+        &self,
+        token_hash: &str,
+        ttl_secs: i64,
+        hashed_password: &str,
+    ) -> Result<Option<i32>, AppError>;
+    async fn delete_expired_password_reset_tokens(&self, ttl_secs: i64) -> Result<u64, AppError>;
+    // 登録済みなら書き換えずに false を返す
+    async fn set_pending_totp_secret(&self, user_id: i32, secret: &str) -> Result<bool, AppError>;
+    // secret が確認待ちのままなら有効にしてリカバリーコードを作り直す
+    async fn enable_totp(
+        &self,
+        user_id: i32,
+        secret: &str,
+        used_step: i64,
+        recovery_code_hashes: &[String],
+    ) -> Result<bool, AppError>;
+    // admin が解除したときは audit も書き込む
+    async fn disable_totp(
+        &self,
+        user_id: i32,
+        audit: Option<&AdminAuditEntry>,
+    ) -> Result<(), AppError>;
+    // 最後に使ったステップより新しいときだけ更新する
+    async fn update_totp_last_used_step(&self, user_id: i32, step: i64) -> Result<bool, AppError>;
+    async fn consume_totp_recovery_code(
+        &self,
+        user_id: i32,
+        code_hash: &str,
+    ) -> Result<bool, AppError>;
+    async fn count_unused_totp_recovery_codes(&self, user_id: i32) -> Result<i64, AppError>;
+}
+
+// パスワード再設定トークンをユーザーに届ける (リクエストとは別のタスクで送る)
+pub trait PasswordResetNotifier: Clone + 'static {
+    async fn send_password_reset(
+        &self,
+        user: &User,
+        reset_token: &str,
+        expires_at: DateTime<Utc>,
+    ) -> Result<(), String>;
+}
+
+// 退会したユーザーのユーザー名 (後ろにユーザー ID が付く)
+const DELETED_USERNAME_PREFIX: &str = "deleted-user-";
+
+// last_used_at の更新はこの秒数に1回までにする
+const SESSION_TOUCH_INTERVAL_SECS: i64 = 60;
+
+// パスワードを確認済みで、TOTP のコードを待っているログイン
+#[derive(Debug, Clone)]
+struct TotpChallenge {
+    user_id: i32,
+    failures: u32,
+    expires_at: DateTime<Utc>,
 }
 
 #[derive(Debug)]
-pub struct AuthService<T: AuthRepository + std::fmt::Debug> {
+pub struct AuthService<
+    T: AuthRepository + std::fmt::Debug,
+    U: PasswordResetNotifier + std::fmt::Debug,
+> {
     repository: T,
+    notifier: U,
+    session_config: SessionConfig,
+    session_cache: SessionCache,
+    login_throttle: LoginThrottle,
+    password_reset_throttle: LoginThrottle,
+    argon2_config: Argon2Config,
+    password_reset_config: PasswordResetConfig,
+    profile_image_config: ProfileImageConfig,
+    profile_image_cache: Cache<(ProfileImageSource, u32, ProfileImageFormat), ProfileImageVariant>,
+    totp_config: TotpConfig,
+    totp_challenges: Cache<String, TotpChallenge>,
+    cpu_pool: Arc<CpuPool>,
 }
 
-impl<T: AuthRepository + std::fmt::Debug> AuthService<T> {
-    pub fn new(repository: T) -> Self {
-        AuthService { repository }
+impl<T: AuthRepository + std::fmt::Debug, U: PasswordResetNotifier + std::fmt::Debug>
+    AuthService<T, U>
+{
+    pub fn new(repository: T, notifier: U, config: &Config, cpu_pool: Arc<CpuPool>) -> Self {
+        AuthService {
+            repository,
+            notifier,
+            session_cache: SessionCache::new(
+                config.session.cache_capacity,
+                config.session.cache_ttl_secs,
+            ),
+            session_config: config.session.clone(),
+            login_throttle: LoginThrottle::new(config.login_throttle.clone()),
+            password_reset_throttle: LoginThrottle::new(config.login_throttle.clone()),
+            argon2_config: config.argon2.clone(),
+            password_reset_config: config.password_reset.clone(),
+            profile_image_config: config.profile_image.clone(),
+            profile_image_cache: Cache::builder()
+                .max_capacity(config.profile_image.variant_cache_bytes)
+                .weigher(|_, variant: &ProfileImageVariant| {
+                    variant.bytes.len().try_into().unwrap_or(u32::MAX)
+                })
+                .build(),
+            totp_config: config.totp.clone(),
+            totp_challenges: Cache::builder()
+                .time_to_live(std::time::Duration::from_secs(
+                    config.totp.challenge_ttl_secs,
+                ))
+                .build(),
+            cpu_pool,
+        }
     }
 
     pub async fn register_user(
@@ -59,7 +296,13 @@ impl<T: AuthRepository + std::fmt::Debug> AuthService<T> {
         role: &str,
         area: Option<i32>,
     ) -> Result<LoginResponseDto, AppError> {
-        if role == "dispatcher" && area.is_none() {
+        // admin は自己登録できない
+        match role.parse::<Role>() {
+            Ok(Role::Admin) | Err(_) => return Err(AppError::BadRequest),
+            Ok(Role::Dispatcher) if area.is_none() => return Err(AppError::BadRequest),
+            Ok(_) => {}
+        }
+        if username.starts_with(DELETED_USERNAME_PREFIX) {
             return Err(AppError::BadRequest);
         }
 
@@ -67,7 +310,7 @@ impl<T: AuthRepository + std::fmt::Debug> AuthService<T> {
             return Err(AppError::Conflict);
         }
 
-        let hashed_password = hash_password(password).unwrap();
+        let hashed_password = self.hash_password_on_pool(password).await?;
 
         self.repository
             .create_user(username, &hashed_password, role)
@@ -83,20 +326,17 @@ impl<T: AuthRepository + std::fmt::Debug> AuthService<T> {
                 match user.role.as_str() {
                     "dispatcher" => {
                         self.repository
-                            .create_dispatcher(user.id, area.unwrap())
+                            .create_dispatcher(user.id, &[area.unwrap()])
                             .await?;
-                        let dispatcher = self
-                            .repository
-                            .find_dispatcher_by_user_id(user.id)
-                            .await?
-                            .unwrap();
+                        let (dispatcher_id, area_ids) =
+                            self.find_dispatcher_areas(user.id).await?.unwrap();
                         Ok(LoginResponseDto {
                             user_id: user.id,
                             username: user.username,
                             session_token,
                             role: user.role,
-                            dispatcher_id: Some(dispatcher.id),
-                            area_id: Some(dispatcher.area_id),
+                            dispatcher_id: Some(dispatcher_id),
+                            area_ids,
                         })
                     }
                     _ => Ok(LoginResponseDto {
@@ -105,7 +345,7 @@ impl<T: AuthRepository + std::fmt::Debug> AuthService<T> {
                         session_token,
                         role: user.role,
                         dispatcher_id: None,
-                        area_id: None,
+                        area_ids: vec![],
                     }),
                 }
             }
@@ -117,35 +357,358 @@ impl<T: AuthRepository + std::fmt::Debug> AuthService<T> {
         &self,
         username: &str,
         password: &str,
-    ) -> Result<LoginResponseDto, AppError> {
-        let user = self
-            .repository
-            .find_user_by_username(username)
-            .await?
+        client_address: &str,
+    ) -> Result<LoginResultDto, AppError> {
+        let throttle_keys = [
+            LoginThrottleKey::username(username),
+            LoginThrottleKey::address(client_address),
+        ];
+        // 存在しないユーザー名も失敗として数える
+        self.login_throttle
+            .begin_attempt(&throttle_keys)
+            .await
+            .map_err(AppError::TooManyRequests)?;
+
+        let verification = self.verify_login_password(username, password).await;
+        let (user, verified, rehashed_password) = match verification {
+            Ok(verification) => verification,
+            Err(err) => {
+                // 検証できなかった試行は失敗として数えない
+                for key in throttle_keys {
+                    self.login_throttle.forgive_attempt(key).await;
+                }
+                return Err(err);
+            }
+        };
+        let user = match user {
+            // 無効にされたアカウントもパスワード違いと区別しない
+            Some(user) if verified && user.deleted_at.is_none() && user.disabled_at.is_none() => {
+                user
+            }
+            _ => return Err(AppError::Unauthorized),
+        };
+        for key in throttle_keys.iter().cloned() {
+            self.login_throttle.forgive_attempt(key).await;
+        }
+        if let Some(rehashed_password) = rehashed_password {
+            // 書き戻しに失敗してもログインは成功させる (次回のログインで再度試みる)
+            if let Err(err) = self
+                .repository
+                .update_user_password_hash(user.id, &user.password, &rehashed_password)
+                .await
+            {
+                log::error!("failed to rehash password of user {}: {}", user.id, err);
+            }
+        }
+        // TOTP の確認が済むまではセッションを発行しない
+        if user.totp_enabled_at.is_some() || self.totp_required_for(&user.role) {
+            return Ok(LoginResultDto::TotpRequired(
+                self.create_totp_challenge(&user).await,
+            ));
+        }
+        // 接続元アドレスの失敗回数は他のユーザー名への試行も含むので、成功しても消さない
+        self.login_throttle.clear(&throttle_keys[0]).await;
+
+        let session_token = generate_session_token();
+        self.repository
+            .create_session(user.id, &session_token)
+            .await?;
+
+        Ok(LoginResultDto::Session(
+            self.login_response(user, session_token).await?,
+        ))
+    }
+
+    fn totp_required_for(&self, role: &str) -> bool {
+        role.parse::<Role>()
+            .is_ok_and(|role| self.totp_config.required_roles.contains(&role))
+    }
+
+    async fn create_totp_challenge(&self, user: &User) -> TotpChallengeDto {
+        let challenge_token = generate_session_token();
+        let expires_at = Utc::now() + Duration::seconds(self.totp_config.challenge_ttl_secs as i64);
+        self.totp_challenges
+            .insert(
+                challenge_token.clone(),
+                TotpChallenge {
+                    user_id: user.id,
+                    failures: 0,
+                    expires_at,
+                },
+            )
+            .await;
+
+        TotpChallengeDto {
+            challenge_token,
+            totp_enrollment_required: user.totp_enabled_at.is_none(),
+            expires_at,
+        }
+    }
+
+    async fn find_totp_challenge(&self, challenge_token: &str) -> Result<User, AppError> {
+        let challenge = self
+            .totp_challenges
+            .get(challenge_token)
+            .await
+            .filter(|challenge| challenge.expires_at > Utc::now())
             .ok_or(AppError::Unauthorized)?;
-        if !verify_password(&user.password, password).unwrap() {
+        self.repository
+            .find_user_by_id(challenge.user_id)
+            .await?
+            .filter(|user| user.deleted_at.is_none() && user.disabled_at.is_none())
+            .ok_or(AppError::Unauthorized)
+    }
+
+    // 登録が必須なのに未登録のユーザーは、セッションがないのでチャレンジで本人確認して登録させる
+    pub async fn begin_totp_enrollment_for_login(
+        &self,
+        challenge_token: &str,
+    ) -> Result<TotpEnrollmentDto, AppError> {
+        let user = self.find_totp_challenge(challenge_token).await?;
+        self.begin_totp_enrollment_for(&user).await
+    }
+
+    // ログインの 2 段階目 (未登録なら登録の確認も兼ねる)
+    pub async fn verify_totp_login(
+        &self,
+        challenge_token: &str,
+        code: &str,
+    ) -> Result<TotpLoginResponseDto, AppError> {
+        let user = self.find_totp_challenge(challenge_token).await?;
+        let throttle_key = LoginThrottleKey::username(&user.username);
+        // パスワードの失敗と合わせて数え、総当たりをロックする
+        self.login_throttle
+            .begin_attempt(std::slice::from_ref(&throttle_key))
+            .await
+            .map_err(AppError::TooManyRequests)?;
+
+        let verification = if user.totp_enabled_at.is_some() {
+            self.check_totp_code(&user, code)
+                .await
+                .map(|verified| (verified, None))
+        } else {
+            self.confirm_totp_enrollment_for(&user, code)
+                .await
+                .map(|recovery_codes| (recovery_codes.is_some(), recovery_codes))
+        };
+        let (verified, recovery_codes) = match verification {
+            Ok(verification) => verification,
+            Err(err) => {
+                self.login_throttle.forgive_attempt(throttle_key).await;
+                return Err(err);
+            }
+        };
+        if !verified {
+            // 同時に送られたコードの失敗も取りこぼさないよう、キャッシュの中で数える
+            let max_attempts = self.totp_config.challenge_max_attempts;
+            self.totp_challenges
+                .entry(challenge_token.to_string())
+                .and_compute_with(|entry| {
+                    let op = match entry.map(|e| e.into_value()) {
+                        Some(challenge) if challenge.failures + 1 >= max_attempts => Op::Remove,
+                        Some(challenge) => Op::Put(TotpChallenge {
+                            failures: challenge.failures + 1,
+                            ..challenge
+                        }),
+                        None => Op::Nop,
+                    };
+                    std::future::ready(op)
+                })
+                .await;
             return Err(AppError::Unauthorized);
         }
 
+        self.totp_challenges.invalidate(challenge_token).await;
+        self.login_throttle.clear(&throttle_key).await;
+
         let session_token = generate_session_token();
         self.repository
             .create_session(user.id, &session_token)
             .await?;
 
+        Ok(TotpLoginResponseDto {
+            login: self.login_response(user, session_token).await?,
+            recovery_codes,
+        })
+    }
+
+    // 新しい共有鍵を発行する (確認のコードが届くまでは有効にならない)
+    async fn begin_totp_enrollment_for(&self, user: &User) -> Result<TotpEnrollmentDto, AppError> {
+        let secret = generate_totp_secret();
+        if !self
+            .repository
+            .set_pending_totp_secret(user.id, &hex::encode(&secret))
+            .await?
+        {
+            return Err(AppError::Conflict);
+        }
+
+        Ok(TotpEnrollmentDto {
+            secret: base32_encode(&secret),
+            provisioning_uri: totp_provisioning_uri(
+                &secret,
+                &self.totp_config.issuer,
+                &user.username,
+            ),
+        })
+    }
+
+    // コードが合っていれば有効にしてリカバリーコードを返す (間違っていれば None)
+    async fn confirm_totp_enrollment_for(
+        &self,
+        user: &User,
+        code: &str,
+    ) -> Result<Option<Vec<String>>, AppError> {
+        if user.totp_enabled_at.is_some() {
+            return Err(AppError::Conflict);
+        }
+        let Some(secret_hex) = &user.totp_secret else {
+            return Err(AppError::BadRequest);
+        };
+        let secret = hex::decode(secret_hex).map_err(|_| AppError::InternalServerError)?;
+        let Some(step) = verify_totp(
+            &secret,
+            code.trim(),
+            Utc::now(),
+            self.totp_config.skew_steps,
+        ) else {
+            return Ok(None);
+        };
+
+        let recovery_codes: Vec<String> = (0..self.totp_config.recovery_code_count)
+            .map(|_| generate_recovery_code())
+            .collect();
+        let recovery_code_hashes: Vec<String> = recovery_codes
+            .iter()
+            .map(|code| sha256_hex(normalize_recovery_code(code).as_bytes()))
+            .collect();
+        if !self
+            .repository
+            .enable_totp(user.id, secret_hex, step, &recovery_code_hashes)
+            .await?
+        {
+            return Err(AppError::Conflict);
+        }
+
+        Ok(Some(recovery_codes))
+    }
+
+    // 認証アプリのコードか、未使用のリカバリーコードなら true
+    async fn check_totp_code(&self, user: &User, code: &str) -> Result<bool, AppError> {
+        let Some(secret_hex) = &user.totp_secret else {
+            return Ok(false);
+        };
+        let secret = hex::decode(secret_hex).map_err(|_| AppError::InternalServerError)?;
+        if let Some(step) = verify_totp(
+            &secret,
+            code.trim(),
+            Utc::now(),
+            self.totp_config.skew_steps,
+        ) {
+            // 一度受け付けたコードは有効期間内でも使い回させない
+            return self
+                .repository
+                .update_totp_last_used_step(user.id, step)
+                .await;
+        }
+
+        let code_hash = sha256_hex(normalize_recovery_code(code).as_bytes());
+        self.repository
+            .consume_totp_recovery_code(user.id, &code_hash)
+            .await
+    }
+
+    pub async fn get_totp_status(&self, principal: &Principal) -> Result<TotpStatusDto, AppError> {
+        let user = self
+            .repository
+            .find_user_by_id(principal.user_id)
+            .await?
+            .ok_or(AppError::NotFound)?;
+        let enabled = user.totp_enabled_at.is_some();
+        let recovery_codes_remaining = if enabled {
+            self.repository
+                .count_unused_totp_recovery_codes(user.id)
+                .await?
+        } else {
+            0
+        };
+
+        Ok(TotpStatusDto {
+            enabled,
+            required: self.totp_required_for(&user.role),
+            recovery_codes_remaining,
+        })
+    }
+
+    pub async fn begin_totp_enrollment(
+        &self,
+        principal: &Principal,
+    ) -> Result<TotpEnrollmentDto, AppError> {
+        let user = self
+            .repository
+            .find_user_by_id(principal.user_id)
+            .await?
+            .ok_or(AppError::NotFound)?;
+        self.begin_totp_enrollment_for(&user).await
+    }
+
+    pub async fn confirm_totp_enrollment(
+        &self,
+        principal: &Principal,
+        code: &str,
+    ) -> Result<TotpRecoveryCodesDto, AppError> {
+        let user = self
+            .repository
+            .find_user_by_id(principal.user_id)
+            .await?
+            .ok_or(AppError::NotFound)?;
+        let recovery_codes = self
+            .confirm_totp_enrollment_for(&user, code)
+            .await?
+            .ok_or(AppError::BadRequest)?;
+
+        Ok(TotpRecoveryCodesDto { recovery_codes })
+    }
+
+    // 登録が必須のロールでは外せない (端末をなくしたら admin に解除してもらう)
+    pub async fn disable_totp(
+        &self,
+        principal: &Principal,
+        password: &str,
+    ) -> Result<(), AppError> {
+        let user = self
+            .repository
+            .find_user_by_id(principal.user_id)
+            .await?
+            .ok_or(AppError::NotFound)?;
+        if self.totp_required_for(&user.role) {
+            return Err(AppError::Forbidden);
+        }
+        if !self.verify_password_on_pool(&user, password).await? {
+            return Err(AppError::Unauthorized);
+        }
+
+        self.repository.disable_totp(user.id, None).await
+    }
+
+    async fn login_response(
+        &self,
+        user: User,
+        session_token: String,
+    ) -> Result<LoginResponseDto, AppError> {
         let dispatcher_id: Option<i32>;
-        let area_id: Option<i32>;
+        let area_ids: Vec<i32>;
 
         if user.role == "dispatcher" {
-            let dispatcher = self
-                .repository
-                .find_dispatcher_by_user_id(user.id)
+            let (id, ids) = self
+                .find_dispatcher_areas(user.id)
                 .await?
                 .ok_or(AppError::InternalServerError)?;
-            dispatcher_id = Some(dispatcher.id);
-            area_id = Some(dispatcher.area_id);
+            dispatcher_id = Some(id);
+            area_ids = ids;
         } else {
             dispatcher_id = None;
-            area_id = None;
+            area_ids = vec![];
         }
 
         Ok(LoginResponseDto {
@@ -154,57 +717,912 @@ impl<T: AuthRepository + std::fmt::Debug> AuthService<T> {
             session_token,
             role: user.role.clone(),
             dispatcher_id,
-            area_id,
+            area_ids,
+        })
+    }
+
+    // dispatcher ならその ID と担当エリアの一覧を返す
+    async fn find_dispatcher_areas(
+        &self,
+        user_id: i32,
+    ) -> Result<Option<(i32, Vec<i32>)>, AppError> {
+        let Some(dispatcher) = self.repository.find_dispatcher_by_user_id(user_id).await? else {
+            return Ok(None);
+        };
+        let area_ids = self
+            .repository
+            .find_area_ids_by_dispatcher_id(dispatcher.id)
+            .await?;
+        Ok(Some((dispatcher.id, area_ids)))
+    }
+
+    async fn hash_password_on_pool(&self, password: &str) -> Result<String, AppError> {
+        let password = password.to_string();
+        let argon2_config = self.argon2_config.clone();
+        self.cpu_pool
+            .run(move || hash_password(&password, &argon2_config))
+            .await?
+    }
+
+    // ユーザーと、パスワードが合っているか、古いコストなら作り直したハッシュを返す
+    async fn verify_login_password(
+        &self,
+        username: &str,
+        password: &str,
+    ) -> Result<(Option<User>, bool, Option<String>), AppError> {
+        let user = self.repository.find_user_by_username(username).await?;
+        let (verified, rehashed_password) = match &user {
+            Some(user) => {
+                let hashed_password = user.password.clone();
+                let password = password.to_string();
+                let argon2_config = self.argon2_config.clone();
+                self.cpu_pool
+                    .run(move || -> Result<(bool, Option<String>), AppError> {
+                        if !verify_password(&hashed_password, &password)? {
+                            return Ok((false, None));
+                        }
+                        // 平文が手元にあるうちに、古いコストのハッシュを作り直しておく
+                        if !password_needs_rehash(&hashed_password, &argon2_config) {
+                            return Ok((true, None));
+                        }
+                        Ok((true, Some(hash_password(&password, &argon2_config)?)))
+                    })
+                    .await??
+            }
+            None => (false, None),
+        };
+        Ok((user, verified, rehashed_password))
+    }
+
+    async fn verify_password_on_pool(&self, user: &User, password: &str) -> Result<bool, AppError> {
+        let hashed_password = user.password.clone();
+        let password = password.to_string();
+        self.cpu_pool
+            .run(move || verify_password(&hashed_password, &password))
+            .await?
+    }
+
+    // 現在のセッション以外はすべて無効にする
+    pub async fn change_password(
+        &self,
+        user_id: i32,
+        session_token: &str,
+        old_password: &str,
+        new_password: &str,
+    ) -> Result<(), AppError> {
+        if new_password.is_empty() {
+            return Err(AppError::BadRequest);
+        }
+
+        let user = self
+            .repository
+            .find_user_by_id(user_id)
+            .await?
+            .ok_or(AppError::NotFound)?;
+        if !self.verify_password_on_pool(&user, old_password).await? {
+            return Err(AppError::Unauthorized);
+        }
+
+        let hashed_password = self.hash_password_on_pool(new_password).await?;
+        self.repository
+            .update_user_password(user_id, &hashed_password)
+            .await?;
+        self.repository
+            .delete_other_sessions_by_user_id(user_id, session_token)
+            .await?;
+        self.session_cache.invalidate_user(user_id);
+
+        Ok(())
+    }
+
+    // ユーザー名が存在するかを応答や応答時間から推測されないよう、存在しなくても同じ処理をする
+    // 発行済みのトークンは再設定に使われるまで有効のままにする (他人の再設定要求で失効させない)
+    pub async fn request_password_reset(
+        &self,
+        username: &str,
+        client_address: &str,
+    ) -> Result<(), AppError> {
+        self.password_reset_throttle
+            .begin_attempt(&[
+                LoginThrottleKey::username(username),
+                LoginThrottleKey::address(client_address),
+            ])
+            .await
+            .map_err(AppError::TooManyRequests)?;
+
+        let user = self.repository.find_user_by_username(username).await?;
+        let reset_token = generate_secret();
+        let created = self
+            .repository
+            .create_password_reset_token(username, &sha256_hex(reset_token.as_bytes()))
+            .await?;
+        let Some(user) = user.filter(|_| created) else {
+            return Ok(());
+        };
+
+        let expires_at = Utc::now() + Duration::seconds(self.password_reset_config.token_ttl_secs);
+        let notifier = self.notifier.clone();
+        actix_web::rt::spawn(async move {
+            if let Err(err) = notifier
+                .send_password_reset(&user, &reset_token, expires_at)
+                .await
+            {
+                log::error!("failed to send password reset to user {}: {}", user.id, err);
+            }
+        });
+
+        Ok(())
+    }
+
+    // トークンは一度しか使えない。再設定後はすべてのセッションを無効にする
+    pub async fn reset_password(
+        &self,
+        reset_token: &str,
+        new_password: &str,
+    ) -> Result<(), AppError> {
+        if new_password.is_empty() {
+            return Err(AppError::BadRequest);
+        }
+
+        // ハッシュを作れなかったときにトークンを使い切らないよう、先にハッシュを作る
+        let hashed_password = self.hash_password_on_pool(new_password).await?;
+        let user_id = self
+            .repository
+            .reset_password_with_token(
+                &sha256_hex(reset_token.as_bytes()),
+                self.password_reset_config.token_ttl_secs,
+                &hashed_password,
+            )
+            .await?
+            .ok_or(AppError::BadRequest)?;
+        self.revoke_all_sessions(user_id).await?;
+
+        // 再設定できたならロックも解除してよい
+        if let Some(user) = self.repository.find_user_by_id(user_id).await? {
+            self.login_throttle
+                .clear(&LoginThrottleKey::username(&user.username))
+                .await;
+        }
+
+        Ok(())
+    }
+
+    pub async fn purge_expired_password_reset_tokens(&self) -> Result<u64, AppError> {
+        self.repository
+            .delete_expired_password_reset_tokens(self.password_reset_config.token_ttl_secs)
+            .await
+    }
+
+    pub fn cpu_pool_stats(&self) -> CpuPoolStats {
+        self.cpu_pool.stats()
+    }
+
+    pub fn get_login_lockouts(&self) -> Vec<LoginLockoutDto> {
+        let mut lockouts: Vec<LoginLockoutDto> = self
+            .login_throttle
+            .entries()
+            .into_iter()
+            .map(|(key, failures)| LoginLockoutDto {
+                kind: key.kind,
+                key: key.key,
+                failures: failures.failures,
+                last_failed_at: failures.last_failed_at,
+                locked_until: failures.locked_until.filter(|until| *until > Utc::now()),
+            })
+            .collect();
+        lockouts.sort_by_key(|lockout| std::cmp::Reverse(lockout.last_failed_at));
+        lockouts
+    }
+
+    pub async fn clear_login_lockout(
+        &self,
+        admin: &Principal,
+        kind: LoginThrottleKind,
+        key: &str,
+    ) -> Result<(), AppError> {
+        let throttle_key = match kind {
+            LoginThrottleKind::Username => LoginThrottleKey::username(key),
+            LoginThrottleKind::Address => LoginThrottleKey::address(key),
+        };
+        if !self.login_throttle.clear(&throttle_key).await {
+            return Err(AppError::NotFound);
+        }
+
+        self.repository
+            .create_admin_audit_log(&admin_audit(
+                admin.user_id,
+                "login_lockout.cleared",
+                None,
+                serde_json::json!({ "kind": kind, "key": key }),
+            ))
+            .await
+    }
+
+    // なりすましたセッションでの更新系のリクエストを、なりすました admin の操作として残す
+    pub async fn record_impersonated_request(
+        &self,
+        principal: &Principal,
+        method: &str,
+        path: &str,
+    ) -> Result<(), AppError> {
+        let Some(impersonator_id) = principal.impersonator_id else {
+            return Ok(());
+        };
+        self.repository
+            .create_admin_audit_log(&admin_audit(
+                impersonator_id,
+                "impersonation.request",
+                Some(principal.user_id),
+                serde_json::json!({ "method": method, "path": path }),
+            ))
+            .await
+    }
+
+    pub async fn search_users(
+        &self,
+        username_prefix: Option<&str>,
+        role: Option<&str>,
+        page: i32,
+        page_size: i32,
+    ) -> Result<Vec<AdminUserDto>, AppError> {
+        if page < 0 || page_size <= 0 {
+            return Err(AppError::BadRequest);
+        }
+        if role.is_some_and(|role| role.parse::<Role>().is_err()) {
+            return Err(AppError::BadRequest);
+        }
+
+        let users = self
+            .repository
+            .search_users(username_prefix, role, page, page_size)
+            .await?;
+
+        let mut results = Vec::with_capacity(users.len());
+        for user in users {
+            results.push(self.admin_user_dto(user).await?);
+        }
+        Ok(results)
+    }
+
+    async fn admin_user_dto(&self, user: User) -> Result<AdminUserDto, AppError> {
+        let area_ids = if user.role == "dispatcher" {
+            self.find_dispatcher_areas(user.id)
+                .await?
+                .map(|(_, area_ids)| area_ids)
+                .unwrap_or_default()
+        } else {
+            vec![]
+        };
+
+        Ok(AdminUserDto {
+            user_id: user.id,
+            username: user.username,
+            role: user.role,
+            area_ids,
+            disabled_at: user.disabled_at,
+            totp_enabled: user.totp_enabled_at.is_some(),
         })
     }
 
+    async fn find_managed_user(&self, user_id: i32) -> Result<User, AppError> {
+        self.repository
+            .find_user_by_id(user_id)
+            .await?
+            .filter(|user| user.deleted_at.is_none())
+            .ok_or(AppError::NotFound)
+    }
+
+    // dispatcher にするときは担当エリアも設定する
+    pub async fn change_user_role(
+        &self,
+        admin: &Principal,
+        user_id: i32,
+        role: &str,
+        area_ids: Option<&[i32]>,
+    ) -> Result<AdminUserDto, AppError> {
+        let new_role: Role = role.parse().map_err(|_| AppError::BadRequest)?;
+        // 自分の admin 権限を外すと誰も戻せなくなりうる
+        if user_id == admin.user_id {
+            return Err(AppError::BadRequest);
+        }
+        let user = self.find_managed_user(user_id).await?;
+        let current_role: Role = user
+            .role
+            .parse()
+            .map_err(|_| AppError::InternalServerError)?;
+
+        // レッカー車を担当したままのドライバーは、先に付け替える必要がある
+        if current_role == Role::Driver
+            && new_role != Role::Driver
+            && self
+                .repository
+                .find_tow_truck_id_by_driver_id(user_id)
+                .await?
+                .is_some()
+        {
+            return Err(AppError::Conflict);
+        }
+
+        let mut new_area_ids = None;
+        if new_role == Role::Dispatcher {
+            let dispatcher = self.repository.find_dispatcher_by_user_id(user_id).await?;
+            match (area_ids, dispatcher) {
+                (Some(area_ids), _) => {
+                    new_area_ids = Some(self.normalize_area_ids(area_ids).await?)
+                }
+                // 以前 dispatcher だったユーザーは元の担当エリアに戻す
+                (None, Some(_)) => {}
+                (None, None) => return Err(AppError::BadRequest),
+            }
+        }
+        // dispatchers の行は依頼から参照されているので、他のロールにしても消さない
+        self.repository
+            .update_user_role(
+                user_id,
+                role,
+                new_area_ids.as_deref(),
+                &admin_audit(
+                    admin.user_id,
+                    "user.role_changed",
+                    Some(user_id),
+                    serde_json::json!({ "from": user.role, "to": role, "area_ids": new_area_ids }),
+                ),
+            )
+            .await?;
+        self.session_cache.invalidate_user(user_id);
+
+        self.admin_user_dto(self.find_managed_user(user_id).await?)
+            .await
+    }
+
+    // 重複を除き、すべて存在するエリアであることを確かめる
+    async fn normalize_area_ids(&self, area_ids: &[i32]) -> Result<Vec<i32>, AppError> {
+        let mut area_ids = area_ids.to_vec();
+        area_ids.sort_unstable();
+        area_ids.dedup();
+        if area_ids.is_empty()
+            || self.repository.count_areas_by_ids(&area_ids).await? != area_ids.len() as i64
+        {
+            return Err(AppError::BadRequest);
+        }
+        Ok(area_ids)
+    }
+
+    pub async fn assign_dispatcher_areas(
+        &self,
+        admin: &Principal,
+        user_id: i32,
+        area_ids: &[i32],
+    ) -> Result<AdminUserDto, AppError> {
+        let user = self.find_managed_user(user_id).await?;
+        if user.role != "dispatcher" {
+            return Err(AppError::BadRequest);
+        }
+        let dispatcher = self.repository.find_dispatcher_by_user_id(user_id).await?;
+        let previous_area_ids = match &dispatcher {
+            Some(dispatcher) => {
+                self.repository
+                    .find_area_ids_by_dispatcher_id(dispatcher.id)
+                    .await?
+            }
+            None => vec![],
+        };
+        let area_ids = self.normalize_area_ids(area_ids).await?;
+        self.repository
+            .replace_dispatcher_areas(
+                user_id,
+                &area_ids,
+                &admin_audit(
+                    admin.user_id,
+                    "user.areas_assigned",
+                    Some(user_id),
+                    serde_json::json!({ "from": previous_area_ids, "to": area_ids }),
+                ),
+            )
+            .await?;
+        self.session_cache.invalidate_user(user_id);
+
+        self.admin_user_dto(user).await
+    }
+
+    // 無効にしたユーザーのセッションはすべて破棄する
+    pub async fn set_user_disabled(
+        &self,
+        admin: &Principal,
+        user_id: i32,
+        disabled: bool,
+    ) -> Result<AdminUserDto, AppError> {
+        if user_id == admin.user_id {
+            return Err(AppError::BadRequest);
+        }
+        self.find_managed_user(user_id).await?;
+
+        let action = if disabled {
+            "user.disabled"
+        } else {
+            "user.enabled"
+        };
+        self.repository
+            .set_user_disabled(
+                user_id,
+                disabled,
+                &admin_audit(admin.user_id, action, Some(user_id), serde_json::json!({})),
+            )
+            .await?;
+        self.session_cache.invalidate_user(user_id);
+
+        self.admin_user_dto(self.find_managed_user(user_id).await?)
+            .await
+    }
+
+    // 端末とリカバリーコードをなくしたユーザー向け (次のログインで登録し直す)
+    pub async fn reset_user_totp(
+        &self,
+        admin: &Principal,
+        user_id: i32,
+    ) -> Result<AdminUserDto, AppError> {
+        self.find_managed_user(user_id).await?;
+        self.repository
+            .disable_totp(
+                user_id,
+                Some(&admin_audit(
+                    admin.user_id,
+                    "user.totp_reset",
+                    Some(user_id),
+                    serde_json::json!({}),
+                )),
+            )
+            .await?;
+
+        self.admin_user_dto(self.find_managed_user(user_id).await?)
+            .await
+    }
+
+    // サポートのため、admin がそのユーザーとしてログインする
+    pub async fn impersonate_user(
+        &self,
+        admin: &Principal,
+        user_id: i32,
+    ) -> Result<LoginResponseDto, AppError> {
+        let user = self.find_managed_user(user_id).await?;
+        // admin へのなりすましは権限の付け替えと同じなので認めない
+        if user.role == "admin" || user.disabled_at.is_some() {
+            return Err(AppError::Forbidden);
+        }
+
+        let session_token = generate_session_token();
+        self.repository
+            .create_impersonation_session(
+                user.id,
+                &session_token,
+                &admin_audit(
+                    admin.user_id,
+                    "user.impersonated",
+                    Some(user_id),
+                    serde_json::json!({ "username": user.username }),
+                ),
+            )
+            .await?;
+
+        self.login_response(user, session_token).await
+    }
+
+    pub async fn get_admin_audit_logs(
+        &self,
+        target_user_id: Option<i32>,
+        page: i32,
+        page_size: i32,
+    ) -> Result<Vec<AuditLogDto>, AppError> {
+        if page < 0 || page_size <= 0 {
+            return Err(AppError::BadRequest);
+        }
+
+        let logs = self
+            .repository
+            .get_admin_audit_logs(target_user_id, page, page_size)
+            .await?;
+
+        Ok(logs
+            .into_iter()
+            .map(|log| AuditLogDto {
+                id: log.id,
+                admin_user_id: log.admin_user_id,
+                action: log.action,
+                target_user_id: log.target_user_id,
+                details: serde_json::from_str(&log.details).unwrap_or(serde_json::Value::Null),
+                created_at: log.created_at,
+            })
+            .collect())
+    }
+
     pub async fn logout_user(&self, session_token: &str) -> Result<(), AppError> {
         self.repository.delete_session(session_token).await?;
+        self.session_cache.invalidate(session_token).await;
         Ok(())
     }
 
-    // NOTE: 戻り値は Path が適切かもしれない
-    pub async fn get_resized_profile_image_byte(&self, user_id: i32) -> Result<Bytes, AppError> {
-        let icon_width = 500;
-        let icon_height = 500;
+    pub async fn get_me(&self, principal: &Principal) -> Result<MeDto, AppError> {
+        let user = self
+            .repository
+            .find_user_by_id(principal.user_id)
+            .await?
+            .ok_or(AppError::NotFound)?;
+
+        Ok(MeDto {
+            user_id: user.id,
+            username: user.username,
+            role: user.role,
+            dispatcher_id: principal.dispatcher_id,
+            area_ids: principal.area_ids.clone(),
+            tow_truck_id: principal.tow_truck_id,
+        })
+    }
 
-        let profile_image_name = match self
+    // ロール・担当エリア・レッカー車の変更は admin だけが行える
+    pub async fn update_me(
+        &self,
+        principal: &Principal,
+        username: Option<&str>,
+    ) -> Result<MeDto, AppError> {
+        if let Some(username) = username {
+            let username = username.trim();
+            if username.is_empty()
+                || username.len() > 255
+                || username.starts_with(DELETED_USERNAME_PREFIX)
+            {
+                return Err(AppError::BadRequest);
+            }
+            self.repository
+                .update_username(principal.user_id, username)
+                .await?;
+        }
+
+        self.get_me(principal).await
+    }
+
+    // 依頼の履歴を残すため、行は消さずにユーザー名とパスワードを潰して匿名化する
+    pub async fn delete_me(&self, principal: &Principal, password: &str) -> Result<(), AppError> {
+        if principal.role == Role::Admin {
+            return Err(AppError::Forbidden);
+        }
+        // レッカー車を担当したままのドライバーは、先に admin が付け替える必要がある
+        if principal.tow_truck_id.is_some() {
+            return Err(AppError::Conflict);
+        }
+
+        let user = self
             .repository
-            .find_profile_image_name_by_user_id(user_id)
-            .await
+            .find_user_by_id(principal.user_id)
+            .await?
+            .ok_or(AppError::NotFound)?;
+        if !self.verify_password_on_pool(&user, password).await? {
+            return Err(AppError::Unauthorized);
+        }
+
+        // 誰も知らないパスワードにしてログインできなくする
+        let unusable_password = self.hash_password_on_pool(&generate_secret()).await?;
+        // 進行中の依頼は依頼者の情報が必要なので、終わるまで退会できない
+        if !self
+            .repository
+            .anonymize_user(
+                user.id,
+                &format!("{}{}", DELETED_USERNAME_PREFIX, user.id),
+                &unusable_password,
+            )
+            .await?
+        {
+            return Err(AppError::Conflict);
+        }
+        self.session_cache.invalidate_user(user.id);
+
+        Ok(())
+    }
+
+    pub fn max_profile_image_upload_bytes(&self) -> usize {
+        self.profile_image_config.max_upload_bytes
+    }
+
+    // 本人か admin だけが変更できる
+    pub async fn update_profile_image(
+        &self,
+        principal: &Principal,
+        user_id: i32,
+        bytes: Bytes,
+    ) -> Result<ProfileImageDto, AppError> {
+        if principal.user_id != user_id && principal.role != Role::Admin {
+            return Err(AppError::Forbidden);
+        }
+
+        let config = self.profile_image_config.clone();
+        let profile_image = self
+            .cpu_pool
+            .run(move || -> Result<String, AppError> {
+                let encoded = normalize_profile_image(&bytes, &config)?;
+                store_profile_image(&encoded).map_err(|err| {
+                    log::error!("failed to store profile image: {}", err);
+                    AppError::InternalServerError
+                })
+            })
+            .await??;
+
+        let previous_profile_image = self
+            .repository
+            .update_profile_image_name(user_id, &profile_image)
+            .await?
+            .ok_or(AppError::NotFound)?;
+        // ファイル名は内容のハッシュなので、同じ画像を使う他のユーザーがいなくなってから消す
+        if previous_profile_image != profile_image
+            && previous_profile_image != DEFAULT_PROFILE_IMAGE
+            && self
+                .repository
+                .count_users_by_profile_image(&previous_profile_image)
+                .await?
+                == 0
         {
-            Ok(Some(name)) => name,
-            Ok(None) => return Err(AppError::NotFound),
-            Err(_) => return Err(AppError::NotFound),
-        };
-
-        let path: PathBuf =
-            Path::new(&format!("images/user_profile/{}", profile_image_name)).to_path_buf();
-
-        let src_image = ImageReader::open(path).unwrap().decode().unwrap();
-        let mut dst_image = Image::new(icon_width, icon_height, src_image.pixel_type().unwrap());
-        let mut resizer = Resizer::new();
-        resizer.resize(&src_image, &mut dst_image, None).unwrap();
-
-        let mut result_buf = BufWriter::new(Vec::new());
-        PngEncoder::new(&mut result_buf)
-            .write_image(
-                dst_image.buffer(),
-                icon_width,
-                icon_height,
-                src_image.color().into(),
+            if let Err(err) = remove_profile_image(&previous_profile_image) {
+                log::warn!(
+                    "failed to remove profile image {}: {}",
+                    previous_profile_image,
+                    err
+                );
+            }
+        }
+
+        Ok(ProfileImageDto {
+            user_id,
+            profile_image,
+        })
+    }
+
+    // 画像を設定していないユーザーや、画像が読めないユーザーには identicon を返す
+    pub async fn get_profile_image_variant(
+        &self,
+        user_id: i32,
+        size: u32,
+        format: ProfileImageFormat,
+    ) -> Result<ProfileImageVariant, AppError> {
+        if !PROFILE_IMAGE_SIZES.contains(&size) {
+            return Err(AppError::BadRequest);
+        }
+
+        let user = self
+            .repository
+            .find_user_by_id(user_id)
+            .await?
+            .ok_or(AppError::NotFound)?;
+        let identicon = ProfileImageSource::identicon(user.id, &user.username);
+
+        if user.profile_image != DEFAULT_PROFILE_IMAGE {
+            let source = ProfileImageSource::File(user.profile_image);
+            match self
+                .render_profile_image_variant(&source, size, format)
+                .await
+            {
+                Err(err @ (AppError::NotFound | AppError::InternalServerError)) => {
+                    log::warn!(
+                        "failed to render profile image {:?} of user {}: {}",
+                        source,
+                        user_id,
+                        err
+                    );
+                }
+                result => return result,
+            }
+        }
+
+        self.render_profile_image_variant(&identicon, size, format)
+            .await
+    }
+
+    // ファイル名は内容のハッシュ、identicon はシードで決まるので、元をキーにしてメモ化できる
+    async fn render_profile_image_variant(
+        &self,
+        source: &ProfileImageSource,
+        size: u32,
+        format: ProfileImageFormat,
+    ) -> Result<ProfileImageVariant, AppError> {
+        let key = (source.clone(), size, format);
+        if let Some(variant) = self.profile_image_cache.get(&key).await {
+            return Ok(variant);
+        }
+
+        let render_source = source.clone();
+        let encoded = self
+            .cpu_pool
+            .run(move || render_profile_image_variant(&render_source, size, format))
+            .await??;
+        let variant = ProfileImageVariant {
+            etag: format!("\"{}\"", sha256_hex(&encoded)),
+            content_type: format.content_type(),
+            bytes: Bytes::from(encoded),
+        };
+        self.profile_image_cache.insert(key, variant.clone()).await;
+
+        Ok(variant)
+    }
+
+    async fn find_active_session(&self, session_token: &str) -> Result<Session, AppError> {
+        self.repository
+            .find_active_session_by_session_token(
+                session_token,
+                self.session_config.absolute_ttl_secs,
+                self.session_config.idle_ttl_secs,
+                self.session_config.impersonation_ttl_secs,
             )
-            .unwrap();
+            .await?
+            .ok_or(AppError::Unauthorized)
+    }
 
-        Ok(Bytes::from(result_buf.into_inner().unwrap()))
+    fn session_expires_at(&self, session: &Session) -> DateTime<Utc> {
+        let ttl_secs = if session.impersonator_id.is_some() {
+            self.session_config.impersonation_ttl_secs
+        } else {
+            self.session_config.absolute_ttl_secs
+        };
+        (session.created_at + Duration::seconds(ttl_secs))
+            .min(session.last_used_at + Duration::seconds(self.session_config.idle_ttl_secs))
     }
 
-    pub async fn validate_session(&self, session_token: &str) -> Result<bool, AppError> {
-        let session = self
+    // 新しいトークンを発行し、使ったトークンは無効にする
+    pub async fn refresh_session(
+        &self,
+        session_token: &str,
+    ) -> Result<RefreshSessionResponseDto, AppError> {
+        let session = self.find_active_session(session_token).await?;
+        // なりすましのセッションは延長させない
+        if session.impersonator_id.is_some() {
+            return Err(AppError::Forbidden);
+        }
+
+        let new_session_token = generate_session_token();
+        self.repository
+            .create_session(session.user_id, &new_session_token)
+            .await?;
+        self.repository.delete_session(session_token).await?;
+        self.session_cache.invalidate(session_token).await;
+
+        let new_session = self.find_active_session(&new_session_token).await?;
+
+        Ok(RefreshSessionResponseDto {
+            expires_at: self.session_expires_at(&new_session),
+            session_token: new_session_token,
+        })
+    }
+
+    pub async fn get_sessions(
+        &self,
+        user_id: i32,
+        current_session_token: &str,
+    ) -> Result<Vec<SessionDto>, AppError> {
+        let sessions = self
             .repository
-            .find_session_by_session_token(session_token)
+            .get_active_sessions_by_user_id(
+                user_id,
+                self.session_config.absolute_ttl_secs,
+                self.session_config.idle_ttl_secs,
+                self.session_config.impersonation_ttl_secs,
+            )
             .await?;
 
-        Ok(session.is_valid)
+        Ok(sessions
+            .into_iter()
+            .map(|session| SessionDto {
+                id: session.id,
+                created_at: session.created_at,
+                last_used_at: session.last_used_at,
+                expires_at: self.session_expires_at(&session),
+                is_current: session.session_token == current_session_token,
+            })
+            .collect())
+    }
+
+    pub async fn revoke_session(&self, user_id: i32, session_id: i32) -> Result<(), AppError> {
+        if !self
+            .repository
+            .delete_session_by_id(user_id, session_id)
+            .await?
+        {
+            return Err(AppError::NotFound);
+        }
+        // セッション ID からはトークンが分からないので、ユーザーのキャッシュをまとめて捨てる
+        self.session_cache.invalidate_user(user_id);
+
+        Ok(())
+    }
+
+    // すべての端末からログアウトする
+    pub async fn revoke_all_sessions(&self, user_id: i32) -> Result<(), AppError> {
+        self.repository.delete_sessions_by_user_id(user_id).await?;
+        self.session_cache.invalidate_user(user_id);
+        Ok(())
+    }
+
+    pub fn session_cache_stats(&self) -> SessionCacheStats {
+        self.session_cache.stats()
+    }
+
+    pub async fn purge_expired_sessions(&self) -> Result<u64, AppError> {
+        self.repository
+            .delete_expired_sessions(
+                self.session_config.absolute_ttl_secs,
+                self.session_config.idle_ttl_secs,
+                self.session_config.impersonation_ttl_secs,
+            )
+            .await
+    }
+
+    // テストで DB を使わずにセッションを有効にする
+    #[cfg(test)]
+    pub async fn cache_session(&self, session_token: &str, principal: Principal) {
+        self.session_cache
+            .insert(session_token, principal, self.session_cache.generation())
+            .await;
+    }
+
+    // 有効なセッションであればそのユーザーを Principal として返す
+    pub async fn validate_session(&self, session_token: &str) -> Result<Principal, AppError> {
+        if let Some(principal) = self.session_cache.get(session_token).await {
+            return Ok(principal);
+        }
+
+        // 引いている間にログアウトなどで失効したら、キャッシュには入れない
+        let cache_generation = self.session_cache.generation();
+        let row: SessionPrincipalRow = self
+            .repository
+            .find_session_principal(
+                session_token,
+                self.session_config.absolute_ttl_secs,
+                self.session_config.idle_ttl_secs,
+                self.session_config.impersonation_ttl_secs,
+            )
+            .await?
+            .ok_or(AppError::Unauthorized)?;
+        if row.last_used_at < Utc::now() - Duration::seconds(SESSION_TOUCH_INTERVAL_SECS) {
+            self.repository.touch_session(row.session_id).await?;
+        }
+        let role: Role = row.role.parse().map_err(|_| AppError::Unauthorized)?;
+
+        let mut principal = Principal {
+            user_id: row.user_id,
+            role,
+            dispatcher_id: None,
+            area_ids: vec![],
+            tow_truck_id: None,
+            impersonator_id: row.impersonator_id,
+        };
+        match role {
+            Role::Dispatcher => {
+                principal.dispatcher_id = row.dispatcher_id;
+                principal.area_ids = row
+                    .area_ids
+                    .as_deref()
+                    .unwrap_or_default()
+                    .split(',')
+                    .filter_map(|area_id| area_id.parse().ok())
+                    .collect();
+            }
+            Role::Driver => principal.tow_truck_id = row.tow_truck_id,
+            Role::Client | Role::Admin => {}
+        }
+
+        self.session_cache
+            .insert(session_token, principal.clone(), cache_generation)
+            .await;
+
+        Ok(principal)
+    }
+}
+
+fn admin_audit(
+    admin_user_id: i32,
+    action: &'static str,
+    target_user_id: Option<i32>,
+    details: serde_json::Value,
+) -> AdminAuditEntry {
+    AdminAuditEntry {
+        admin_user_id,
+        action,
+        target_user_id,
+        details: details.to_string(),
     }
 }
@@ -1,5 +1,8 @@
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 
+use crate::infrastructure::login_throttle::LoginThrottleKind;
+
 // Input Data Structure
 
 #[derive(Deserialize, Debug)]
@@ -21,6 +24,46 @@ pub struct LogoutRequestDto {
     pub session_token: String,
 }
 
+#[derive(Deserialize, Debug)]
+pub struct ClearLoginLockoutQueryDto {
+    pub kind: LoginThrottleKind,
+    pub key: String,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct ChangePasswordRequestDto {
+    pub old_password: String,
+    pub new_password: String,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct RequestPasswordResetRequestDto {
+    pub username: String,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct ResetPasswordRequestDto {
+    pub reset_token: String,
+    pub new_password: String,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct TotpLoginRequestDto {
+    pub challenge_token: String,
+    // 認証アプリのコードか、リカバリーコード
+    pub code: String,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct TotpChallengeRequestDto {
+    pub challenge_token: String,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct ProfileImageQueryDto {
+    pub size: Option<u32>,
+}
+
 // Output Data Structure
 
 #[derive(Serialize)]
@@ -30,5 +73,81 @@ pub struct LoginResponseDto {
     pub session_token: String,
     pub role: String,
     pub dispatcher_id: Option<i32>,
-    pub area_id: Option<i32>,
+    pub area_ids: Vec<i32>,
+}
+
+// 2 段階認証が必要なら、セッションの代わりに確認用のトークンを返す
+#[derive(Serialize)]
+#[serde(untagged)]
+pub enum LoginResultDto {
+    Session(LoginResponseDto),
+    TotpRequired(TotpChallengeDto),
+}
+
+#[derive(Serialize)]
+pub struct TotpChallengeDto {
+    pub challenge_token: String,
+    // true なら、ログインの前に challenge_token を使って TOTP を登録する
+    pub totp_enrollment_required: bool,
+    pub expires_at: DateTime<Utc>,
+}
+
+#[derive(Serialize)]
+pub struct TotpLoginResponseDto {
+    #[serde(flatten)]
+    pub login: LoginResponseDto,
+    // ログイン時に登録を済ませた場合だけ返す (再表示はできない)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub recovery_codes: Option<Vec<String>>,
+}
+
+#[derive(Serialize)]
+pub struct TotpEnrollmentDto {
+    // 認証アプリに手入力する場合の Base32 表記
+    pub secret: String,
+    pub provisioning_uri: String,
+}
+
+#[derive(Serialize)]
+pub struct TotpRecoveryCodesDto {
+    pub recovery_codes: Vec<String>,
+}
+
+#[derive(Serialize)]
+pub struct TotpStatusDto {
+    pub enabled: bool,
+    // ロールによって登録が必須か
+    pub required: bool,
+    pub recovery_codes_remaining: i64,
+}
+
+#[derive(Serialize)]
+pub struct RefreshSessionResponseDto {
+    pub session_token: String,
+    pub expires_at: DateTime<Utc>,
+}
+
+#[derive(Serialize)]
+pub struct SessionDto {
+    pub id: i32,
+    pub created_at: DateTime<Utc>,
+    pub last_used_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+    pub is_current: bool,
+}
+
+#[derive(Serialize)]
+pub struct LoginLockoutDto {
+    pub kind: LoginThrottleKind,
+    pub key: String,
+    pub failures: u32,
+    pub last_failed_at: DateTime<Utc>,
+    // ロック中でなければ null
+    pub locked_until: Option<DateTime<Utc>>,
+}
+
+#[derive(Serialize)]
+pub struct ProfileImageDto {
+    pub user_id: i32,
+    pub profile_image: String,
 }
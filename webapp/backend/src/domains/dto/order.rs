@@ -1,23 +1,33 @@
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 
-use crate::models::order::CompletedOrder;
+use crate::models::order::{CompletedOrder, OrderOffer};
 
 // Input Data Structure
 
 #[derive(Deserialize, Debug)]
 pub struct ClientOrderRequestDto {
-    pub client_id: i32,
     pub node_id: i32,
     pub car_value: f64,
+    pub required_vehicle_class: Option<String>,
+    // 費用を負担する保険会社など
+    pub sponsor: Option<String>,
 }
 
 #[derive(Deserialize, Debug)]
 pub struct DispatcherOrderRequestDto {
     pub order_id: i32,
-    pub dispatcher_id: i32,
     pub tow_truck_id: i32,
-    pub order_time: DateTime<Utc>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct PaginatedOrderQueryDto {
+    pub page: Option<i32>,
+    pub page_size: Option<i32>,
+    pub sort_by: Option<String>,
+    pub sort_order: Option<String>,
+    pub status: Option<String>,
+    pub area: Option<i32>,
 }
 
 #[derive(Deserialize, Debug)]
@@ -43,6 +53,7 @@ pub struct OrderDto {
     pub node_id: i32,
     pub area_id: i32,
     pub car_value: f64,
+    pub required_vehicle_class: String,
     pub order_time: DateTime<Utc>,
     pub completed_time: Option<DateTime<Utc>>,
 }
@@ -69,3 +80,43 @@ impl CompletedOrderDto {
         }
     }
 }
+
+#[derive(Serialize, Debug)]
+pub struct OrderOfferDto {
+    pub id: i32,
+    pub order_id: i32,
+    pub tow_truck_id: i32,
+    pub dispatcher_id: i32,
+    pub status: String,
+    pub created_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+    pub responded_at: Option<DateTime<Utc>>,
+}
+
+impl OrderOfferDto {
+    pub fn from_entity(entity: OrderOffer) -> Self {
+        OrderOfferDto {
+            id: entity.id,
+            order_id: entity.order_id,
+            tow_truck_id: entity.tow_truck_id,
+            dispatcher_id: entity.dispatcher_id,
+            status: entity.status,
+            created_at: entity.created_at,
+            expires_at: entity.expires_at,
+            responded_at: entity.responded_at,
+        }
+    }
+}
+
+// event_type: offered | offer_declined | offer_expired | offers_exhausted
+//   | dispatched | truck_assigned | completed | cancelled
+#[derive(Serialize, Clone, Debug)]
+pub struct OrderEventDto {
+    pub event_type: String,
+    pub order_id: i32,
+    pub client_id: i32,
+    pub status: String,
+    pub tow_truck_id: Option<i32>,
+    pub driver_username: Option<String>,
+    pub sponsor: Option<String>,
+}
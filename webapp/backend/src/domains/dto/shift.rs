@@ -0,0 +1,52 @@
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+use crate::models::shift::{OnDutyDriver, Shift};
+
+// Output Data Structure
+
+#[derive(Serialize, Debug)]
+pub struct ShiftDto {
+    pub id: i32,
+    pub driver_user_id: i32,
+    pub tow_truck_id: i32,
+    pub started_at: DateTime<Utc>,
+    pub ended_at: Option<DateTime<Utc>>,
+}
+
+impl ShiftDto {
+    pub fn from_entity(entity: Shift) -> Self {
+        ShiftDto {
+            id: entity.id,
+            driver_user_id: entity.driver_id,
+            tow_truck_id: entity.tow_truck_id,
+            started_at: entity.started_at,
+            ended_at: entity.ended_at,
+        }
+    }
+}
+
+#[derive(Serialize, Debug)]
+pub struct OnDutyDriverDto {
+    pub shift_id: i32,
+    pub driver_user_id: i32,
+    pub driver_username: String,
+    pub tow_truck_id: i32,
+    pub tow_truck_status: String,
+    pub area_id: i32,
+    pub started_at: DateTime<Utc>,
+}
+
+impl OnDutyDriverDto {
+    pub fn from_entity(entity: OnDutyDriver) -> Self {
+        OnDutyDriverDto {
+            shift_id: entity.shift_id,
+            driver_user_id: entity.driver_id,
+            driver_username: entity.driver_username,
+            tow_truck_id: entity.tow_truck_id,
+            tow_truck_status: entity.tow_truck_status,
+            area_id: entity.area_id,
+            started_at: entity.started_at,
+        }
+    }
+}
@@ -1,21 +1,41 @@
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 
+use crate::models::geofence::GeofenceEvent;
+use crate::models::tow_truck::StaleTowTruck;
+
 // Input Data Structure
 
 #[derive(Deserialize, Debug)]
 pub struct UpdateLocationRequestDto {
-    pub tow_truck_id: i32,
     pub node_id: i32,
 }
 
+#[derive(Deserialize, Debug)]
+pub struct CreateTowTruckRequestDto {
+    pub driver_id: i32,
+    pub area_id: i32,
+    pub node_id: i32,
+    pub vehicle_class: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct UpdateTowTruckRequestDto {
+    pub driver_id: Option<i32>,
+    pub area_id: Option<i32>,
+    pub node_id: Option<i32>,
+    pub vehicle_class: Option<String>,
+}
+
 // Output Data Structure
 
-#[derive(Serialize, Clone)]
+#[derive(Serialize, Clone, Debug)]
 pub struct TowTruckDto {
     pub id: i32,
     pub driver_user_id: i32,
     pub driver_username: Option<String>,
     pub status: String,
+    pub vehicle_class: String,
     pub node_id: i32,
     pub area_id: i32,
 }
@@ -27,8 +47,66 @@ impl TowTruckDto {
             driver_user_id: entity.driver_id,
             driver_username: entity.driver_username,
             status: entity.status,
+            vehicle_class: entity.vehicle_class,
             node_id: entity.node_id,
             area_id: entity.area_id,
         }
     }
 }
+
+#[derive(Serialize)]
+pub struct StaleTowTruckDto {
+    pub id: i32,
+    pub driver_user_id: i32,
+    pub driver_username: Option<String>,
+    pub status: String,
+    pub node_id: i32,
+    pub area_id: i32,
+    pub last_located_at: DateTime<Utc>,
+}
+
+impl StaleTowTruckDto {
+    pub fn from_entity(entity: StaleTowTruck) -> Self {
+        StaleTowTruckDto {
+            id: entity.id,
+            driver_user_id: entity.driver_id,
+            driver_username: entity.driver_username,
+            status: entity.status,
+            node_id: entity.node_id,
+            area_id: entity.area_id,
+            last_located_at: entity.last_located_at,
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct GeofenceEventDto {
+    pub id: i32,
+    pub tow_truck_id: i32,
+    pub area_id: i32,
+    pub node_id: i32,
+    pub node_area_id: i32,
+    pub event_type: String,
+    pub created_at: DateTime<Utc>,
+}
+
+impl GeofenceEventDto {
+    pub fn from_entity(entity: GeofenceEvent) -> Self {
+        GeofenceEventDto {
+            id: entity.id,
+            tow_truck_id: entity.tow_truck_id,
+            area_id: entity.area_id,
+            node_id: entity.node_id,
+            node_area_id: entity.node_area_id,
+            event_type: entity.event_type,
+            created_at: entity.created_at,
+        }
+    }
+}
+
+// event_type: location | status
+#[derive(Serialize, Clone, Debug)]
+pub struct TowTruckEventDto {
+    pub event_type: String,
+    pub tow_truck: TowTruckDto,
+}
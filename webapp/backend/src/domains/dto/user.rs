@@ -0,0 +1,83 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+// Input Data Structure
+
+#[derive(Deserialize, Debug)]
+pub struct UpdateMeRequestDto {
+    pub username: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct DeleteMeRequestDto {
+    pub password: String,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct ConfirmTotpRequestDto {
+    pub code: String,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct DisableTotpRequestDto {
+    pub password: String,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct SearchUsersQueryDto {
+    pub username_prefix: Option<String>,
+    pub role: Option<String>,
+    pub page: Option<i32>,
+    pub page_size: Option<i32>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct ChangeRoleRequestDto {
+    pub role: String,
+    // dispatcher に変更するときは必須
+    pub area_ids: Option<Vec<i32>>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct AssignAreasRequestDto {
+    pub area_ids: Vec<i32>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct AuditLogQueryDto {
+    pub target_user_id: Option<i32>,
+    pub page: Option<i32>,
+    pub page_size: Option<i32>,
+}
+
+// Output Data Structure
+
+#[derive(Serialize)]
+pub struct MeDto {
+    pub user_id: i32,
+    pub username: String,
+    pub role: String,
+    pub dispatcher_id: Option<i32>,
+    pub area_ids: Vec<i32>,
+    pub tow_truck_id: Option<i32>,
+}
+
+#[derive(Serialize)]
+pub struct AdminUserDto {
+    pub user_id: i32,
+    pub username: String,
+    pub role: String,
+    pub area_ids: Vec<i32>,
+    pub disabled_at: Option<DateTime<Utc>>,
+    pub totp_enabled: bool,
+}
+
+#[derive(Serialize)]
+pub struct AuditLogDto {
+    pub id: i32,
+    pub admin_user_id: i32,
+    pub action: String,
+    pub target_user_id: Option<i32>,
+    pub details: serde_json::Value,
+    pub created_at: DateTime<Utc>,
+}
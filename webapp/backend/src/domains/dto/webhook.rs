@@ -0,0 +1,78 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::models::webhook::{WebhookDelivery, WebhookSubscription};
+
+// Input Data Structure
+
+#[derive(Deserialize, Debug)]
+pub struct RegisterWebhookRequestDto {
+    pub url: String,
+    pub event_types: Vec<String>,
+    // 指定するとこの sponsor の依頼のイベントだけを受け取る
+    pub sponsor: Option<String>,
+}
+
+// Output Data Structure
+
+#[derive(Serialize, Debug)]
+pub struct WebhookSubscriptionDto {
+    pub id: i32,
+    pub url: String,
+    pub event_types: Vec<String>,
+    pub sponsor: Option<String>,
+    pub is_active: bool,
+    pub created_at: DateTime<Utc>,
+}
+
+impl WebhookSubscriptionDto {
+    pub fn from_entity(entity: WebhookSubscription) -> Self {
+        WebhookSubscriptionDto {
+            id: entity.id,
+            event_types: entity.event_types(),
+            url: entity.url,
+            sponsor: entity.sponsor,
+            is_active: entity.is_active,
+            created_at: entity.created_at,
+        }
+    }
+}
+
+// 署名用の secret は登録時にだけ返す
+#[derive(Serialize, Debug)]
+pub struct RegisteredWebhookDto {
+    #[serde(flatten)]
+    pub subscription: WebhookSubscriptionDto,
+    pub secret: String,
+}
+
+#[derive(Serialize, Debug)]
+pub struct WebhookDeliveryDto {
+    pub id: i32,
+    pub subscription_id: i32,
+    pub event_type: String,
+    pub status: String,
+    pub attempts: i32,
+    pub response_status: Option<i32>,
+    pub last_error: Option<String>,
+    pub next_attempt_at: DateTime<Utc>,
+    pub created_at: DateTime<Utc>,
+    pub delivered_at: Option<DateTime<Utc>>,
+}
+
+impl WebhookDeliveryDto {
+    pub fn from_entity(entity: WebhookDelivery) -> Self {
+        WebhookDeliveryDto {
+            id: entity.id,
+            subscription_id: entity.subscription_id,
+            event_type: entity.event_type,
+            status: entity.status,
+            attempts: entity.attempts,
+            response_status: entity.response_status,
+            last_error: entity.last_error,
+            next_attempt_at: entity.next_attempt_at,
+            created_at: entity.created_at,
+            delivered_at: entity.delivered_at,
+        }
+    }
+}
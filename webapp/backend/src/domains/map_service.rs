@@ -1,6 +1,9 @@
 use crate::{
     errors::AppError,
-    models::graph::{Edge, Node},
+    models::{
+        graph::{Edge, Node},
+        user::{Principal, Role},
+    },
 };
 
 pub trait MapRepository {
@@ -25,12 +28,27 @@ impl<T: MapRepository + std::fmt::Debug> MapService<T> {
         MapService { repository }
     }
 
+    // ディスパッチャーは両端のノードが担当エリアにある道路だけを変更できる
     pub async fn update_edge(
         &self,
+        principal: &Principal,
         node_a_id: i32,
         node_b_id: i32,
         weight: i32,
     ) -> Result<(), AppError> {
+        if principal.role != Role::Admin {
+            for node_id in [node_a_id, node_b_id] {
+                let area_id = match self.repository.get_area_id_by_node_id(node_id).await {
+                    Ok(area_id) => area_id,
+                    Err(sqlx::Error::RowNotFound) => return Err(AppError::BadRequest),
+                    Err(err) => return Err(err.into()),
+                };
+                if !principal.can_access_area(area_id) {
+                    return Err(AppError::Forbidden);
+                }
+            }
+        }
+
         self.repository
             .update_edge(node_a_id, node_b_id, weight)
             .await?;
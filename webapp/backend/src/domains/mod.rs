@@ -2,4 +2,6 @@ pub mod auth_service;
 pub mod dto;
 pub mod map_service;
 pub mod order_service;
+pub mod shift_service;
 pub mod tow_truck_service;
+pub mod webhook_service;
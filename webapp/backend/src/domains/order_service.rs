@@ -1,16 +1,28 @@
 use std::collections::HashMap;
 
 use chrono::{DateTime, Utc};
+use tokio::sync::broadcast;
 
 use super::{
     auth_service::AuthRepository,
-    dto::order::{CompletedOrderDto, OrderDto},
+    dto::{
+        order::{
+            CompletedOrderDto, OrderDto, OrderEventDto, OrderOfferDto, PaginatedOrderQueryDto,
+        },
+        tow_truck::{TowTruckDto, TowTruckEventDto},
+    },
     map_service::MapRepository,
-    tow_truck_service::TowTruckRepository,
+    tow_truck_service::{find_nearest_dispatchable_tow_truck, TowTruckRepository},
 };
 use crate::{
+    config::DispatchConfig,
     errors::AppError,
-    models::order::{CompletedOrder, Order},
+    infrastructure::event_bus::EventBus,
+    models::{
+        order::{CompletedOrder, Order, OrderOffer},
+        tow_truck::VehicleClass,
+        user::{Principal, Role},
+    },
 };
 
 pub trait OrderRepository {
@@ -23,27 +35,43 @@ pub trait OrderRepository {
         sort_by: Option<String>,
         sort_order: Option<String>,
         status: Option<String>,
-        area: Option<i32>,
+        area_ids: Option<&[i32]>,
     ) -> Result<Vec<Order>, AppError>;
     async fn create_order(
         &self,
         customer_id: i32,
         node_id: i32,
         car_value: f64,
+        required_vehicle_class: Option<&str>,
+        sponsor: Option<&str>,
     ) -> Result<(), AppError>;
-    async fn update_order_dispatched(
+    async fn get_all_completed_orders(&self) -> Result<Vec<CompletedOrder>, AppError>;
+    async fn create_order_offer(
         &self,
-        id: i32,
-        dispatcher_id: i32,
+        order_id: i32,
         tow_truck_id: i32,
-    ) -> Result<(), AppError>;
-    async fn create_completed_order(
+        dispatcher_id: i32,
+        timeout_secs: i64,
+        order_status: &str,
+    ) -> Result<Option<i32>, AppError>;
+    async fn find_order_offer_by_id(&self, id: i32) -> Result<Option<OrderOffer>, AppError>;
+    async fn get_order_offers_by_order_id(
         &self,
         order_id: i32,
+    ) -> Result<Vec<OrderOffer>, AppError>;
+    async fn get_pending_order_offers_by_tow_truck_id(
+        &self,
         tow_truck_id: i32,
+    ) -> Result<Vec<OrderOffer>, AppError>;
+    async fn get_expired_order_offers(&self) -> Result<Vec<OrderOffer>, AppError>;
+    // 期限内の pending の打診だけを承諾でき、同じトランザクションで配車を確定する (承諾できたら true)
+    async fn accept_order_offer(
+        &self,
+        offer: &OrderOffer,
         completed_time: DateTime<Utc>,
-    ) -> Result<(), AppError>;
-    async fn get_all_completed_orders(&self) -> Result<Vec<CompletedOrder>, AppError>;
+    ) -> Result<bool, AppError>;
+    // pending の打診を declined / expired にする (更新できたら true)
+    async fn close_order_offer(&self, id: i32, status: &str) -> Result<bool, AppError>;
 }
 
 #[derive(Debug)]
@@ -57,6 +85,9 @@ pub struct OrderService<
     tow_truck_repository: U,
     auth_repository: V,
     map_repository: W,
+    dispatch_config: DispatchConfig,
+    tow_truck_events: EventBus<TowTruckEventDto>,
+    order_events: EventBus<OrderEventDto>,
 }
 
 impl<
@@ -71,23 +102,85 @@ impl<
         tow_truck_repository: U,
         auth_repository: V,
         map_repository: W,
+        dispatch_config: DispatchConfig,
+        tow_truck_events: EventBus<TowTruckEventDto>,
+        order_events: EventBus<OrderEventDto>,
     ) -> Self {
         OrderService {
             order_repository,
             tow_truck_repository,
             auth_repository,
             map_repository,
+            dispatch_config,
+            tow_truck_events,
+            order_events,
         }
     }
 
-    pub async fn update_order_status(&self, order_id: i32, status: &str) -> Result<(), AppError> {
+    // admin 以外は自分に関係する依頼だけを扱える
+    async fn authorize_order(&self, principal: &Principal, order: &Order) -> Result<(), AppError> {
+        let allowed = match principal.role {
+            Role::Admin => true,
+            Role::Client => order.client_id == principal.user_id,
+            Role::Driver => {
+                order.tow_truck_id.is_some() && order.tow_truck_id == principal.tow_truck_id
+            }
+            Role::Dispatcher => {
+                let area_id = self
+                    .map_repository
+                    .get_area_id_by_node_id(order.node_id)
+                    .await?;
+                principal.can_access_area(area_id)
+            }
+        };
+
+        if allowed {
+            Ok(())
+        } else {
+            Err(AppError::Forbidden)
+        }
+    }
+
+    pub async fn update_order_status(
+        &self,
+        principal: &Principal,
+        order_id: i32,
+        status: &str,
+    ) -> Result<(), AppError> {
+        let order = self.order_repository.find_order_by_id(order_id).await?;
+        self.authorize_order(principal, &order).await?;
+
         self.order_repository
             .update_order_status(order_id, status)
-            .await
+            .await?;
+
+        if matches!(status, "completed" | "cancelled") {
+            let order = self.order_repository.find_order_by_id(order_id).await?;
+            self.order_events.publish(OrderEventDto {
+                event_type: status.to_string(),
+                order_id,
+                client_id: order.client_id,
+                status: order.status,
+                tow_truck_id: order.tow_truck_id,
+                driver_username: None,
+                sponsor: order.sponsor.clone(),
+            });
+        }
+
+        Ok(())
     }
 
-    pub async fn get_order_by_id(&self, id: i32) -> Result<OrderDto, AppError> {
+    pub fn subscribe_order_events(&self) -> broadcast::Receiver<OrderEventDto> {
+        self.order_events.subscribe()
+    }
+
+    pub async fn get_order_by_id(
+        &self,
+        principal: &Principal,
+        id: i32,
+    ) -> Result<OrderDto, AppError> {
         let order = self.order_repository.find_order_by_id(id).await?;
+        self.authorize_order(principal, &order).await?;
 
         let client_username_future = self.auth_repository.find_user_by_id(order.client_id);
 
@@ -153,6 +246,11 @@ impl<
             None => (None, None),
         };
 
+        let required_vehicle_class = order
+            .required_vehicle_class(self.dispatch_config.flatbed_car_value_threshold)
+            .as_str()
+            .to_string();
+
         Ok(OrderDto {
             id: order.id,
             client_id: order.client_id,
@@ -167,6 +265,7 @@ impl<
             status: order.status,
             node_id: order.node_id,
             car_value: order.car_value,
+            required_vehicle_class,
             order_time: order.order_time,
             completed_time: order.completed_time,
         })
@@ -212,16 +311,20 @@ impl<
 
     pub async fn get_paginated_orders(
         &self,
-        page: i32,
-        page_size: i32,
-        sort_by: Option<String>,
-        sort_order: Option<String>,
-        status: Option<String>,
-        area: Option<i32>,
+        principal: &Principal,
+        query: PaginatedOrderQueryDto,
     ) -> Result<Vec<OrderDto>, AppError> {
+        let area_ids = principal.scope_areas(query.area)?;
         let orders = self
             .order_repository
-            .get_paginated_orders(page, page_size, sort_by, sort_order, status, area)
+            .get_paginated_orders(
+                query.page.unwrap_or(0),
+                query.page_size.unwrap_or(10),
+                query.sort_by,
+                query.sort_order,
+                query.status,
+                area_ids.as_deref(),
+            )
             .await?;
 
         let username_map = self.get_username_map(&orders).await?;
@@ -276,6 +379,11 @@ impl<
                 None => (None, None),
             };
 
+            let required_vehicle_class = order
+                .required_vehicle_class(self.dispatch_config.flatbed_car_value_threshold)
+                .as_str()
+                .to_string();
+
             results.push(OrderDto {
                 id: order.id,
                 client_id: order.client_id,
@@ -290,6 +398,7 @@ impl<
                 status: order.status,
                 node_id: order.node_id,
                 car_value: order.car_value,
+                required_vehicle_class,
                 order_time: order.order_time,
                 completed_time: order.completed_time,
             });
@@ -303,10 +412,24 @@ impl<
         client_id: i32,
         node_id: i32,
         car_value: f64,
+        required_vehicle_class: Option<String>,
+        sponsor: Option<String>,
     ) -> Result<(), AppError> {
+        if let Some(class) = &required_vehicle_class {
+            class
+                .parse::<VehicleClass>()
+                .map_err(|_| AppError::BadRequest)?;
+        }
+
         match self
             .order_repository
-            .create_order(client_id, node_id, car_value)
+            .create_order(
+                client_id,
+                node_id,
+                car_value,
+                required_vehicle_class.as_deref(),
+                sponsor.as_deref(),
+            )
             .await
         {
             Ok(_) => Ok(()),
@@ -314,28 +437,333 @@ impl<
         }
     }
 
+    // 配車はまずドライバーへの打診として行い、承諾されたら確定する
     pub async fn create_dispatcher_order(
         &self,
+        principal: &Principal,
         order_id: i32,
-        dispatcher_id: i32,
         tow_truck_id: i32,
-        order_time: DateTime<Utc>,
-    ) -> Result<(), AppError> {
-        if (self
+    ) -> Result<OrderOfferDto, AppError> {
+        // 打診はディスパッチャーの名前で記録するので、ディスパッチャーを持たない admin は配車できない
+        let dispatcher_id = match principal.role {
+            Role::Dispatcher => principal.dispatcher_id.ok_or(AppError::Forbidden)?,
+            _ => return Err(AppError::Forbidden),
+        };
+        let order = self
             .order_repository
-            .create_completed_order(order_id, tow_truck_id, order_time)
-            .await)
-            .is_err()
+            .find_order_by_id(order_id)
+            .await
+            .map_err(|_| AppError::BadRequest)?;
+        self.authorize_order(principal, &order).await?;
+        if order.status != "pending" {
+            return Err(AppError::Conflict);
+        }
+
+        let vehicle_classes = order
+            .required_vehicle_class(self.dispatch_config.flatbed_car_value_threshold)
+            .compatible_classes();
+        if !self
+            .tow_truck_repository
+            .is_tow_truck_dispatchable(
+                tow_truck_id,
+                &vehicle_classes,
+                self.dispatch_config.out_of_area_tow_trucks_dispatchable,
+            )
+            .await?
         {
             return Err(AppError::BadRequest);
         }
 
-        tokio::try_join!(
-            self.order_repository
-                .update_order_dispatched(order_id, dispatcher_id, tow_truck_id),
+        self.offer_order(&order, dispatcher_id, tow_truck_id).await
+    }
+
+    async fn offer_order(
+        &self,
+        order: &Order,
+        dispatcher_id: i32,
+        tow_truck_id: i32,
+    ) -> Result<OrderOfferDto, AppError> {
+        // 読み込んだ後に依頼やレッカー車の状態が変わっていたら打診しない
+        let offer_id = self
+            .order_repository
+            .create_order_offer(
+                order.id,
+                tow_truck_id,
+                dispatcher_id,
+                self.dispatch_config.order_offer_timeout_secs,
+                &order.status,
+            )
+            .await?
+            .ok_or(AppError::Conflict)?;
+
+        let offer = self
+            .order_repository
+            .find_order_offer_by_id(offer_id)
+            .await?
+            .ok_or(AppError::InternalServerError)?;
+
+        let tow_truck = self
+            .tow_truck_repository
+            .find_tow_truck_by_id(tow_truck_id)
+            .await?;
+        self.order_events.publish(OrderEventDto {
+            event_type: "offered".to_string(),
+            order_id: order.id,
+            client_id: order.client_id,
+            status: "offered".to_string(),
+            tow_truck_id: Some(tow_truck_id),
+            driver_username: tow_truck
+                .as_ref()
+                .and_then(|tow_truck| tow_truck.driver_username.clone()),
+            sponsor: order.sponsor.clone(),
+        });
+        if let Some(tow_truck) = tow_truck {
+            self.tow_truck_events.publish(TowTruckEventDto {
+                event_type: "status".to_string(),
+                tow_truck: TowTruckDto::from_entity(tow_truck),
+            });
+        }
+
+        Ok(OrderOfferDto::from_entity(offer))
+    }
+
+    pub async fn get_order_offers(
+        &self,
+        principal: &Principal,
+        order_id: i32,
+    ) -> Result<Vec<OrderOfferDto>, AppError> {
+        let order = self.order_repository.find_order_by_id(order_id).await?;
+        self.authorize_order(principal, &order).await?;
+
+        let offers = self
+            .order_repository
+            .get_order_offers_by_order_id(order_id)
+            .await?;
+
+        Ok(offers.into_iter().map(OrderOfferDto::from_entity).collect())
+    }
+
+    pub async fn get_order_offer_inbox(
+        &self,
+        driver_id: i32,
+    ) -> Result<Vec<OrderOfferDto>, AppError> {
+        let tow_truck = self
+            .tow_truck_repository
+            .find_tow_truck_by_driver_id(driver_id)
+            .await?
+            .ok_or(AppError::NotFound)?;
+
+        let offers = self
+            .order_repository
+            .get_pending_order_offers_by_tow_truck_id(tow_truck.id)
+            .await?;
+
+        Ok(offers.into_iter().map(OrderOfferDto::from_entity).collect())
+    }
+
+    // 他のドライバー宛ての打診は存在しないものとして扱う
+    async fn find_driver_order_offer(
+        &self,
+        offer_id: i32,
+        driver_id: i32,
+    ) -> Result<OrderOffer, AppError> {
+        let offer = self
+            .order_repository
+            .find_order_offer_by_id(offer_id)
+            .await?
+            .ok_or(AppError::NotFound)?;
+
+        match self
+            .tow_truck_repository
+            .find_tow_truck_by_id(offer.tow_truck_id)
+            .await?
+        {
+            Some(tow_truck) if tow_truck.driver_id == driver_id => Ok(offer),
+            _ => Err(AppError::NotFound),
+        }
+    }
+
+    pub async fn accept_order_offer(&self, offer_id: i32, driver_id: i32) -> Result<(), AppError> {
+        let offer = self.find_driver_order_offer(offer_id, driver_id).await?;
+        if !self
+            .order_repository
+            .accept_order_offer(&offer, Utc::now())
+            .await?
+        {
+            return Err(AppError::Conflict);
+        }
+
+        let order = self
+            .order_repository
+            .find_order_by_id(offer.order_id)
+            .await?;
+
+        self.order_events.publish(OrderEventDto {
+            event_type: "dispatched".to_string(),
+            order_id: order.id,
+            client_id: order.client_id,
+            status: "dispatched".to_string(),
+            tow_truck_id: Some(offer.tow_truck_id),
+            driver_username: None,
+            sponsor: order.sponsor.clone(),
+        });
+
+        if let Some(tow_truck) = self
+            .tow_truck_repository
+            .find_tow_truck_by_id(offer.tow_truck_id)
+            .await?
+        {
+            self.order_events.publish(OrderEventDto {
+                event_type: "truck_assigned".to_string(),
+                order_id: order.id,
+                client_id: order.client_id,
+                status: "dispatched".to_string(),
+                tow_truck_id: Some(offer.tow_truck_id),
+                driver_username: tow_truck.driver_username.clone(),
+                sponsor: order.sponsor.clone(),
+            });
+            self.tow_truck_events.publish(TowTruckEventDto {
+                event_type: "status".to_string(),
+                tow_truck: TowTruckDto::from_entity(tow_truck),
+            });
+        }
+
+        Ok(())
+    }
+
+    pub async fn decline_order_offer(&self, offer_id: i32, driver_id: i32) -> Result<(), AppError> {
+        let offer = self.find_driver_order_offer(offer_id, driver_id).await?;
+        if !self
+            .order_repository
+            .close_order_offer(offer.id, "declined")
+            .await?
+        {
+            return Err(AppError::Conflict);
+        }
+
+        self.offer_to_next_tow_truck(offer, "offer_declined").await
+    }
+
+    // 期限切れの打診を expired にして次のレッカー車へ回し、処理した件数を返す
+    pub async fn expire_order_offers(&self) -> Result<u64, AppError> {
+        let mut count = 0;
+        for offer in self.order_repository.get_expired_order_offers().await? {
+            // 1 件の失敗で残りの打診を次の実行まで待たせない
+            let offer_id = offer.id;
+            let result = match self
+                .order_repository
+                .close_order_offer(offer.id, "expired")
+                .await
+            {
+                Ok(true) => self.offer_to_next_tow_truck(offer, "offer_expired").await,
+                Ok(false) => continue,
+                Err(err) => Err(err),
+            };
+            match result {
+                Ok(()) => count += 1,
+                Err(err) => log::error!("failed to expire order offer {}: {}", offer_id, err),
+            }
+        }
+
+        Ok(count)
+    }
+
+    // 断られた / 期限切れになったレッカー車を解放し、まだ打診していない次に近いレッカー車へ打診する
+    async fn offer_to_next_tow_truck(
+        &self,
+        offer: OrderOffer,
+        event_type: &str,
+    ) -> Result<(), AppError> {
+        if let Some(tow_truck) = self
+            .tow_truck_repository
+            .find_tow_truck_by_id(offer.tow_truck_id)
+            .await?
+            .filter(|tow_truck| tow_truck.status == "offered")
+        {
             self.tow_truck_repository
-                .update_status(tow_truck_id, "busy")
-        )?;
+                .update_status(tow_truck.id, "available")
+                .await?;
+            if let Some(tow_truck) = self
+                .tow_truck_repository
+                .find_tow_truck_by_id(tow_truck.id)
+                .await?
+            {
+                self.tow_truck_events.publish(TowTruckEventDto {
+                    event_type: "status".to_string(),
+                    tow_truck: TowTruckDto::from_entity(tow_truck),
+                });
+            }
+        }
+
+        let order = self
+            .order_repository
+            .find_order_by_id(offer.order_id)
+            .await?;
+        self.order_events.publish(OrderEventDto {
+            event_type: event_type.to_string(),
+            order_id: order.id,
+            client_id: order.client_id,
+            status: order.status.clone(),
+            tow_truck_id: Some(offer.tow_truck_id),
+            driver_username: None,
+            sponsor: order.sponsor.clone(),
+        });
+
+        // 打診中にキャンセルされた依頼は次へ回さない
+        if order.status != "offered" {
+            return Ok(());
+        }
+
+        let mut excluded_tow_truck_ids: Vec<i32> = self
+            .order_repository
+            .get_order_offers_by_order_id(order.id)
+            .await?
+            .iter()
+            .map(|offer| offer.tow_truck_id)
+            .collect();
+        loop {
+            let next_tow_truck = find_nearest_dispatchable_tow_truck(
+                &self.tow_truck_repository,
+                &self.map_repository,
+                &self.dispatch_config,
+                &order,
+                &excluded_tow_truck_ids,
+            )
+            .await?;
+            let Some(tow_truck) = next_tow_truck else {
+                break;
+            };
+
+            match self
+                .offer_order(&order, offer.dispatcher_id, tow_truck.id)
+                .await
+            {
+                Ok(_) => return Ok(()),
+                // 同時に他の依頼へ打診されたレッカー車は飛ばして次を探す
+                Err(AppError::Conflict) => {
+                    let current = self.order_repository.find_order_by_id(order.id).await?;
+                    if current.status != "offered" {
+                        return Ok(());
+                    }
+                    excluded_tow_truck_ids.push(tow_truck.id);
+                }
+                Err(err) => return Err(err),
+            }
+        }
+
+        // 打診できるレッカー車が残っていなければ配車待ちに戻す
+        self.order_repository
+            .update_order_status(order.id, "pending")
+            .await?;
+        self.order_events.publish(OrderEventDto {
+            event_type: "offers_exhausted".to_string(),
+            order_id: order.id,
+            client_id: order.client_id,
+            status: "pending".to_string(),
+            tow_truck_id: None,
+            driver_username: None,
+            sponsor: order.sponsor.clone(),
+        });
 
         Ok(())
     }
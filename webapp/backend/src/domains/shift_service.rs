@@ -0,0 +1,134 @@
+use super::dto::shift::{OnDutyDriverDto, ShiftDto};
+use super::tow_truck_service::TowTruckRepository;
+use crate::errors::AppError;
+use crate::models::shift::{OnDutyDriver, Shift};
+use crate::models::user::{Principal, Role};
+
+pub trait ShiftRepository {
+    async fn find_active_shift_by_driver_id(
+        &self,
+        driver_id: i32,
+    ) -> Result<Option<Shift>, AppError>;
+    async fn start_shift(&self, driver_id: i32, tow_truck_id: i32) -> Result<(), AppError>;
+    async fn end_shift(&self, shift_id: i32) -> Result<(), AppError>;
+    async fn get_paginated_shifts_by_driver_id(
+        &self,
+        driver_id: i32,
+        page: i32,
+        page_size: i32,
+    ) -> Result<Vec<Shift>, AppError>;
+    // area_ids が None ならすべてのエリア
+    async fn get_on_duty_drivers(
+        &self,
+        area_ids: Option<&[i32]>,
+    ) -> Result<Vec<OnDutyDriver>, AppError>;
+}
+
+#[derive(Debug)]
+pub struct ShiftService<
+    T: ShiftRepository + std::fmt::Debug,
+    U: TowTruckRepository + std::fmt::Debug,
+> {
+    shift_repository: T,
+    tow_truck_repository: U,
+}
+
+impl<T: ShiftRepository + std::fmt::Debug, U: TowTruckRepository + std::fmt::Debug>
+    ShiftService<T, U>
+{
+    pub fn new(shift_repository: T, tow_truck_repository: U) -> Self {
+        ShiftService {
+            shift_repository,
+            tow_truck_repository,
+        }
+    }
+
+    pub async fn start_shift(&self, driver_id: i32) -> Result<ShiftDto, AppError> {
+        // レッカー車に紐づいていないユーザーはドライバーではない
+        let tow_truck = self
+            .tow_truck_repository
+            .find_tow_truck_by_driver_id(driver_id)
+            .await?
+            .ok_or(AppError::NotFound)?;
+
+        if self
+            .shift_repository
+            .find_active_shift_by_driver_id(driver_id)
+            .await?
+            .is_some()
+        {
+            return Err(AppError::Conflict);
+        }
+
+        self.shift_repository
+            .start_shift(driver_id, tow_truck.id)
+            .await?;
+
+        let shift = self
+            .shift_repository
+            .find_active_shift_by_driver_id(driver_id)
+            .await?
+            .ok_or(AppError::InternalServerError)?;
+
+        Ok(ShiftDto::from_entity(shift))
+    }
+
+    pub async fn end_shift(&self, driver_id: i32) -> Result<(), AppError> {
+        let shift = self
+            .shift_repository
+            .find_active_shift_by_driver_id(driver_id)
+            .await?
+            .ok_or(AppError::Conflict)?;
+
+        self.shift_repository.end_shift(shift.id).await
+    }
+
+    // ドライバーは自分の履歴、ディスパッチャーは担当エリアのレッカー車のドライバーの履歴だけを見られる
+    pub async fn get_shift_history(
+        &self,
+        principal: &Principal,
+        driver_id: Option<i32>,
+        page: i32,
+        page_size: i32,
+    ) -> Result<Vec<ShiftDto>, AppError> {
+        let driver_id = match principal.role {
+            Role::Driver => principal.user_id,
+            Role::Client | Role::Dispatcher | Role::Admin => {
+                let driver_id = driver_id.ok_or(AppError::BadRequest)?;
+                let tow_truck = self
+                    .tow_truck_repository
+                    .find_tow_truck_by_driver_id(driver_id)
+                    .await?
+                    .ok_or(AppError::NotFound)?;
+                if !principal.can_access_area(tow_truck.area_id) {
+                    return Err(AppError::Forbidden);
+                }
+                driver_id
+            }
+        };
+
+        let shifts = self
+            .shift_repository
+            .get_paginated_shifts_by_driver_id(driver_id, page, page_size)
+            .await?;
+
+        Ok(shifts.into_iter().map(ShiftDto::from_entity).collect())
+    }
+
+    pub async fn get_on_duty_drivers(
+        &self,
+        principal: &Principal,
+        area: Option<i32>,
+    ) -> Result<Vec<OnDutyDriverDto>, AppError> {
+        let area_ids = principal.scope_areas(area)?;
+        let drivers = self
+            .shift_repository
+            .get_on_duty_drivers(area_ids.as_deref())
+            .await?;
+
+        Ok(drivers
+            .into_iter()
+            .map(OnDutyDriverDto::from_entity)
+            .collect())
+    }
+}
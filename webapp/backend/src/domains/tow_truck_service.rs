@@ -1,11 +1,21 @@
+use std::collections::HashSet;
+use std::sync::Mutex;
+
 use rayon::prelude::*;
+use tokio::sync::broadcast;
 
-use super::dto::tow_truck::TowTruckDto;
+use super::auth_service::AuthRepository;
+use super::dto::tow_truck::{GeofenceEventDto, StaleTowTruckDto, TowTruckDto, TowTruckEventDto};
 use super::map_service::MapRepository;
 use super::order_service::OrderRepository;
+use crate::config::DispatchConfig;
 use crate::errors::AppError;
+use crate::infrastructure::event_bus::EventBus;
+use crate::models::geofence::GeofenceEvent;
 use crate::models::graph::Graph;
-use crate::models::tow_truck::TowTruck;
+use crate::models::order::Order;
+use crate::models::tow_truck::{StaleTowTruck, TowTruck, VehicleClass};
+use crate::models::user::{Principal, Role};
 
 pub trait TowTruckRepository {
     async fn get_paginated_tow_trucks(
@@ -13,11 +23,59 @@ pub trait TowTruckRepository {
         page: i32,
         page_size: i32,
         status: Option<String>,
-        area_id: Option<i32>,
+        area_ids: Option<&[i32]>,
     ) -> Result<Vec<TowTruck>, AppError>;
     async fn update_location(&self, truck_id: i32, node_id: i32) -> Result<(), AppError>;
+    async fn record_location(&self, truck_id: i32, node_id: i32) -> Result<TowTruck, AppError>;
     async fn update_status(&self, truck_id: i32, status: &str) -> Result<(), AppError>;
     async fn find_tow_truck_by_id(&self, id: i32) -> Result<Option<TowTruck>, AppError>;
+    async fn find_tow_truck_by_driver_id(
+        &self,
+        driver_id: i32,
+    ) -> Result<Option<TowTruck>, AppError>;
+    async fn get_dispatchable_tow_trucks(
+        &self,
+        area_id: i32,
+        vehicle_classes: &[VehicleClass],
+        include_out_of_area: bool,
+    ) -> Result<Vec<TowTruck>, AppError>;
+    async fn is_tow_truck_dispatchable(
+        &self,
+        truck_id: i32,
+        vehicle_classes: &[VehicleClass],
+        include_out_of_area: bool,
+    ) -> Result<bool, AppError>;
+    async fn mark_stale_tow_trucks_offline(
+        &self,
+        truck_ids: &[i32],
+        threshold_secs: i64,
+    ) -> Result<u64, AppError>;
+    async fn get_stale_tow_trucks(
+        &self,
+        threshold_secs: i64,
+        area_ids: Option<&[i32]>,
+    ) -> Result<Vec<StaleTowTruck>, AppError>;
+    async fn create_tow_truck(
+        &self,
+        driver_id: i32,
+        area_id: i32,
+        node_id: i32,
+        vehicle_class: VehicleClass,
+    ) -> Result<i32, AppError>;
+    async fn update_tow_truck(
+        &self,
+        truck_id: i32,
+        driver_id: i32,
+        area_id: i32,
+        vehicle_class: VehicleClass,
+    ) -> Result<(), AppError>;
+    async fn retire_tow_truck(&self, truck_id: i32) -> Result<(), AppError>;
+    async fn get_paginated_geofence_events(
+        &self,
+        area_id: i32,
+        page: i32,
+        page_size: i32,
+    ) -> Result<Vec<GeofenceEvent>, AppError>;
 }
 
 #[derive(Debug)]
@@ -25,41 +83,79 @@ pub struct TowTruckService<
     T: TowTruckRepository + std::fmt::Debug,
     U: OrderRepository + std::fmt::Debug,
     V: MapRepository + std::fmt::Debug,
+    W: AuthRepository + std::fmt::Debug,
 > {
     tow_truck_repository: T,
     order_repository: U,
     map_repository: V,
+    auth_repository: W,
+    stale_threshold_secs: i64,
+    dispatch_config: DispatchConfig,
+    tow_truck_events: EventBus<TowTruckEventDto>,
+    // このプロセスの起動後に位置情報を送ってきたレッカー車
+    // 初期データの位置情報は古いので、一度も報告のないレッカー車は offline にしない
+    reported_tow_truck_ids: Mutex<HashSet<i32>>,
 }
 
 impl<
         T: TowTruckRepository + std::fmt::Debug,
         U: OrderRepository + std::fmt::Debug,
         V: MapRepository + std::fmt::Debug,
-    > TowTruckService<T, U, V>
+        W: AuthRepository + std::fmt::Debug,
+    > TowTruckService<T, U, V, W>
 {
-    pub fn new(tow_truck_repository: T, order_repository: U, map_repository: V) -> Self {
+    pub fn new(
+        tow_truck_repository: T,
+        order_repository: U,
+        map_repository: V,
+        auth_repository: W,
+        stale_threshold_secs: i64,
+        dispatch_config: DispatchConfig,
+        tow_truck_events: EventBus<TowTruckEventDto>,
+    ) -> Self {
         TowTruckService {
             tow_truck_repository,
             order_repository,
             map_repository,
+            auth_repository,
+            stale_threshold_secs,
+            dispatch_config,
+            tow_truck_events,
+            reported_tow_truck_ids: Mutex::new(HashSet::new()),
         }
     }
 
-    pub async fn get_tow_truck_by_id(&self, id: i32) -> Result<Option<TowTruckDto>, AppError> {
+    pub async fn get_tow_truck_by_id(
+        &self,
+        principal: &Principal,
+        id: i32,
+    ) -> Result<Option<TowTruckDto>, AppError> {
         let tow_truck = self.tow_truck_repository.find_tow_truck_by_id(id).await?;
+
+        // ドライバーは自分のレッカー車、ディスパッチャーは担当エリアのレッカー車だけを見られる
+        // 見られないレッカー車は存在を明かさないよう、ないものとして扱う
+        let tow_truck = tow_truck.filter(|tow_truck| match principal.role {
+            Role::Driver => principal.tow_truck_id == Some(tow_truck.id),
+            Role::Client | Role::Dispatcher | Role::Admin => {
+                principal.can_access_area(tow_truck.area_id)
+            }
+        });
+
         Ok(tow_truck.map(TowTruckDto::from_entity))
     }
 
     pub async fn get_all_tow_trucks(
         &self,
+        principal: &Principal,
         page: i32,
         page_size: i32,
         status: Option<String>,
         area: Option<i32>,
     ) -> Result<Vec<TowTruckDto>, AppError> {
+        let area_ids = principal.scope_areas(area)?;
         let tow_trucks = self
             .tow_truck_repository
-            .get_paginated_tow_trucks(page, page_size, status, area)
+            .get_paginated_tow_trucks(page, page_size, status, area_ids.as_deref())
             .await?;
         let tow_truck_dtos = tow_trucks
             .into_iter()
@@ -70,15 +166,224 @@ impl<
     }
 
     pub async fn update_location(&self, truck_id: i32, node_id: i32) -> Result<(), AppError> {
-        self.tow_truck_repository
-            .update_location(truck_id, node_id)
+        let tow_truck = self
+            .tow_truck_repository
+            .record_location(truck_id, node_id)
             .await?;
+        self.reported_tow_truck_ids
+            .lock()
+            .map_err(|_| AppError::InternalServerError)?
+            .insert(truck_id);
+
+        self.tow_truck_events.publish(TowTruckEventDto {
+            event_type: "location".to_string(),
+            tow_truck: TowTruckDto::from_entity(tow_truck),
+        });
 
         Ok(())
     }
 
+    // 取りこぼしがないよう、スナップショットを取る前に購読を始める
+    pub async fn subscribe_tow_trucks(
+        &self,
+        principal: &Principal,
+        area: i32,
+    ) -> Result<(Vec<TowTruckDto>, broadcast::Receiver<TowTruckEventDto>), AppError> {
+        if !principal.can_access_area(area) {
+            return Err(AppError::Forbidden);
+        }
+
+        let receiver = self.tow_truck_events.subscribe();
+        let snapshot = self
+            .get_all_tow_trucks(principal, 0, -1, None, Some(area))
+            .await?;
+
+        Ok((snapshot, receiver))
+    }
+
+    pub async fn get_geofence_events(
+        &self,
+        principal: &Principal,
+        area: i32,
+        page: i32,
+        page_size: i32,
+    ) -> Result<Vec<GeofenceEventDto>, AppError> {
+        if !principal.can_access_area(area) {
+            return Err(AppError::Forbidden);
+        }
+
+        let events = self
+            .tow_truck_repository
+            .get_paginated_geofence_events(area, page, page_size)
+            .await?;
+
+        Ok(events
+            .into_iter()
+            .map(GeofenceEventDto::from_entity)
+            .collect())
+    }
+
+    pub async fn mark_stale_tow_trucks_offline(&self) -> Result<u64, AppError> {
+        let truck_ids: Vec<i32> = self
+            .reported_tow_truck_ids
+            .lock()
+            .map_err(|_| AppError::InternalServerError)?
+            .iter()
+            .copied()
+            .collect();
+        if truck_ids.is_empty() {
+            return Ok(0);
+        }
+
+        self.tow_truck_repository
+            .mark_stale_tow_trucks_offline(&truck_ids, self.stale_threshold_secs)
+            .await
+    }
+
+    pub async fn get_stale_tow_trucks(
+        &self,
+        principal: &Principal,
+        area: Option<i32>,
+    ) -> Result<Vec<StaleTowTruckDto>, AppError> {
+        let area_ids = principal.scope_areas(area)?;
+        let tow_trucks = self
+            .tow_truck_repository
+            .get_stale_tow_trucks(self.stale_threshold_secs, area_ids.as_deref())
+            .await?;
+
+        Ok(tow_trucks
+            .into_iter()
+            .map(StaleTowTruckDto::from_entity)
+            .collect())
+    }
+
+    // driver ロールで、他の現役レッカー車に紐づいていないユーザーのみ割り当てられる
+    async fn validate_driver(
+        &self,
+        driver_id: i32,
+        tow_truck_id: Option<i32>,
+    ) -> Result<(), AppError> {
+        let user = self
+            .auth_repository
+            .find_user_by_id(driver_id)
+            .await?
+            .ok_or(AppError::BadRequest)?;
+        if user.role != "driver" {
+            return Err(AppError::BadRequest);
+        }
+
+        match self
+            .tow_truck_repository
+            .find_tow_truck_by_driver_id(driver_id)
+            .await?
+        {
+            Some(tow_truck) if Some(tow_truck.id) != tow_truck_id => Err(AppError::Conflict),
+            _ => Ok(()),
+        }
+    }
+
+    async fn validate_node_in_area(&self, node_id: i32, area_id: i32) -> Result<(), AppError> {
+        match self.map_repository.get_area_id_by_node_id(node_id).await {
+            Ok(node_area_id) if node_area_id == area_id => Ok(()),
+            Ok(_) | Err(sqlx::Error::RowNotFound) => Err(AppError::BadRequest),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    pub async fn create_tow_truck(
+        &self,
+        driver_id: i32,
+        area_id: i32,
+        node_id: i32,
+        vehicle_class: Option<String>,
+    ) -> Result<TowTruckDto, AppError> {
+        let vehicle_class = match vehicle_class {
+            Some(vehicle_class) => vehicle_class.parse().map_err(|_| AppError::BadRequest)?,
+            None => VehicleClass::WheelLift,
+        };
+        self.validate_driver(driver_id, None).await?;
+        self.validate_node_in_area(node_id, area_id).await?;
+
+        let truck_id = self
+            .tow_truck_repository
+            .create_tow_truck(driver_id, area_id, node_id, vehicle_class)
+            .await?;
+
+        self.tow_truck_repository
+            .find_tow_truck_by_id(truck_id)
+            .await?
+            .map(TowTruckDto::from_entity)
+            .ok_or(AppError::InternalServerError)
+    }
+
+    pub async fn update_tow_truck(
+        &self,
+        truck_id: i32,
+        driver_id: Option<i32>,
+        area_id: Option<i32>,
+        node_id: Option<i32>,
+        vehicle_class: Option<String>,
+    ) -> Result<TowTruckDto, AppError> {
+        let tow_truck = self
+            .tow_truck_repository
+            .find_tow_truck_by_id(truck_id)
+            .await?
+            .filter(|tow_truck| tow_truck.status != "retired")
+            .ok_or(AppError::NotFound)?;
+
+        let driver_id = driver_id.unwrap_or(tow_truck.driver_id);
+        if driver_id != tow_truck.driver_id {
+            self.validate_driver(driver_id, Some(truck_id)).await?;
+        }
+
+        let vehicle_class = vehicle_class
+            .as_deref()
+            .unwrap_or(&tow_truck.vehicle_class)
+            .parse()
+            .map_err(|_| AppError::BadRequest)?;
+
+        // エリアを移す場合は、移動先エリアのノードも指定する必要がある
+        let area_id = area_id.unwrap_or(tow_truck.area_id);
+        match node_id {
+            Some(node_id) => self.validate_node_in_area(node_id, area_id).await?,
+            None if area_id != tow_truck.area_id => return Err(AppError::BadRequest),
+            None => {}
+        }
+
+        self.tow_truck_repository
+            .update_tow_truck(truck_id, driver_id, area_id, vehicle_class)
+            .await?;
+        if let Some(node_id) = node_id {
+            self.tow_truck_repository
+                .update_location(truck_id, node_id)
+                .await?;
+        }
+
+        self.tow_truck_repository
+            .find_tow_truck_by_id(truck_id)
+            .await?
+            .map(TowTruckDto::from_entity)
+            .ok_or(AppError::InternalServerError)
+    }
+
+    pub async fn retire_tow_truck(&self, truck_id: i32) -> Result<(), AppError> {
+        let tow_truck = self
+            .tow_truck_repository
+            .find_tow_truck_by_id(truck_id)
+            .await?
+            .filter(|tow_truck| tow_truck.status != "retired")
+            .ok_or(AppError::NotFound)?;
+
+        if matches!(tow_truck.status.as_str(), "busy" | "offered") {
+            return Err(AppError::Conflict);
+        }
+
+        self.tow_truck_repository.retire_tow_truck(truck_id).await
+    }
+
     pub async fn get_nearest_available_tow_trucks(
         &self,
+        principal: &Principal,
         order_id: i32,
     ) -> Result<Option<TowTruckDto>, AppError> {
         let order = self.order_repository.find_order_by_id(order_id).await?;
@@ -86,40 +391,75 @@ impl<
             .map_repository
             .get_area_id_by_node_id(order.node_id)
             .await?;
-        let tow_trucks = self
-            .tow_truck_repository
-            .get_paginated_tow_trucks(0, -1, Some("available".to_string()), Some(area_id))
-            .await?;
+        if !principal.can_access_area(area_id) {
+            return Err(AppError::Forbidden);
+        }
 
-        let (nodes, edges) = tokio::try_join!(
-            self.map_repository.get_all_nodes(Some(area_id)),
-            self.map_repository.get_all_edges(Some(area_id))
-        )?;
+        let tow_truck = find_nearest_dispatchable_tow_truck(
+            &self.tow_truck_repository,
+            &self.map_repository,
+            &self.dispatch_config,
+            &order,
+            &[],
+        )
+        .await?;
 
-        let mut graph = Graph::new();
-        for node in nodes {
-            graph.add_node(node);
-        }
-        for edge in edges {
-            graph.add_edge(edge);
-        }
+        Ok(tow_truck.map(TowTruckDto::from_entity))
+    }
+}
 
-        let truck_node_ids: Vec<i32> = tow_trucks.iter().map(|truck| truck.node_id).collect();
-        if let Some(id) = graph
-            .find_closest_node(order.node_id, truck_node_ids, 10000000)
-            .await
-        {
-            // NOTE: 検索処理が負荷になるかも
-            let truck = tow_trucks
-                .par_iter()
-                .find_any(|truck| truck.node_id == id)
-                .cloned()
-                .unwrap();
-
-            let tow_truck_dto = TowTruckDto::from_entity(truck);
-            Ok(Some(tow_truck_dto))
-        } else {
-            Ok(None)
-        }
+// 依頼地点から最も近い配車可能なレッカー車 (excluded_tow_truck_ids は候補から外す)
+pub async fn find_nearest_dispatchable_tow_truck<
+    U: TowTruckRepository + std::fmt::Debug,
+    W: MapRepository + std::fmt::Debug,
+>(
+    tow_truck_repository: &U,
+    map_repository: &W,
+    dispatch_config: &DispatchConfig,
+    order: &Order,
+    excluded_tow_truck_ids: &[i32],
+) -> Result<Option<TowTruck>, AppError> {
+    let area_id = map_repository.get_area_id_by_node_id(order.node_id).await?;
+    let tow_trucks: Vec<TowTruck> = tow_truck_repository
+        .get_dispatchable_tow_trucks(
+            area_id,
+            &order
+                .required_vehicle_class(dispatch_config.flatbed_car_value_threshold)
+                .compatible_classes(),
+            dispatch_config.out_of_area_tow_trucks_dispatchable,
+        )
+        .await?
+        .into_iter()
+        .filter(|truck| !excluded_tow_truck_ids.contains(&truck.id))
+        .collect();
+
+    let (nodes, edges) = tokio::try_join!(
+        map_repository.get_all_nodes(Some(area_id)),
+        map_repository.get_all_edges(Some(area_id))
+    )?;
+
+    let mut graph = Graph::new();
+    for node in nodes {
+        graph.add_node(node);
+    }
+    for edge in edges {
+        graph.add_edge(edge);
+    }
+
+    let truck_node_ids: Vec<i32> = tow_trucks.iter().map(|truck| truck.node_id).collect();
+    if let Some(id) = graph
+        .find_closest_node(order.node_id, truck_node_ids, 10000000)
+        .await
+    {
+        // NOTE: 検索処理が負荷になるかも
+        let truck = tow_trucks
+            .par_iter()
+            .find_any(|truck| truck.node_id == id)
+            .cloned()
+            .unwrap();
+
+        Ok(Some(truck))
+    } else {
+        Ok(None)
     }
 }
@@ -0,0 +1,662 @@
+use chrono::Utc;
+use futures_util::{stream, StreamExt};
+use serde::Serialize;
+use url::{Host, Url};
+
+use super::dto::webhook::{RegisteredWebhookDto, WebhookDeliveryDto, WebhookSubscriptionDto};
+use crate::config::WebhookConfig;
+use crate::errors::AppError;
+use crate::models::webhook::{WebhookDelivery, WebhookSubscription};
+use crate::utils::{generate_secret, hmac_sha256_hex, is_public_ip};
+
+// 購読できるイベント種別
+pub const WEBHOOK_EVENT_TYPES: [&str; 10] = [
+    "order.offered",
+    "order.offer_declined",
+    "order.offer_expired",
+    "order.offers_exhausted",
+    "order.dispatched",
+    "order.truck_assigned",
+    "order.completed",
+    "order.cancelled",
+    "tow_truck.location",
+    "tow_truck.status",
+];
+
+pub trait WebhookRepository {
+    async fn create_subscription(
+        &self,
+        url: &str,
+        secret: &str,
+        event_types: &str,
+        sponsor: Option<&str>,
+    ) -> Result<i32, AppError>;
+    async fn find_subscription_by_id(
+        &self,
+        id: i32,
+    ) -> Result<Option<WebhookSubscription>, AppError>;
+    async fn get_active_subscriptions(&self) -> Result<Vec<WebhookSubscription>, AppError>;
+    async fn get_active_subscriptions_for_event(
+        &self,
+        event_type: &str,
+        sponsor: Option<&str>,
+    ) -> Result<Vec<WebhookSubscription>, AppError>;
+    async fn deactivate_subscription(&self, id: i32) -> Result<(), AppError>;
+    async fn create_delivery(
+        &self,
+        subscription_id: i32,
+        event_type: &str,
+        payload: &str,
+    ) -> Result<i32, AppError>;
+    async fn find_delivery_by_id(&self, id: i32) -> Result<Option<WebhookDelivery>, AppError>;
+    async fn get_due_deliveries(&self, limit: i32) -> Result<Vec<WebhookDelivery>, AppError>;
+    async fn get_paginated_deliveries(
+        &self,
+        subscription_id: i32,
+        page: i32,
+        page_size: i32,
+    ) -> Result<Vec<WebhookDelivery>, AppError>;
+    async fn mark_delivery_succeeded(&self, id: i32, response_status: i32) -> Result<(), AppError>;
+    // retry_after_secs が None の場合は failed として再送しない
+    async fn mark_delivery_attempt_failed(
+        &self,
+        id: i32,
+        response_status: Option<i32>,
+        error: &str,
+        retry_after_secs: Option<i64>,
+    ) -> Result<(), AppError>;
+}
+
+// 署名済みのリクエストを送り、レスポンスのステータスコードを返す
+pub trait WebhookSender {
+    async fn send(&self, url: &str, headers: &[(&str, &str)], body: &[u8]) -> Result<u16, String>;
+}
+
+#[derive(Serialize, Debug)]
+struct WebhookPayload<'a, D: Serialize> {
+    event_type: &'a str,
+    occurred_at: String,
+    data: D,
+}
+
+#[derive(Debug)]
+pub struct WebhookService<
+    T: WebhookRepository + std::fmt::Debug,
+    U: WebhookSender + std::fmt::Debug,
+> {
+    webhook_repository: T,
+    sender: U,
+    config: WebhookConfig,
+}
+
+impl<T: WebhookRepository + std::fmt::Debug, U: WebhookSender + std::fmt::Debug>
+    WebhookService<T, U>
+{
+    pub fn new(webhook_repository: T, sender: U, config: WebhookConfig) -> Self {
+        WebhookService {
+            webhook_repository,
+            sender,
+            config,
+        }
+    }
+
+    pub async fn register(
+        &self,
+        url: &str,
+        event_types: &[String],
+        sponsor: Option<&str>,
+    ) -> Result<RegisteredWebhookDto, AppError> {
+        validate_webhook_url(url).await?;
+        if event_types.is_empty()
+            || event_types
+                .iter()
+                .any(|event_type| !WEBHOOK_EVENT_TYPES.contains(&event_type.as_str()))
+        {
+            return Err(AppError::BadRequest);
+        }
+        // レッカー車のイベントはどの sponsor にも属さないので、運営側の登録先しか受け取れない
+        if sponsor.is_some()
+            && event_types
+                .iter()
+                .any(|event_type| !event_type.starts_with("order."))
+        {
+            return Err(AppError::BadRequest);
+        }
+
+        let secret = generate_secret();
+        let id = self
+            .webhook_repository
+            .create_subscription(url, &secret, &event_types.join(","), sponsor)
+            .await?;
+
+        let subscription = self
+            .webhook_repository
+            .find_subscription_by_id(id)
+            .await?
+            .ok_or(AppError::InternalServerError)?;
+
+        Ok(RegisteredWebhookDto {
+            subscription: WebhookSubscriptionDto::from_entity(subscription),
+            secret,
+        })
+    }
+
+    pub async fn get_subscriptions(&self) -> Result<Vec<WebhookSubscriptionDto>, AppError> {
+        let subscriptions = self.webhook_repository.get_active_subscriptions().await?;
+
+        Ok(subscriptions
+            .into_iter()
+            .map(WebhookSubscriptionDto::from_entity)
+            .collect())
+    }
+
+    pub async fn deactivate(&self, id: i32) -> Result<(), AppError> {
+        match self.webhook_repository.find_subscription_by_id(id).await? {
+            Some(subscription) if subscription.is_active => {
+                self.webhook_repository.deactivate_subscription(id).await
+            }
+            _ => Err(AppError::NotFound),
+        }
+    }
+
+    pub async fn get_deliveries(
+        &self,
+        subscription_id: i32,
+        page: i32,
+        page_size: i32,
+    ) -> Result<Vec<WebhookDeliveryDto>, AppError> {
+        if self
+            .webhook_repository
+            .find_subscription_by_id(subscription_id)
+            .await?
+            .is_none()
+        {
+            return Err(AppError::NotFound);
+        }
+
+        let deliveries = self
+            .webhook_repository
+            .get_paginated_deliveries(subscription_id, page, page_size)
+            .await?;
+
+        Ok(deliveries
+            .into_iter()
+            .map(WebhookDeliveryDto::from_entity)
+            .collect())
+    }
+
+    // イベントを購読している登録先のうち、そのイベントを受け取れるものごとに配信を積む
+    pub async fn enqueue<D: Serialize>(
+        &self,
+        event_type: &str,
+        sponsor: Option<&str>,
+        data: D,
+    ) -> Result<(), AppError> {
+        let subscriptions = self
+            .webhook_repository
+            .get_active_subscriptions_for_event(event_type, sponsor)
+            .await?;
+        if subscriptions.is_empty() {
+            return Ok(());
+        }
+
+        let payload = serde_json::to_string(&WebhookPayload {
+            event_type,
+            occurred_at: Utc::now().to_rfc3339(),
+            data,
+        })
+        .map_err(|_| AppError::InternalServerError)?;
+
+        for subscription in subscriptions {
+            self.webhook_repository
+                .create_delivery(subscription.id, event_type, &payload)
+                .await?;
+        }
+
+        Ok(())
+    }
+
+    // 同じ payload で新しい配信として送り直す
+    pub async fn replay(&self, delivery_id: i32) -> Result<WebhookDeliveryDto, AppError> {
+        let delivery = self
+            .webhook_repository
+            .find_delivery_by_id(delivery_id)
+            .await?
+            .ok_or(AppError::NotFound)?;
+
+        let id = self
+            .webhook_repository
+            .create_delivery(
+                delivery.subscription_id,
+                &delivery.event_type,
+                &delivery.payload,
+            )
+            .await?;
+
+        let replayed = self
+            .webhook_repository
+            .find_delivery_by_id(id)
+            .await?
+            .ok_or(AppError::InternalServerError)?;
+
+        Ok(WebhookDeliveryDto::from_entity(replayed))
+    }
+
+    // 送信時刻を過ぎた配信を送り、送信した件数を返す
+    // 1 件の失敗で残りの配信を止めないよう、失敗はログに残して次へ進む
+    pub async fn deliver_due(&self, limit: i32) -> Result<usize, AppError> {
+        let deliveries = self.webhook_repository.get_due_deliveries(limit).await?;
+        let count = deliveries.len();
+
+        stream::iter(deliveries)
+            .for_each_concurrent(self.config.delivery_concurrency, |delivery| async move {
+                let delivery_id = delivery.id;
+                if let Err(err) = self.deliver(delivery).await {
+                    log::error!("failed to deliver webhook {}: {}", delivery_id, err);
+                }
+            })
+            .await;
+
+        Ok(count)
+    }
+
+    async fn deliver(&self, delivery: WebhookDelivery) -> Result<(), AppError> {
+        let subscription = match self
+            .webhook_repository
+            .find_subscription_by_id(delivery.subscription_id)
+            .await?
+        {
+            Some(subscription) if subscription.is_active => subscription,
+            _ => {
+                return self
+                    .webhook_repository
+                    .mark_delivery_attempt_failed(
+                        delivery.id,
+                        None,
+                        "subscription is inactive",
+                        None,
+                    )
+                    .await;
+            }
+        };
+
+        let signature = format!(
+            "sha256={}",
+            hmac_sha256_hex(subscription.secret.as_bytes(), delivery.payload.as_bytes())
+        );
+        let delivery_id = delivery.id.to_string();
+        let headers = [
+            ("Content-Type", "application/json"),
+            ("X-Webhook-Event", delivery.event_type.as_str()),
+            ("X-Webhook-Delivery", delivery_id.as_str()),
+            ("X-Webhook-Signature", signature.as_str()),
+        ];
+
+        let (response_status, error) = match self
+            .sender
+            .send(&subscription.url, &headers, delivery.payload.as_bytes())
+            .await
+        {
+            Ok(status) if (200..300).contains(&status) => {
+                return self
+                    .webhook_repository
+                    .mark_delivery_succeeded(delivery.id, status as i32)
+                    .await;
+            }
+            Ok(status) => (Some(status as i32), format!("unexpected status {}", status)),
+            Err(err) => (None, err),
+        };
+
+        let attempts = delivery.attempts + 1;
+        let retry_after_secs = if attempts >= self.config.max_attempts {
+            None
+        } else {
+            Some(self.config.retry_base_secs << (attempts - 1).min(20))
+        };
+
+        self.webhook_repository
+            .mark_delivery_attempt_failed(delivery.id, response_status, &error, retry_after_secs)
+            .await
+    }
+}
+
+// 登録先は https で、外部から到達できるホストに限る (内部のサーバーへの送信を防ぐ)
+async fn validate_webhook_url(url: &str) -> Result<(), AppError> {
+    let url = Url::parse(url).map_err(|_| AppError::BadRequest)?;
+    if url.scheme() != "https" {
+        return Err(AppError::BadRequest);
+    }
+
+    let is_public = match url.host().ok_or(AppError::BadRequest)? {
+        Host::Ipv4(ip) => is_public_ip(ip.into()),
+        Host::Ipv6(ip) => is_public_ip(ip.into()),
+        Host::Domain(domain) => {
+            let port = url.port_or_known_default().ok_or(AppError::BadRequest)?;
+            let addrs: Vec<_> = tokio::net::lookup_host((domain, port))
+                .await
+                .map_err(|_| AppError::BadRequest)?
+                .collect();
+            !addrs.is_empty() && addrs.iter().all(|addr| is_public_ip(addr.ip()))
+        }
+    };
+    if !is_public {
+        return Err(AppError::BadRequest);
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::{SocketAddr, TcpListener};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Mutex;
+    use std::time::Duration;
+
+    use actix_web::{rt, web, App, HttpRequest, HttpResponse, HttpServer};
+    use chrono::Utc;
+
+    use super::{WebhookRepository, WebhookService};
+    use crate::config::WebhookConfig;
+    use crate::errors::AppError;
+    use crate::infrastructure::http_client::HttpClient;
+    use crate::models::webhook::{WebhookDelivery, WebhookSubscription};
+    use crate::utils::hmac_sha256_hex;
+
+    #[derive(Debug, Default)]
+    struct InMemoryWebhookRepository {
+        subscriptions: Vec<WebhookSubscription>,
+        deliveries: Mutex<Vec<WebhookDelivery>>,
+    }
+
+    impl InMemoryWebhookRepository {
+        fn delivery(&self, id: i32) -> WebhookDelivery {
+            self.deliveries.lock().unwrap()[id as usize - 1].clone()
+        }
+    }
+
+    impl WebhookRepository for InMemoryWebhookRepository {
+        async fn create_subscription(
+            &self,
+            _url: &str,
+            _secret: &str,
+            _event_types: &str,
+            _sponsor: Option<&str>,
+        ) -> Result<i32, AppError> {
+            unimplemented!()
+        }
+
+        async fn find_subscription_by_id(
+            &self,
+            id: i32,
+        ) -> Result<Option<WebhookSubscription>, AppError> {
+            Ok(self.subscriptions.iter().find(|s| s.id == id).cloned())
+        }
+
+        async fn get_active_subscriptions(&self) -> Result<Vec<WebhookSubscription>, AppError> {
+            unimplemented!()
+        }
+
+        async fn get_active_subscriptions_for_event(
+            &self,
+            _event_type: &str,
+            _sponsor: Option<&str>,
+        ) -> Result<Vec<WebhookSubscription>, AppError> {
+            unimplemented!()
+        }
+
+        async fn deactivate_subscription(&self, _id: i32) -> Result<(), AppError> {
+            unimplemented!()
+        }
+
+        async fn create_delivery(
+            &self,
+            subscription_id: i32,
+            event_type: &str,
+            payload: &str,
+        ) -> Result<i32, AppError> {
+            let mut deliveries = self.deliveries.lock().unwrap();
+            let id = deliveries.len() as i32 + 1;
+            deliveries.push(WebhookDelivery {
+                id,
+                subscription_id,
+                event_type: event_type.to_string(),
+                payload: payload.to_string(),
+                status: "pending".to_string(),
+                attempts: 0,
+                response_status: None,
+                last_error: None,
+                next_attempt_at: Utc::now(),
+                created_at: Utc::now(),
+                delivered_at: None,
+            });
+            Ok(id)
+        }
+
+        async fn find_delivery_by_id(&self, id: i32) -> Result<Option<WebhookDelivery>, AppError> {
+            Ok(self
+                .deliveries
+                .lock()
+                .unwrap()
+                .iter()
+                .find(|d| d.id == id)
+                .cloned())
+        }
+
+        async fn get_due_deliveries(&self, limit: i32) -> Result<Vec<WebhookDelivery>, AppError> {
+            Ok(self
+                .deliveries
+                .lock()
+                .unwrap()
+                .iter()
+                .filter(|d| d.status == "pending" && d.next_attempt_at <= Utc::now())
+                .take(limit as usize)
+                .cloned()
+                .collect())
+        }
+
+        async fn get_paginated_deliveries(
+            &self,
+            _subscription_id: i32,
+            _page: i32,
+            _page_size: i32,
+        ) -> Result<Vec<WebhookDelivery>, AppError> {
+            unimplemented!()
+        }
+
+        async fn mark_delivery_succeeded(
+            &self,
+            id: i32,
+            response_status: i32,
+        ) -> Result<(), AppError> {
+            let mut deliveries = self.deliveries.lock().unwrap();
+            let delivery = &mut deliveries[id as usize - 1];
+            delivery.status = "succeeded".to_string();
+            delivery.attempts += 1;
+            delivery.response_status = Some(response_status);
+            delivery.last_error = None;
+            delivery.delivered_at = Some(Utc::now());
+            Ok(())
+        }
+
+        async fn mark_delivery_attempt_failed(
+            &self,
+            id: i32,
+            response_status: Option<i32>,
+            error: &str,
+            retry_after_secs: Option<i64>,
+        ) -> Result<(), AppError> {
+            let mut deliveries = self.deliveries.lock().unwrap();
+            let delivery = &mut deliveries[id as usize - 1];
+            delivery.status = match retry_after_secs {
+                Some(_) => "pending".to_string(),
+                None => "failed".to_string(),
+            };
+            delivery.attempts += 1;
+            delivery.response_status = response_status;
+            delivery.last_error = Some(error.to_string());
+            delivery.next_attempt_at =
+                Utc::now() + chrono::Duration::seconds(retry_after_secs.unwrap_or(0));
+            Ok(())
+        }
+    }
+
+    // 受け取ったリクエストの署名ヘッダーと本文を記録し、最初の failures 回は 500 を返すサーバー
+    #[derive(Default)]
+    struct Received {
+        failures: usize,
+        count: AtomicUsize,
+        requests: Mutex<Vec<(String, String)>>,
+    }
+
+    async fn receive(req: HttpRequest, body: String, state: web::Data<Received>) -> HttpResponse {
+        let signature = req
+            .headers()
+            .get("X-Webhook-Signature")
+            .and_then(|value| value.to_str().ok())
+            .unwrap_or_default()
+            .to_string();
+        state.requests.lock().unwrap().push((signature, body));
+
+        if state.count.fetch_add(1, Ordering::SeqCst) < state.failures {
+            HttpResponse::InternalServerError().finish()
+        } else {
+            HttpResponse::Ok().finish()
+        }
+    }
+
+    fn spawn_server(failures: usize) -> (SocketAddr, web::Data<Received>) {
+        let state = web::Data::new(Received {
+            failures,
+            ..Default::default()
+        });
+        let app_state = state.clone();
+        let server = HttpServer::new(move || {
+            App::new()
+                .app_data(app_state.clone())
+                .route("/hook", web::post().to(receive))
+        })
+        .workers(1)
+        .bind(("127.0.0.1", 0))
+        .unwrap();
+        let addr = server.addrs()[0];
+        rt::spawn(server.run());
+        (addr, state)
+    }
+
+    fn subscription(id: i32, url: String) -> WebhookSubscription {
+        WebhookSubscription {
+            id,
+            url,
+            secret: format!("secret-{}", id),
+            event_types: "order.completed".to_string(),
+            sponsor: None,
+            is_active: true,
+            created_at: Utc::now(),
+        }
+    }
+
+    fn service(
+        subscriptions: Vec<WebhookSubscription>,
+        max_attempts: i32,
+    ) -> WebhookService<InMemoryWebhookRepository, HttpClient> {
+        WebhookService::new(
+            InMemoryWebhookRepository {
+                subscriptions,
+                ..Default::default()
+            },
+            HttpClient::new(Duration::from_secs(5)),
+            WebhookConfig {
+                max_attempts,
+                // 失敗した配信をすぐに再送できるようにする
+                retry_base_secs: 0,
+                delivery_interval_secs: 1,
+                request_timeout_secs: 5,
+                delivery_concurrency: 4,
+            },
+        )
+    }
+
+    #[actix_web::test]
+    async fn failed_delivery_is_retried_with_signature() {
+        let (addr, received) = spawn_server(1);
+        let service = service(vec![subscription(1, format!("http://{}/hook", addr))], 3);
+        let payload = r#"{"event_type":"order.completed"}"#;
+        service
+            .webhook_repository
+            .create_delivery(1, "order.completed", payload)
+            .await
+            .unwrap();
+
+        assert_eq!(service.deliver_due(10).await.unwrap(), 1);
+        let delivery = service.webhook_repository.delivery(1);
+        assert_eq!(delivery.status, "pending");
+        assert_eq!(delivery.attempts, 1);
+        assert_eq!(delivery.response_status, Some(500));
+
+        assert_eq!(service.deliver_due(10).await.unwrap(), 1);
+        let delivery = service.webhook_repository.delivery(1);
+        assert_eq!(delivery.status, "succeeded");
+        assert_eq!(delivery.attempts, 2);
+        assert_eq!(delivery.response_status, Some(200));
+
+        let expected_signature = format!(
+            "sha256={}",
+            hmac_sha256_hex(b"secret-1", payload.as_bytes())
+        );
+        let requests = received.requests.lock().unwrap();
+        assert_eq!(requests.len(), 2);
+        for (signature, body) in requests.iter() {
+            assert_eq!(signature, &expected_signature);
+            assert_eq!(body, payload);
+        }
+    }
+
+    #[actix_web::test]
+    async fn delivery_gives_up_after_max_attempts() {
+        let (addr, _received) = spawn_server(usize::MAX);
+        let service = service(vec![subscription(1, format!("http://{}/hook", addr))], 2);
+        service
+            .webhook_repository
+            .create_delivery(1, "order.completed", "{}")
+            .await
+            .unwrap();
+
+        service.deliver_due(10).await.unwrap();
+        service.deliver_due(10).await.unwrap();
+        let delivery = service.webhook_repository.delivery(1);
+        assert_eq!(delivery.status, "failed");
+        assert_eq!(delivery.attempts, 2);
+        assert_eq!(service.deliver_due(10).await.unwrap(), 0);
+    }
+
+    #[actix_web::test]
+    async fn unreachable_subscriber_does_not_block_others() {
+        // 閉じたポートには接続できない
+        let closed_addr = TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        let (addr, _received) = spawn_server(0);
+        let service = service(
+            vec![
+                subscription(1, format!("http://{}/hook", closed_addr)),
+                subscription(2, format!("http://{}/hook", addr)),
+            ],
+            3,
+        );
+        for subscription_id in [1, 2] {
+            service
+                .webhook_repository
+                .create_delivery(subscription_id, "order.completed", "{}")
+                .await
+                .unwrap();
+        }
+
+        assert_eq!(service.deliver_due(10).await.unwrap(), 2);
+        let unreachable = service.webhook_repository.delivery(1);
+        assert_eq!(unreachable.status, "pending");
+        assert!(unreachable.last_error.is_some());
+        assert_eq!(service.webhook_repository.delivery(2).status, "succeeded");
+    }
+}
@@ -1,4 +1,4 @@
-use actix_web::{HttpResponse, ResponseError};
+use actix_web::{http::header, HttpResponse, ResponseError};
 use serde::Serialize;
 use thiserror::Error;
 
@@ -8,16 +8,38 @@ pub enum AppError {
     BadRequest,
     #[error("Unauthorized")]
     Unauthorized,
+    #[error("Forbidden")]
+    Forbidden,
     #[error("Not Found")]
     NotFound,
     #[error("Conflict")]
     Conflict,
+    // 再試行までの秒数を Retry-After で返す
+    #[error("Too Many Requests")]
+    TooManyRequests(u64),
     #[error("Internal Server Error")]
     InternalServerError,
+    #[error("Service Unavailable")]
+    ServiceUnavailable,
     #[error(transparent)]
     SqlxError(#[from] sqlx::Error),
 }
 
+impl AppError {
+    // ユニーク制約違反 (MySQL の ER_DUP_ENTRY) は 409 にする
+    pub fn conflict_on_duplicate(err: sqlx::Error) -> AppError {
+        if let sqlx::Error::Database(db_err) = &err {
+            if db_err
+                .try_downcast_ref::<sqlx::mysql::MySqlDatabaseError>()
+                .is_some_and(|e| e.number() == 1062)
+            {
+                return AppError::Conflict;
+            }
+        }
+        AppError::SqlxError(err)
+    }
+}
+
 #[derive(Serialize)]
 struct ErrorResponse {
     message: String,
@@ -33,11 +55,16 @@ impl ResponseError for AppError {
         match *self {
             AppError::BadRequest => HttpResponse::BadRequest().json(error_response),
             AppError::Unauthorized => HttpResponse::Unauthorized().json(error_response),
+            AppError::Forbidden => HttpResponse::Forbidden().json(error_response),
             AppError::NotFound => HttpResponse::NotFound().json(error_response),
             AppError::Conflict => HttpResponse::Conflict().json(error_response),
+            AppError::TooManyRequests(retry_after_secs) => HttpResponse::TooManyRequests()
+                .insert_header((header::RETRY_AFTER, retry_after_secs.to_string()))
+                .json(error_response),
             AppError::InternalServerError => {
                 HttpResponse::InternalServerError().json(error_response)
             }
+            AppError::ServiceUnavailable => HttpResponse::ServiceUnavailable().json(error_response),
             AppError::SqlxError(_) => HttpResponse::InternalServerError().json(error_response),
         }
     }
@@ -0,0 +1,160 @@
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+use tokio::sync::{oneshot, Semaphore};
+
+use crate::config::CpuPoolConfig;
+use crate::errors::AppError;
+
+#[derive(Debug, Default)]
+struct Counters {
+    running: AtomicU64,
+    rejected: AtomicU64,
+}
+
+pub struct CpuPoolStats {
+    pub threads: u64,
+    pub queue_capacity: u64,
+    pub queued: u64,
+    pub running: u64,
+    pub rejected: u64,
+}
+
+// Argon2 や画像処理など CPU を使う処理を actix のワーカーから切り離して実行するスレッドプール
+// 実行中と実行待ちの合計が threads + queue_capacity に達していたら、待たずに 503 を返す
+#[derive(Debug)]
+pub struct CpuPool {
+    pool: rayon::ThreadPool,
+    permits: Arc<Semaphore>,
+    counters: Arc<Counters>,
+    threads: usize,
+    queue_capacity: usize,
+}
+
+impl CpuPool {
+    pub fn new(config: &CpuPoolConfig) -> Self {
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(config.threads)
+            .thread_name(|i| format!("cpu-pool-{}", i))
+            .build()
+            .expect("Failed to build cpu pool");
+
+        CpuPool {
+            pool,
+            permits: Arc::new(Semaphore::new(config.threads + config.queue_capacity)),
+            counters: Arc::new(Counters::default()),
+            threads: config.threads,
+            queue_capacity: config.queue_capacity,
+        }
+    }
+
+    pub async fn run<F, R>(&self, f: F) -> Result<R, AppError>
+    where
+        F: FnOnce() -> R + Send + 'static,
+        R: Send + 'static,
+    {
+        let Ok(permit) = self.permits.clone().try_acquire_owned() else {
+            self.counters.rejected.fetch_add(1, Ordering::Relaxed);
+            return Err(AppError::ServiceUnavailable);
+        };
+
+        let (result_sender, result_receiver) = oneshot::channel();
+        let counters = self.counters.clone();
+        self.pool.spawn(move || {
+            counters.running.fetch_add(1, Ordering::Relaxed);
+            // rayon は spawn したジョブが panic するとプロセスごと落とすので、ここで止める
+            let result = panic::catch_unwind(AssertUnwindSafe(f));
+            counters.running.fetch_sub(1, Ordering::Relaxed);
+            // 結果を受け取った呼び出し元がすぐ次を投入できるよう、先に枠を返す
+            drop(permit);
+            match result {
+                Ok(result) => {
+                    let _ = result_sender.send(result);
+                }
+                Err(_) => log::error!("cpu pool job panicked"),
+            }
+        });
+
+        // ジョブが panic すると送信側が破棄されてここでエラーになる
+        result_receiver
+            .await
+            .map_err(|_| AppError::InternalServerError)
+    }
+
+    pub fn stats(&self) -> CpuPoolStats {
+        let in_flight =
+            (self.threads + self.queue_capacity - self.permits.available_permits()) as u64;
+        let running = self.counters.running.load(Ordering::Relaxed);
+        CpuPoolStats {
+            threads: self.threads as u64,
+            queue_capacity: self.queue_capacity as u64,
+            queued: in_flight.saturating_sub(running),
+            running,
+            rejected: self.counters.rejected.load(Ordering::Relaxed),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{mpsc, Arc};
+
+    use super::CpuPool;
+    use crate::{config::CpuPoolConfig, errors::AppError};
+
+    #[actix_web::test]
+    async fn rejects_jobs_beyond_threads_and_queue_capacity() {
+        let pool = Arc::new(CpuPool::new(&CpuPoolConfig {
+            threads: 1,
+            queue_capacity: 1,
+        }));
+
+        // 1 つ目は実行中、2 つ目は実行待ちのままにしておく
+        let (release_sender, release_receiver) = mpsc::channel::<()>();
+        let release_receiver = Arc::new(std::sync::Mutex::new(release_receiver));
+        let blocked = (0..2)
+            .map(|_| {
+                let pool = pool.clone();
+                let release_receiver = release_receiver.clone();
+                actix_web::rt::spawn(async move {
+                    pool.run(move || release_receiver.lock().unwrap().recv().unwrap())
+                        .await
+                })
+            })
+            .collect::<Vec<_>>();
+        while pool.stats().queued + pool.stats().running < 2 {
+            actix_web::rt::task::yield_now().await;
+        }
+
+        assert!(matches!(
+            pool.run(|| ()).await,
+            Err(AppError::ServiceUnavailable)
+        ));
+        assert_eq!(pool.stats().rejected, 1);
+
+        for _ in 0..2 {
+            release_sender.send(()).unwrap();
+        }
+        for job in blocked {
+            assert!(job.await.unwrap().is_ok());
+        }
+        // 空きができれば再び受け付ける
+        assert_eq!(pool.run(|| 1 + 1).await.unwrap(), 2);
+    }
+
+    #[actix_web::test]
+    async fn panicking_job_is_an_internal_error() {
+        let pool = CpuPool::new(&CpuPoolConfig {
+            threads: 1,
+            queue_capacity: 0,
+        });
+
+        assert!(matches!(
+            pool.run(|| panic!("boom")).await,
+            Err(AppError::InternalServerError)
+        ));
+        // panic したジョブの枠も返される
+        assert_eq!(pool.run(|| 1).await.unwrap(), 1);
+    }
+}
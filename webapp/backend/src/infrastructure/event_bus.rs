@@ -0,0 +1,23 @@
+use tokio::sync::broadcast;
+
+// プロセス内でイベントを購読者へ配信する
+#[derive(Debug, Clone)]
+pub struct EventBus<T: Clone> {
+    sender: broadcast::Sender<T>,
+}
+
+impl<T: Clone> EventBus<T> {
+    pub fn new(capacity: usize) -> Self {
+        let (sender, _) = broadcast::channel(capacity);
+        EventBus { sender }
+    }
+
+    pub fn publish(&self, event: T) {
+        // 購読者がいない場合はエラーになるが、捨てて問題ない
+        let _ = self.sender.send(event);
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<T> {
+        self.sender.subscribe()
+    }
+}
@@ -0,0 +1,64 @@
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use reqwest::dns::{Addrs, Name, Resolve, Resolving};
+use reqwest::redirect::Policy;
+
+use crate::domains::webhook_service::WebhookSender;
+use crate::utils::is_public_ip;
+
+// Webhook の送信に使う HTTP クライアント (POST してステータスコードだけを見る)
+#[derive(Clone, Debug)]
+pub struct HttpClient {
+    client: reqwest::Client,
+}
+
+impl HttpClient {
+    pub fn new(timeout: Duration) -> Self {
+        let client = reqwest::Client::builder()
+            .timeout(timeout)
+            // 登録時に検証した URL から内部のアドレスへ飛ばされないようにする
+            .redirect(Policy::none())
+            .dns_resolver(Arc::new(PublicAddrResolver))
+            .build()
+            .expect("failed to build http client");
+
+        HttpClient { client }
+    }
+}
+
+// 名前解決の結果がプライベート・ループバック・リンクローカルのアドレスなら接続しない
+// (登録後に DNS の向き先を変えられても内部のサーバーへは送らない)
+struct PublicAddrResolver;
+
+impl Resolve for PublicAddrResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        Box::pin(async move {
+            let addrs: Vec<SocketAddr> = tokio::net::lookup_host((name.as_str(), 0))
+                .await?
+                .filter(|addr| is_public_ip(addr.ip()))
+                .collect();
+            if addrs.is_empty() {
+                return Err(
+                    format!("{} does not resolve to a public address", name.as_str()).into(),
+                );
+            }
+            let addrs: Addrs = Box::new(addrs.into_iter());
+            Ok(addrs)
+        })
+    }
+}
+
+impl WebhookSender for HttpClient {
+    async fn send(&self, url: &str, headers: &[(&str, &str)], body: &[u8]) -> Result<u16, String> {
+        let mut request = self.client.post(url).body(body.to_vec());
+        for (name, value) in headers {
+            request = request.header(*name, *value);
+        }
+
+        let response = request.send().await.map_err(|err| err.to_string())?;
+
+        Ok(response.status().as_u16())
+    }
+}
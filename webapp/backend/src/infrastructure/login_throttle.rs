@@ -0,0 +1,304 @@
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use moka::{
+    future::Cache,
+    ops::compute::{CompResult, Op},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::config::LoginThrottleConfig;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LoginThrottleKind {
+    Username,
+    Address,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct LoginThrottleKey {
+    pub kind: LoginThrottleKind,
+    pub key: String,
+}
+
+impl LoginThrottleKey {
+    pub fn username(username: &str) -> Self {
+        // MySQL の照合順序では大文字小文字を区別しないので、揃えておかないと回避できてしまう
+        LoginThrottleKey {
+            kind: LoginThrottleKind::Username,
+            key: username.to_lowercase(),
+        }
+    }
+
+    pub fn address(address: &str) -> Self {
+        LoginThrottleKey {
+            kind: LoginThrottleKind::Address,
+            key: address.to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct LoginFailures {
+    pub failures: u32,
+    pub last_failed_at: DateTime<Utc>,
+    pub locked_until: Option<DateTime<Utc>>,
+}
+
+// ログイン失敗回数をユーザー名・接続元アドレスごとに数え、一定回数を超えたら一時的にロックする
+#[derive(Debug)]
+pub struct LoginThrottle {
+    cache: Cache<LoginThrottleKey, LoginFailures>,
+    config: LoginThrottleConfig,
+}
+
+impl LoginThrottle {
+    pub fn new(config: LoginThrottleConfig) -> Self {
+        LoginThrottle {
+            // ロック中のエントリが先に消えないよう、最長ロック時間 + リセット時間は保持する
+            cache: Cache::builder()
+                .max_capacity(config.capacity)
+                .time_to_idle(Duration::from_secs(
+                    (config.max_lockout_secs + config.reset_after_secs) as u64,
+                ))
+                .build(),
+            config,
+        }
+    }
+
+    // 試行を先に失敗として数えておき、成功したら forgive_attempt で取り消す
+    // ロックの判定と加算を 1 回のキャッシュ操作で行うので、同時の試行で上限を超えない
+    // いずれかのキーがロック中なら、数えずに解除までの秒数を返す
+    pub async fn begin_attempt(&self, keys: &[LoginThrottleKey]) -> Result<(), u64> {
+        for (i, key) in keys.iter().enumerate() {
+            if let Err(retry_after_secs) = self.count_attempt(key.clone()).await {
+                for counted in &keys[..i] {
+                    self.forgive_attempt(counted.clone()).await;
+                }
+                return Err(retry_after_secs);
+            }
+        }
+        Ok(())
+    }
+
+    async fn count_attempt(&self, key: LoginThrottleKey) -> Result<(), u64> {
+        let config = self.config.clone();
+        let result = self
+            .cache
+            .entry(key)
+            .and_compute_with(|entry| {
+                let now = Utc::now();
+                let previous = entry.map(|e| e.into_value());
+                let op = match previous {
+                    Some(previous) if retry_after_secs(&previous, now).is_some() => Op::Nop,
+                    previous => Op::Put(next_failures(previous.as_ref(), now, &config)),
+                };
+                std::future::ready(op)
+            })
+            .await;
+
+        match result {
+            CompResult::Unchanged(entry) => match retry_after_secs(entry.value(), Utc::now()) {
+                Some(secs) => Err(secs),
+                None => Ok(()),
+            },
+            _ => Ok(()),
+        }
+    }
+
+    // 先に数えた試行を取り消す (上限を下回ればロックも外す)
+    pub async fn forgive_attempt(&self, key: LoginThrottleKey) {
+        let max_failures = self.config.max_failures;
+        self.cache
+            .entry(key)
+            .and_compute_with(|entry| {
+                let op = match entry.map(|e| e.into_value()) {
+                    Some(previous) if previous.failures <= 1 => Op::Remove,
+                    Some(previous) => {
+                        let failures = previous.failures - 1;
+                        Op::Put(LoginFailures {
+                            failures,
+                            locked_until: previous
+                                .locked_until
+                                .filter(|_| failures >= max_failures),
+                            ..previous
+                        })
+                    }
+                    None => Op::Nop,
+                };
+                std::future::ready(op)
+            })
+            .await;
+    }
+
+    pub async fn clear(&self, key: &LoginThrottleKey) -> bool {
+        self.cache.remove(key).await.is_some()
+    }
+
+    pub fn entries(&self) -> Vec<(LoginThrottleKey, LoginFailures)> {
+        self.cache
+            .iter()
+            .map(|(key, failures)| (key.as_ref().clone(), failures))
+            .collect()
+    }
+}
+
+// ロック中なら解除までの秒数
+fn retry_after_secs(failures: &LoginFailures, now: DateTime<Utc>) -> Option<u64> {
+    let locked_until = failures
+        .locked_until
+        .filter(|locked_until| *locked_until > now)?;
+    // 端数は切り上げて、Retry-After より前に再試行させない
+    Some(((locked_until - now).num_milliseconds() as u64).div_ceil(1000))
+}
+
+// 失敗を 1 回数えた後の状態
+fn next_failures(
+    previous: Option<&LoginFailures>,
+    now: DateTime<Utc>,
+    config: &LoginThrottleConfig,
+) -> LoginFailures {
+    let reset_after = chrono::Duration::seconds(config.reset_after_secs);
+    let failures = match previous {
+        // 最後の失敗 (ロック中ならロック解除) から reset_after_secs 経っていれば数え直す
+        Some(previous)
+            if previous
+                .locked_until
+                .map_or(previous.last_failed_at, |locked_until| {
+                    locked_until.max(previous.last_failed_at)
+                })
+                + reset_after
+                > now =>
+        {
+            previous.failures + 1
+        }
+        _ => 1,
+    };
+
+    let locked_until = (failures >= config.max_failures).then(|| {
+        // 上限を超えるたびにロック時間を倍にする
+        let exponent = (failures - config.max_failures).min(31);
+        let lockout_secs = config
+            .base_lockout_secs
+            .saturating_mul(1 << exponent)
+            .min(config.max_lockout_secs);
+        now + chrono::Duration::seconds(lockout_secs)
+    });
+
+    LoginFailures {
+        failures,
+        last_failed_at: now,
+        locked_until,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use chrono::{Duration, Utc};
+
+    use super::{next_failures, retry_after_secs, LoginFailures, LoginThrottle, LoginThrottleKey};
+    use crate::config::LoginThrottleConfig;
+
+    fn config() -> LoginThrottleConfig {
+        LoginThrottleConfig {
+            max_failures: 3,
+            base_lockout_secs: 60,
+            max_lockout_secs: 300,
+            reset_after_secs: 600,
+            capacity: 100,
+        }
+    }
+
+    fn lockout_secs(failures: &LoginFailures) -> Option<i64> {
+        failures
+            .locked_until
+            .map(|locked_until| (locked_until - failures.last_failed_at).num_seconds())
+    }
+
+    #[test]
+    fn locks_at_max_failures_and_doubles_up_to_the_cap() {
+        let config = config();
+        let mut now = Utc::now();
+        let mut state: Option<LoginFailures> = None;
+        let mut lockouts = vec![];
+        for _ in 0..6 {
+            let next = next_failures(state.as_ref(), now, &config);
+            lockouts.push(lockout_secs(&next));
+            // ロックが明けてから次の失敗をする
+            now = next.locked_until.unwrap_or(now) + Duration::seconds(1);
+            state = Some(next);
+        }
+        assert_eq!(
+            lockouts,
+            vec![None, None, Some(60), Some(120), Some(240), Some(300)]
+        );
+    }
+
+    #[test]
+    fn failures_reset_after_quiet_period() {
+        let config = config();
+        let now = Utc::now();
+        let first = next_failures(None, now, &config);
+        let second = next_failures(Some(&first), now + Duration::seconds(599), &config);
+        assert_eq!(second.failures, 2);
+        let third = next_failures(Some(&second), now + Duration::seconds(1200), &config);
+        assert_eq!(third.failures, 1);
+        assert_eq!(third.locked_until, None);
+    }
+
+    #[test]
+    fn retry_after_rounds_up_and_expires() {
+        let now = Utc::now();
+        let failures = LoginFailures {
+            failures: 3,
+            last_failed_at: now,
+            locked_until: Some(now + Duration::milliseconds(1500)),
+        };
+        assert_eq!(retry_after_secs(&failures, now), Some(2));
+        assert_eq!(
+            retry_after_secs(&failures, now + Duration::seconds(2)),
+            None
+        );
+    }
+
+    #[actix_web::test]
+    async fn concurrent_attempts_do_not_exceed_max_failures() {
+        let throttle = Arc::new(LoginThrottle::new(config()));
+        let keys = [LoginThrottleKey::username("alice")];
+        let attempts = (0..10).map(|_| {
+            let throttle = throttle.clone();
+            let keys = keys.clone();
+            actix_web::rt::spawn(async move { throttle.begin_attempt(&keys).await })
+        });
+        let results = futures_util::future::join_all(attempts).await;
+        let allowed = results
+            .into_iter()
+            .filter(|result| matches!(result, Ok(Ok(()))))
+            .count();
+        assert_eq!(allowed, 3);
+    }
+
+    #[actix_web::test]
+    async fn forgiven_attempt_lifts_the_lock() {
+        let throttle = LoginThrottle::new(config());
+        let keys = [
+            LoginThrottleKey::username("alice"),
+            LoginThrottleKey::address("192.0.2.1"),
+        ];
+        for _ in 0..3 {
+            assert!(throttle.begin_attempt(&keys).await.is_ok());
+        }
+        assert!(throttle.begin_attempt(&keys).await.is_err());
+
+        // 3 回目が成功していれば、上限を下回るのでロックは外れる
+        throttle.forgive_attempt(keys[1].clone()).await;
+        // 他のキーがロック中なら、先に数えた分は取り消される
+        let reversed = [keys[1].clone(), keys[0].clone()];
+        assert!(throttle.begin_attempt(&reversed).await.is_err());
+        assert!(throttle.begin_attempt(&keys[1..]).await.is_ok());
+        assert!(throttle.begin_attempt(&keys[1..]).await.is_err());
+    }
+}
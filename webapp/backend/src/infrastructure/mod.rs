@@ -1 +1,9 @@
+pub mod cpu_pool;
 pub mod db;
+pub mod event_bus;
+pub mod http_client;
+pub mod login_throttle;
+pub mod password_reset_notifier;
+pub mod profile_image;
+pub mod session_cache;
+pub mod totp;
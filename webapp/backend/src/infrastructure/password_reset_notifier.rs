@@ -0,0 +1,54 @@
+use std::fs::OpenOptions;
+use std::io::Write;
+
+use chrono::{DateTime, Utc};
+
+use crate::domains::auth_service::PasswordResetNotifier;
+use crate::models::user::User;
+
+// 開発用: 再設定トークンをファイルに追記する
+// パスが空なら要求があったことだけをログに出す (トークンはログに残さない)
+#[derive(Debug, Clone)]
+pub struct LocalPasswordResetNotifier {
+    outbox_path: String,
+}
+
+impl LocalPasswordResetNotifier {
+    pub fn new(outbox_path: String) -> Self {
+        LocalPasswordResetNotifier { outbox_path }
+    }
+}
+
+impl PasswordResetNotifier for LocalPasswordResetNotifier {
+    async fn send_password_reset(
+        &self,
+        user: &User,
+        reset_token: &str,
+        expires_at: DateTime<Utc>,
+    ) -> Result<(), String> {
+        if self.outbox_path.is_empty() {
+            log::info!(
+                "password reset requested: user_id={}\ttoken=<redacted>\texpires_at={}",
+                user.id,
+                expires_at.to_rfc3339(),
+            );
+            return Ok(());
+        }
+
+        let line = format!(
+            "{}\tuser_id={}\tusername={}\ttoken={}\texpires_at={}",
+            Utc::now().to_rfc3339(),
+            user.id,
+            user.username,
+            reset_token,
+            expires_at.to_rfc3339(),
+        );
+
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.outbox_path)
+            .map_err(|err| err.to_string())?;
+        writeln!(file, "{}", line).map_err(|err| err.to_string())
+    }
+}
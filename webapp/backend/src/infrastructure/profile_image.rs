@@ -0,0 +1,271 @@
+use std::fs;
+use std::io::{self, Cursor, Write};
+use std::path::{Path, PathBuf};
+
+use actix_web::web::Bytes;
+use fast_image_resize::images::Image;
+use fast_image_resize::{PixelType, Resizer};
+use image::codecs::jpeg::JpegEncoder;
+use image::codecs::png::PngEncoder;
+use image::codecs::webp::WebPEncoder;
+use image::imageops::FilterType;
+use image::{DynamicImage, ExtendedColorType, ImageEncoder, ImageFormat, ImageReader, Limits};
+
+use crate::config::ProfileImageConfig;
+use crate::errors::AppError;
+use crate::utils::sha256_hex;
+
+pub const PROFILE_IMAGE_DIR: &str = "images/user_profile";
+pub const DEFAULT_PROFILE_IMAGE: &str = "default.png";
+// 配信する正方形画像の一辺
+pub const PROFILE_IMAGE_SIZES: [u32; 3] = [64, 128, 500];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ProfileImageFormat {
+    WebP,
+    Jpeg,
+    Png,
+}
+
+// 配信する画像の元
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum ProfileImageSource {
+    // PROFILE_IMAGE_DIR 以下のファイル
+    File(String),
+    // 画像を設定していないユーザー用に生成する模様 (値はシードの SHA-256)
+    Identicon(String),
+}
+
+impl ProfileImageSource {
+    pub fn identicon(user_id: i32, username: &str) -> Self {
+        ProfileImageSource::Identicon(sha256_hex(format!("{}:{}", user_id, username).as_bytes()))
+    }
+}
+
+// エンコード済みのサイズ違いの画像
+#[derive(Debug, Clone)]
+pub struct ProfileImageVariant {
+    pub bytes: Bytes,
+    pub etag: String,
+    pub content_type: &'static str,
+}
+
+impl ProfileImageFormat {
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            ProfileImageFormat::WebP => "image/webp",
+            ProfileImageFormat::Jpeg => "image/jpeg",
+            ProfileImageFormat::Png => "image/png",
+        }
+    }
+}
+
+// アップロードされた画像を検証し、正方形の RGBA PNG に正規化する
+// 再エンコードするので EXIF などのメタデータは残らない
+pub fn normalize_profile_image(
+    bytes: &[u8],
+    config: &ProfileImageConfig,
+) -> Result<Vec<u8>, AppError> {
+    let mut reader = ImageReader::new(Cursor::new(bytes))
+        .with_guessed_format()
+        .map_err(|_| AppError::BadRequest)?;
+    match reader.format() {
+        Some(ImageFormat::Png | ImageFormat::Jpeg | ImageFormat::WebP) => {}
+        _ => return Err(AppError::BadRequest),
+    }
+
+    let mut limits = Limits::default();
+    limits.max_image_width = Some(config.max_dimension);
+    limits.max_image_height = Some(config.max_dimension);
+    reader.limits(limits);
+
+    let image = reader.decode().map_err(|_| AppError::BadRequest)?;
+    if image.width() < config.min_dimension || image.height() < config.min_dimension {
+        return Err(AppError::BadRequest);
+    }
+
+    // 中央を正方形に切り抜き、大きすぎれば縮小する
+    let side = image.width().min(image.height());
+    let mut image = image.crop_imm(
+        (image.width() - side) / 2,
+        (image.height() - side) / 2,
+        side,
+        side,
+    );
+    if side > config.stored_dimension {
+        image = image.resize_exact(
+            config.stored_dimension,
+            config.stored_dimension,
+            FilterType::Lanczos3,
+        );
+    }
+    let image = DynamicImage::ImageRgba8(image.into_rgba8());
+
+    let mut encoded = Vec::new();
+    PngEncoder::new(&mut encoded)
+        .write_image(
+            image.as_bytes(),
+            image.width(),
+            image.height(),
+            image.color().into(),
+        )
+        .map_err(|_| AppError::InternalServerError)?;
+
+    Ok(encoded)
+}
+
+// 内容のハッシュをファイル名にして保存し、そのファイル名を返す
+// 同じ内容なら同じファイルを共有する
+pub fn store_profile_image(encoded: &[u8]) -> io::Result<String> {
+    let file_name = format!("{}.png", sha256_hex(encoded));
+    let path = Path::new(PROFILE_IMAGE_DIR).join(&file_name);
+    if path.exists() {
+        return Ok(file_name);
+    }
+
+    // 書きかけのファイルを読まれないよう、一時ファイルに書いてから rename する
+    let tmp_path: PathBuf =
+        Path::new(PROFILE_IMAGE_DIR).join(format!(".{}.{}.tmp", file_name, rand::random::<u32>()));
+    let mut file = fs::File::create(&tmp_path)?;
+    file.write_all(encoded)?;
+    file.sync_all()?;
+    fs::rename(&tmp_path, &path)?;
+
+    Ok(file_name)
+}
+
+// 既に消えていてもよい
+pub fn remove_profile_image(profile_image_name: &str) -> io::Result<()> {
+    match fs::remove_file(Path::new(PROFILE_IMAGE_DIR).join(profile_image_name)) {
+        Err(err) if err.kind() != io::ErrorKind::NotFound => Err(err),
+        _ => Ok(()),
+    }
+}
+
+// 画像を size x size に縮小して指定の形式でエンコードする
+pub fn render_profile_image_variant(
+    source: &ProfileImageSource,
+    size: u32,
+    format: ProfileImageFormat,
+) -> Result<Vec<u8>, AppError> {
+    let profile_image_name = match source {
+        ProfileImageSource::File(profile_image_name) => profile_image_name,
+        ProfileImageSource::Identicon(seed) => {
+            let seed = hex::decode(seed).map_err(|_| AppError::InternalServerError)?;
+            return encode_profile_image(&render_identicon(&seed, size), size, format);
+        }
+    };
+
+    let path = Path::new(PROFILE_IMAGE_DIR).join(profile_image_name);
+    let src_image = ImageReader::open(&path)
+        .map_err(|_| AppError::NotFound)?
+        .with_guessed_format()
+        .map_err(|_| AppError::InternalServerError)?
+        .decode()
+        .map_err(|_| AppError::InternalServerError)?;
+    let src_image = DynamicImage::ImageRgba8(src_image.into_rgba8());
+
+    let mut dst_image = Image::new(size, size, PixelType::U8x4);
+    Resizer::new()
+        .resize(&src_image, &mut dst_image, None)
+        .map_err(|_| AppError::InternalServerError)?;
+
+    encode_profile_image(dst_image.buffer(), size, format)
+}
+
+// RGBA8 の画素列をエンコードする (JPEG は透過を白で塗りつぶす)
+pub fn encode_profile_image(
+    rgba: &[u8],
+    size: u32,
+    format: ProfileImageFormat,
+) -> Result<Vec<u8>, AppError> {
+    let mut encoded = Vec::new();
+    let result = match format {
+        ProfileImageFormat::WebP => WebPEncoder::new_lossless(&mut encoded).write_image(
+            rgba,
+            size,
+            size,
+            ExtendedColorType::Rgba8,
+        ),
+        ProfileImageFormat::Jpeg => {
+            let rgb: Vec<u8> = rgba
+                .chunks_exact(4)
+                .flat_map(|px| {
+                    let alpha = px[3] as u16;
+                    [0, 1, 2].map(|i| ((px[i] as u16 * alpha + 255 * (255 - alpha)) / 255) as u8)
+                })
+                .collect();
+            JpegEncoder::new_with_quality(&mut encoded, 85).write_image(
+                &rgb,
+                size,
+                size,
+                ExtendedColorType::Rgb8,
+            )
+        }
+        ProfileImageFormat::Png => {
+            PngEncoder::new(&mut encoded).write_image(rgba, size, size, ExtendedColorType::Rgba8)
+        }
+    };
+    result.map_err(|_| AppError::InternalServerError)?;
+
+    Ok(encoded)
+}
+
+const IDENTICON_GRID: u32 = 5;
+const IDENTICON_BACKGROUND: [u8; 4] = [0xf0, 0xf0, 0xf0, 0xff];
+
+// GitHub 風の左右対称な 5x5 の模様を RGBA8 で描く
+// 色と模様はシードだけで決まるので、同じユーザーには常に同じ画像になる
+fn render_identicon(seed: &[u8], size: u32) -> Vec<u8> {
+    let hue = u16::from_be_bytes([seed[0], seed[1]]) as f64 % 360.0;
+    let saturation = 0.45 + (seed[2] as f64 / 255.0) * 0.25;
+    let lightness = 0.45 + (seed[3] as f64 / 255.0) * 0.15;
+    let foreground = hsl_to_rgba(hue, saturation, lightness);
+
+    // 左半分 (中央の列を含む 3 列) だけを決めて右側は反転させる
+    let pattern = u16::from_be_bytes([seed[4], seed[5]]);
+    let half = IDENTICON_GRID.div_ceil(2);
+    let is_filled = |column: u32, row: u32| {
+        let column = column.min(IDENTICON_GRID - 1 - column);
+        pattern >> (row * half + column) & 1 == 1
+    };
+
+    // 周囲に 1/2 マス分の余白を取る
+    let margin = size / (IDENTICON_GRID * 2);
+    let grid_size = size - margin * 2;
+    let mut rgba = Vec::with_capacity((size * size * 4) as usize);
+    for y in 0..size {
+        for x in 0..size {
+            let inside = (margin..margin + grid_size).contains(&x)
+                && (margin..margin + grid_size).contains(&y);
+            let filled = inside
+                && is_filled(
+                    (x - margin) * IDENTICON_GRID / grid_size,
+                    (y - margin) * IDENTICON_GRID / grid_size,
+                );
+            rgba.extend_from_slice(if filled {
+                &foreground
+            } else {
+                &IDENTICON_BACKGROUND
+            });
+        }
+    }
+    rgba
+}
+
+fn hsl_to_rgba(hue: f64, saturation: f64, lightness: f64) -> [u8; 4] {
+    let chroma = (1.0 - (2.0 * lightness - 1.0).abs()) * saturation;
+    let h = hue / 60.0;
+    let x = chroma * (1.0 - (h % 2.0 - 1.0).abs());
+    let (r, g, b) = match h as u32 {
+        0 => (chroma, x, 0.0),
+        1 => (x, chroma, 0.0),
+        2 => (0.0, chroma, x),
+        3 => (0.0, x, chroma),
+        4 => (x, 0.0, chroma),
+        _ => (chroma, 0.0, x),
+    };
+    let m = lightness - chroma / 2.0;
+    let to_u8 = |v: f64| ((v + m) * 255.0).round() as u8;
+    [to_u8(r), to_u8(g), to_u8(b), 0xff]
+}
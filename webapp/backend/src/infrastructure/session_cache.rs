@@ -0,0 +1,120 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+use moka::future::Cache;
+
+use crate::models::user::Principal;
+
+// 検証済みセッションのキャッシュ (キーはセッショントークン)
+#[derive(Debug)]
+pub struct SessionCache {
+    cache: Cache<String, Principal>,
+    // 失効させるたびに進める。DB を引いている間に失効したセッションをキャッシュに戻さないために使う
+    generation: AtomicU64,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+pub struct SessionCacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    pub entries: u64,
+}
+
+impl SessionCache {
+    pub fn new(max_capacity: u64, ttl_secs: u64) -> Self {
+        SessionCache {
+            cache: Cache::builder()
+                .max_capacity(max_capacity)
+                .time_to_live(Duration::from_secs(ttl_secs))
+                .support_invalidation_closures()
+                .build(),
+            generation: AtomicU64::new(0),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        }
+    }
+
+    pub async fn get(&self, session_token: &str) -> Option<Principal> {
+        let principal = self.cache.get(session_token).await;
+        let counter = match principal {
+            Some(_) => &self.hits,
+            None => &self.misses,
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+
+        principal
+    }
+
+    // DB を引く前に読んでおき、insert に渡す
+    pub fn generation(&self) -> u64 {
+        self.generation.load(Ordering::SeqCst)
+    }
+
+    // generation を読んだ後に失効があれば、入れたエントリーを取り除く
+    // (insert の後に始まった失効は、そちらの invalidate で取り除かれる)
+    pub async fn insert(&self, session_token: &str, principal: Principal, generation: u64) {
+        self.cache
+            .insert(session_token.to_string(), principal)
+            .await;
+        if self.generation() != generation {
+            self.cache.invalidate(session_token).await;
+        }
+    }
+
+    // DB から削除した後に呼ぶ
+    pub async fn invalidate(&self, session_token: &str) {
+        self.generation.fetch_add(1, Ordering::SeqCst);
+        self.cache.invalidate(session_token).await;
+    }
+
+    // そのユーザーのセッションをすべて捨てる
+    pub fn invalidate_user(&self, user_id: i32) {
+        self.generation.fetch_add(1, Ordering::SeqCst);
+        // support_invalidation_closures を指定しているので失敗しない
+        let _ = self
+            .cache
+            .invalidate_entries_if(move |_, principal| principal.user_id == user_id);
+    }
+
+    pub fn stats(&self) -> SessionCacheStats {
+        SessionCacheStats {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            entries: self.cache.entry_count(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SessionCache;
+    use crate::models::user::{Principal, Role};
+
+    fn principal(user_id: i32) -> Principal {
+        Principal {
+            user_id,
+            role: Role::Client,
+            dispatcher_id: None,
+            area_ids: vec![],
+            tow_truck_id: None,
+            impersonator_id: None,
+        }
+    }
+
+    #[actix_web::test]
+    async fn does_not_cache_sessions_revoked_during_the_lookup() {
+        let cache = SessionCache::new(100, 60);
+
+        // DB を引いている間にログアウトされた
+        let generation = cache.generation();
+        cache.invalidate_user(1);
+        cache.insert("token", principal(1), generation).await;
+        assert!(cache.get("token").await.is_none());
+
+        // 失効がなければキャッシュする
+        let generation = cache.generation();
+        cache.insert("token", principal(1), generation).await;
+        assert!(cache.get("token").await.is_some());
+    }
+}
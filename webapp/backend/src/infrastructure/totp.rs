@@ -0,0 +1,174 @@
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
+use rand::distributions::Alphanumeric;
+use rand::Rng;
+use sha1::Sha1;
+use url::Url;
+
+// RFC 6238 の既定値 (認証アプリの多くはこれ以外に対応していない)
+const TOTP_DIGITS: u32 = 6;
+const TOTP_PERIOD_SECS: i64 = 30;
+const TOTP_SECRET_BYTES: usize = 20;
+const BASE32_ALPHABET: &[u8; 32] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ234567";
+
+pub fn generate_totp_secret() -> Vec<u8> {
+    let mut secret = vec![0u8; TOTP_SECRET_BYTES];
+    rand::thread_rng().fill(&mut secret[..]);
+    secret
+}
+
+// 認証アプリに手入力させるときの表記 (RFC 4648 の Base32、パディングなし)
+pub fn base32_encode(bytes: &[u8]) -> String {
+    let mut encoded = String::with_capacity(bytes.len().div_ceil(5) * 8);
+    let mut buffer: u32 = 0;
+    let mut bits = 0;
+    for &byte in bytes {
+        buffer = ((buffer << 8) | byte as u32) & 0xffff;
+        bits += 8;
+        while bits >= 5 {
+            bits -= 5;
+            encoded.push(BASE32_ALPHABET[((buffer >> bits) & 0x1f) as usize] as char);
+        }
+    }
+    if bits > 0 {
+        encoded.push(BASE32_ALPHABET[((buffer << (5 - bits)) & 0x1f) as usize] as char);
+    }
+    encoded
+}
+
+// QR コードにして認証アプリに読み込ませる otpauth URI
+pub fn totp_provisioning_uri(secret: &[u8], issuer: &str, account_name: &str) -> String {
+    let mut uri = Url::parse("otpauth://totp/").expect("valid otpauth uri");
+    uri.set_path(&format!("{}:{}", issuer, account_name));
+    uri.query_pairs_mut()
+        .append_pair("secret", &base32_encode(secret))
+        .append_pair("issuer", issuer)
+        .append_pair("algorithm", "SHA1")
+        .append_pair("digits", &TOTP_DIGITS.to_string())
+        .append_pair("period", &TOTP_PERIOD_SECS.to_string());
+    uri.to_string()
+}
+
+// 前後 skew_steps ステップまでのコードを受け付け、一致したステップを返す
+// 同じコードの使い回しを防ぐため、呼び出し側で最後に使ったステップと比べる
+pub fn verify_totp(secret: &[u8], code: &str, now: DateTime<Utc>, skew_steps: i64) -> Option<i64> {
+    if code.len() != TOTP_DIGITS as usize || !code.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let code: u32 = code.parse().ok()?;
+
+    let current_step = now.timestamp() / TOTP_PERIOD_SECS;
+    (current_step - skew_steps..=current_step + skew_steps)
+        .filter(|step| *step >= 0)
+        .find(|step| hotp::<Hmac<Sha1>>(secret, *step as u64, TOTP_DIGITS) == code)
+}
+
+// RFC 4226 (RFC 6238 の SHA-256 や SHA-512 にも使えるよう、HMAC は差し替えられる)
+fn hotp<M: Mac + hmac::digest::KeyInit>(secret: &[u8], counter: u64, digits: u32) -> u32 {
+    let mut mac = <M as Mac>::new_from_slice(secret).expect("HMAC accepts keys of any length");
+    mac.update(&counter.to_be_bytes());
+    let hash = mac.finalize().into_bytes();
+    let offset = (hash[hash.len() - 1] & 0x0f) as usize;
+    let binary = u32::from_be_bytes([
+        hash[offset] & 0x7f,
+        hash[offset + 1],
+        hash[offset + 2],
+        hash[offset + 3],
+    ]);
+    binary % 10u32.pow(digits)
+}
+
+// 端末をなくしたときに TOTP の代わりに 1 回だけ使えるコード (xxxxx-xxxxx)
+pub fn generate_recovery_code() -> String {
+    let code: String = rand::thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(10)
+        .map(|c| char::from(c).to_ascii_lowercase())
+        .collect();
+    format!("{}-{}", &code[..5], &code[5..])
+}
+
+// 入力時の区切りや大文字小文字の違いは無視する
+pub fn normalize_recovery_code(code: &str) -> String {
+    code.chars()
+        .filter(|c| c.is_ascii_alphanumeric())
+        .map(|c| c.to_ascii_lowercase())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{TimeZone, Utc};
+    use hmac::Hmac;
+    use sha1::Sha1;
+    use sha2::{Sha256, Sha512};
+
+    use super::{base32_encode, hotp, verify_totp, TOTP_PERIOD_SECS};
+
+    const SHA1_SEED: &[u8] = b"12345678901234567890";
+    const SHA256_SEED: &[u8] = b"12345678901234567890123456789012";
+    const SHA512_SEED: &[u8] = b"1234567890123456789012345678901234567890123456789012345678901234";
+
+    // RFC 6238 Appendix B (8 桁)
+    #[test]
+    fn matches_rfc6238_test_vectors() {
+        let vectors: [(i64, u32, u32, u32); 6] = [
+            (59, 94287082, 46119246, 90693936),
+            (1111111109, 7081804, 68084774, 25091201),
+            (1111111111, 14050471, 67062674, 99943326),
+            (1234567890, 89005924, 91819424, 93441116),
+            (2000000000, 69279037, 90698825, 38618901),
+            (20000000000, 65353130, 77737706, 47863826),
+        ];
+        for (time, sha1, sha256, sha512) in vectors {
+            let step = (time / TOTP_PERIOD_SECS) as u64;
+            assert_eq!(
+                hotp::<Hmac<Sha1>>(SHA1_SEED, step, 8),
+                sha1,
+                "SHA1 at {}",
+                time
+            );
+            assert_eq!(
+                hotp::<Hmac<Sha256>>(SHA256_SEED, step, 8),
+                sha256,
+                "SHA256 at {}",
+                time
+            );
+            assert_eq!(
+                hotp::<Hmac<Sha512>>(SHA512_SEED, step, 8),
+                sha512,
+                "SHA512 at {}",
+                time
+            );
+        }
+    }
+
+    #[test]
+    fn verify_totp_returns_the_matching_step_within_skew() {
+        let now = Utc.timestamp_opt(1111111111, 0).unwrap();
+        // 1111111111 の 8 桁のコードの下 6 桁
+        assert_eq!(verify_totp(SHA1_SEED, "050471", now, 1), Some(37037037));
+        // 1 つ前のステップ (1111111109) のコードも受け付ける
+        assert_eq!(verify_totp(SHA1_SEED, "081804", now, 1), Some(37037036));
+        assert_eq!(verify_totp(SHA1_SEED, "081804", now, 0), None);
+        assert_eq!(verify_totp(SHA1_SEED, "50471", now, 1), None);
+        assert_eq!(verify_totp(SHA1_SEED, "05047a", now, 1), None);
+    }
+
+    // RFC 4648 の 10 章 (パディングは付けない)
+    #[test]
+    fn base32_encodes_rfc4648_test_vectors() {
+        let vectors = [
+            ("", ""),
+            ("f", "MY"),
+            ("fo", "MZXQ"),
+            ("foo", "MZXW6"),
+            ("foob", "MZXW6YQ"),
+            ("fooba", "MZXW6YTB"),
+            ("foobar", "MZXW6YTBOI"),
+        ];
+        for (input, expected) in vectors {
+            assert_eq!(base32_encode(input.as_bytes()), expected);
+        }
+    }
+}
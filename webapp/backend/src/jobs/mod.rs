@@ -0,0 +1,4 @@
+pub mod order_offer_job;
+pub mod session_purge_job;
+pub mod stale_tow_truck_job;
+pub mod webhook_job;
@@ -0,0 +1,36 @@
+use std::time::Duration;
+
+use actix_web::{rt, web};
+
+use crate::{
+    domains::order_service::OrderService,
+    repositories::{
+        auth_repository::AuthRepositoryImpl, map_repository::MapRepositoryImpl,
+        order_repository::OrderRepositoryImpl, tow_truck_repository::TowTruckRepositoryImpl,
+    },
+};
+
+// 応答のないまま期限が切れた打診を次に近いレッカー車へ回す
+pub fn spawn(
+    service: web::Data<
+        OrderService<
+            OrderRepositoryImpl,
+            TowTruckRepositoryImpl,
+            AuthRepositoryImpl,
+            MapRepositoryImpl,
+        >,
+    >,
+    interval_secs: u64,
+) {
+    rt::spawn(async move {
+        let mut interval = rt::time::interval(Duration::from_secs(interval_secs));
+        loop {
+            interval.tick().await;
+            match service.expire_order_offers().await {
+                Ok(0) => {}
+                Ok(count) => log::info!("expired {} order offers", count),
+                Err(err) => log::error!("failed to expire order offers: {}", err),
+            }
+        }
+    });
+}
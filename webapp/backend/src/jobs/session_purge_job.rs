@@ -0,0 +1,32 @@
+use std::time::Duration;
+
+use actix_web::{rt, web};
+
+use crate::{
+    domains::auth_service::AuthService,
+    infrastructure::password_reset_notifier::LocalPasswordResetNotifier,
+    repositories::auth_repository::AuthRepositoryImpl,
+};
+
+// 期限切れ・無効になったセッションとパスワード再設定トークンを定期的に削除する
+pub fn spawn(
+    service: web::Data<AuthService<AuthRepositoryImpl, LocalPasswordResetNotifier>>,
+    interval_secs: u64,
+) {
+    rt::spawn(async move {
+        let mut interval = rt::time::interval(Duration::from_secs(interval_secs));
+        loop {
+            interval.tick().await;
+            match service.purge_expired_sessions().await {
+                Ok(0) => {}
+                Ok(count) => log::info!("purged {} expired sessions", count),
+                Err(err) => log::error!("failed to purge expired sessions: {}", err),
+            }
+            match service.purge_expired_password_reset_tokens().await {
+                Ok(0) => {}
+                Ok(count) => log::info!("purged {} expired password reset tokens", count),
+                Err(err) => log::error!("failed to purge password reset tokens: {}", err),
+            }
+        }
+    });
+}
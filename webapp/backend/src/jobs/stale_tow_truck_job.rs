@@ -0,0 +1,38 @@
+use std::time::Duration;
+
+use actix_web::{rt, web};
+
+use crate::{
+    domains::tow_truck_service::TowTruckService,
+    repositories::{
+        auth_repository::AuthRepositoryImpl, map_repository::MapRepositoryImpl,
+        order_repository::OrderRepositoryImpl, tow_truck_repository::TowTruckRepositoryImpl,
+    },
+};
+
+// 位置情報が途絶えたレッカー車を定期的に offline にする
+pub fn spawn(
+    service: web::Data<
+        TowTruckService<
+            TowTruckRepositoryImpl,
+            OrderRepositoryImpl,
+            MapRepositoryImpl,
+            AuthRepositoryImpl,
+        >,
+    >,
+    interval_secs: u64,
+) {
+    rt::spawn(async move {
+        let mut interval = rt::time::interval(Duration::from_secs(interval_secs));
+        // 最初の tick はすぐに来るので、起動直後には実行しない
+        interval.tick().await;
+        loop {
+            interval.tick().await;
+            match service.mark_stale_tow_trucks_offline().await {
+                Ok(0) => {}
+                Ok(count) => log::info!("marked {} stale tow trucks offline", count),
+                Err(err) => log::error!("failed to mark stale tow trucks offline: {}", err),
+            }
+        }
+    });
+}
@@ -0,0 +1,71 @@
+use std::time::Duration;
+
+use actix_web::{rt, web};
+use tokio::sync::broadcast::{self, error::RecvError};
+
+use crate::{
+    domains::{
+        dto::{order::OrderEventDto, tow_truck::TowTruckEventDto},
+        webhook_service::WebhookService,
+    },
+    infrastructure::{event_bus::EventBus, http_client::HttpClient},
+    repositories::webhook_repository::WebhookRepositoryImpl,
+};
+
+const DELIVERY_BATCH_SIZE: i32 = 100;
+
+type Service = web::Data<WebhookService<WebhookRepositoryImpl, HttpClient>>;
+
+// 注文・レッカー車のイベントを Webhook の配信として積み、送信時刻を過ぎたものを定期的に送る
+pub fn spawn(
+    service: Service,
+    order_events: &EventBus<OrderEventDto>,
+    tow_truck_events: &EventBus<TowTruckEventDto>,
+    interval_secs: u64,
+) {
+    spawn_enqueue(service.clone(), order_events.subscribe(), |event| {
+        (format!("order.{}", event.event_type), event.sponsor.clone())
+    });
+    spawn_enqueue(service.clone(), tow_truck_events.subscribe(), |event| {
+        (format!("tow_truck.{}", event.event_type), None)
+    });
+
+    rt::spawn(async move {
+        let mut interval = rt::time::interval(Duration::from_secs(interval_secs));
+        loop {
+            interval.tick().await;
+            match service.deliver_due(DELIVERY_BATCH_SIZE).await {
+                Ok(0) => {}
+                Ok(count) => log::info!("attempted {} webhook deliveries", count),
+                Err(err) => log::error!("failed to deliver webhooks: {}", err),
+            }
+        }
+    });
+}
+
+fn spawn_enqueue<E, F>(service: Service, mut receiver: broadcast::Receiver<E>, event_type: F)
+where
+    E: serde::Serialize + Clone + Send + 'static,
+    // イベント種別と、そのイベントを受け取れる sponsor (None なら運営側の登録先だけ)
+    F: Fn(&E) -> (String, Option<String>) + 'static,
+{
+    rt::spawn(async move {
+        loop {
+            match receiver.recv().await {
+                Ok(event) => {
+                    let (event_type, sponsor) = event_type(&event);
+                    if let Err(err) = service
+                        .enqueue(&event_type, sponsor.as_deref(), &event)
+                        .await
+                    {
+                        log::error!("failed to enqueue webhook {}: {}", event_type, err);
+                    }
+                }
+                Err(RecvError::Lagged(skipped)) => {
+                    log::warn!("webhook enqueue lagged, {} events skipped", skipped)
+                }
+                Err(RecvError::Closed) => break,
+            }
+        }
+    });
+}
@@ -3,149 +3,128 @@ use std::sync::Arc;
 
 use actix_cors::Cors;
 use actix_web::{web, App, HttpServer};
-use api::{
-    auth_handler, health_check_handler, map_handler, order_handler, result_handler,
-    tow_truck_handler,
-};
 use domains::map_service::MapService;
 use domains::{
-    auth_service::AuthService, order_service::OrderService, tow_truck_service::TowTruckService,
+    auth_service::AuthService, order_service::OrderService, shift_service::ShiftService,
+    tow_truck_service::TowTruckService, webhook_service::WebhookService,
 };
-use middlewares::auth_middleware::AuthMiddleware;
+use infrastructure::cpu_pool::CpuPool;
+use infrastructure::event_bus::EventBus;
+use infrastructure::http_client::HttpClient;
+use infrastructure::password_reset_notifier::LocalPasswordResetNotifier;
 use repositories::auth_repository::AuthRepositoryImpl;
 use repositories::map_repository::MapRepositoryImpl;
 use repositories::order_repository::OrderRepositoryImpl;
+use repositories::shift_repository::ShiftRepositoryImpl;
 use repositories::tow_truck_repository::TowTruckRepositoryImpl;
+use repositories::webhook_repository::WebhookRepositoryImpl;
 
 mod api;
+mod config;
 mod domains;
 mod errors;
 mod infrastructure;
+mod jobs;
 mod middlewares;
 mod models;
 mod repositories;
+mod routes;
 mod utils;
 
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
+    dotenv::dotenv().ok();
+    env_logger::init();
+
+    let config = config::Config::from_env();
     let pool = infrastructure::db::create_pool().await;
+    let tow_truck_events = EventBus::new(1024);
+    let order_events = EventBus::new(1024);
 
     let sock_path = "/tmp/da.sock";
 
-    let auth_service = web::Data::new(AuthService::new(AuthRepositoryImpl::new(pool.clone())));
-    let auth_service_for_middleware =
-        Arc::new(AuthService::new(AuthRepositoryImpl::new(pool.clone())));
+    let cpu_pool = Arc::new(CpuPool::new(&config.cpu_pool));
+    let auth_service = web::Data::new(AuthService::new(
+        AuthRepositoryImpl::new(pool.clone()),
+        LocalPasswordResetNotifier::new(config.password_reset.outbox_path.clone()),
+        &config,
+        cpu_pool.clone(),
+    ));
+    // セッションキャッシュを共有するため、ミドルウェアとハンドラーで同じインスタンスを使う
+    let auth_service_for_middleware = auth_service.clone().into_inner();
     let tow_truck_service = web::Data::new(TowTruckService::new(
         TowTruckRepositoryImpl::new(pool.clone()),
         OrderRepositoryImpl::new(pool.clone()),
         MapRepositoryImpl::new(pool.clone()),
+        AuthRepositoryImpl::new(pool.clone()),
+        config.stale_tow_truck_threshold_secs,
+        config.dispatch.clone(),
+        tow_truck_events.clone(),
     ));
     let order_service = web::Data::new(OrderService::new(
         OrderRepositoryImpl::new(pool.clone()),
         TowTruckRepositoryImpl::new(pool.clone()),
         AuthRepositoryImpl::new(pool.clone()),
         MapRepositoryImpl::new(pool.clone()),
+        config.dispatch.clone(),
+        tow_truck_events.clone(),
+        order_events.clone(),
     ));
     let map_service = web::Data::new(MapService::new(MapRepositoryImpl::new(pool.clone())));
+    let shift_service = web::Data::new(ShiftService::new(
+        ShiftRepositoryImpl::new(pool.clone()),
+        TowTruckRepositoryImpl::new(pool.clone()),
+    ));
+
+    let webhook_service = web::Data::new(WebhookService::new(
+        WebhookRepositoryImpl::new(pool.clone()),
+        HttpClient::new(std::time::Duration::from_secs(
+            config.webhook.request_timeout_secs,
+        )),
+        config.webhook.clone(),
+    ));
+
+    jobs::stale_tow_truck_job::spawn(
+        tow_truck_service.clone(),
+        config.stale_tow_truck_check_interval_secs,
+    );
+    jobs::session_purge_job::spawn(auth_service.clone(), config.session.purge_interval_secs);
+    jobs::order_offer_job::spawn(
+        order_service.clone(),
+        config.order_offer_check_interval_secs,
+    );
+    jobs::webhook_job::spawn(
+        webhook_service.clone(),
+        &order_events,
+        &tow_truck_events,
+        config.webhook.delivery_interval_secs,
+    );
 
-    let server =
-        HttpServer::new(move || {
-            let mut cors = Cors::default();
+    let server = HttpServer::new(move || {
+        let mut cors = Cors::default();
 
-            cors = cors
-                .allowed_methods(vec!["GET", "POST", "PUT", "DELETE"])
-                .allowed_headers(vec![
-                    actix_web::http::header::AUTHORIZATION,
-                    actix_web::http::header::ACCEPT,
-                ])
-                .allowed_header(actix_web::http::header::CONTENT_TYPE)
-                .supports_credentials()
-                .max_age(3600);
+        cors = cors
+            .allowed_methods(vec!["GET", "POST", "PUT", "PATCH", "DELETE"])
+            .allowed_headers(vec![
+                actix_web::http::header::AUTHORIZATION,
+                actix_web::http::header::ACCEPT,
+            ])
+            .allowed_header(actix_web::http::header::CONTENT_TYPE)
+            .supports_credentials()
+            .max_age(3600);
 
-            App::new()
-                .app_data(tow_truck_service.clone())
-                .app_data(auth_service.clone())
-                .app_data(order_service.clone())
-                .app_data(map_service.clone())
-                .wrap(cors)
-                .service(
-                    web::scope("/api")
-                        .service(
-                            web::resource("/health_check")
-                                .route(web::get().to(health_check_handler::health_check_handler)),
-                        )
-                        .service(
-                            web::resource("/result")
-                                .route(web::get().to(result_handler::result_handler)),
-                        )
-                        .service(
-                            web::resource("/register")
-                                .route(web::post().to(auth_handler::register_handler)),
-                        )
-                        .service(
-                            web::resource("/login")
-                                .route(web::post().to(auth_handler::login_handler)),
-                        )
-                        .service(
-                            web::resource("/logout")
-                                .route(web::post().to(auth_handler::logout_handler)),
-                        )
-                        .service(
-                            web::resource("/user_image/{user_id}")
-                                .route(web::get().to(auth_handler::user_profile_image_handler)),
-                        )
-                        .service(
-                            web::scope("/tow_truck")
-                                .wrap(AuthMiddleware::new(auth_service_for_middleware.clone()))
-                                .service(
-                                    web::resource("/list").route(
-                                        web::get().to(
-                                            tow_truck_handler::get_paginated_tow_trucks_handler,
-                                        ),
-                                    ),
-                                )
-                                .service(web::resource("/location").route(
-                                    web::post().to(tow_truck_handler::update_location_handler),
-                                ))
-                                .service(web::resource("/nearest").route(web::get().to(
-                                    tow_truck_handler::get_nearest_available_tow_trucks_handler,
-                                )))
-                                .service(web::resource("/{id}").route(
-                                    web::get().to(tow_truck_handler::get_tow_truck_handler),
-                                )),
-                        )
-                        .service(
-                            web::scope("/order")
-                                .wrap(AuthMiddleware::new(auth_service_for_middleware.clone()))
-                                .service(web::resource("/list").route(
-                                    web::get().to(order_handler::get_paginated_orders_handler),
-                                ))
-                                .service(web::resource("/status").route(
-                                    web::post().to(order_handler::update_order_status_handler),
-                                ))
-                                .service(web::resource("/client").route(
-                                    web::post().to(order_handler::create_client_order_handler),
-                                ))
-                                .service(web::resource("/dispatcher").route(
-                                    web::post().to(order_handler::create_dispatcher_order_handler),
-                                ))
-                                .service(
-                                    web::resource("/{id}")
-                                        .route(web::get().to(order_handler::get_order_handler)),
-                                ),
-                        )
-                        .service(
-                            web::scope("/map")
-                                .wrap(AuthMiddleware::new(auth_service_for_middleware.clone()))
-                                .service(
-                                    web::resource("/update_edge")
-                                        .route(web::put().to(map_handler::update_edge_handler)),
-                                ),
-                        ),
-                )
-        })
-        .bind_uds(sock_path)
-        .expect("Failed to bind uds");
+        App::new()
+            .app_data(tow_truck_service.clone())
+            .app_data(auth_service.clone())
+            .app_data(order_service.clone())
+            .app_data(map_service.clone())
+            .app_data(shift_service.clone())
+            .app_data(webhook_service.clone())
+            .wrap(cors)
+            .configure(|cfg| routes::configure(cfg, auth_service_for_middleware.clone()))
+    })
+    .bind_uds(sock_path)
+    .expect("Failed to bind uds");
 
     // 755 -> 777 にする
     while fs::metadata(sock_path).is_err() {
@@ -1,21 +1,67 @@
+use std::rc::Rc;
 use std::sync::Arc;
 
 use actix_web::{
     dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform},
-    Error,
+    http::Method,
+    web, Error, FromRequest, HttpMessage, HttpRequest,
 };
 use futures_util::future::{ready, LocalBoxFuture, Ready};
+use serde::Deserialize;
 
 use crate::{
-    domains::auth_service::AuthService, repositories::auth_repository::AuthRepositoryImpl,
+    domains::auth_service::AuthService, errors::AppError,
+    infrastructure::password_reset_notifier::LocalPasswordResetNotifier, models::user::Principal,
+    repositories::auth_repository::AuthRepositoryImpl,
 };
 
+#[derive(Deserialize)]
+struct SessionTokenQuery {
+    session_token: String,
+}
+
+// EventSource はヘッダーを付けられないので、SSE のルートに限りクエリパラメータのトークンも受け付ける
+// (URL はアクセスログなどに残るので、それ以外のルートでは受け付けない)
+const QUERY_TOKEN_PATHS: [&str; 2] = ["/api/tow_truck/stream", "/api/order/stream"];
+
+pub fn extract_session_token(req: &HttpRequest) -> Option<String> {
+    req.headers()
+        .get("Authorization")
+        .and_then(|h| h.to_str().ok())
+        .map(|s| s.to_string())
+        .or_else(|| {
+            if !QUERY_TOKEN_PATHS.contains(&req.path()) {
+                return None;
+            }
+            web::Query::<SessionTokenQuery>::from_query(req.query_string())
+                .ok()
+                .map(|query| query.into_inner().session_token)
+        })
+}
+
+// AuthMiddleware の内側のハンドラーで、ログイン中のユーザーを引数として受け取る
+impl FromRequest for Principal {
+    type Error = AppError;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut actix_web::dev::Payload) -> Self::Future {
+        ready(
+            req.extensions()
+                .get::<Principal>()
+                .cloned()
+                .ok_or(AppError::Unauthorized),
+        )
+    }
+}
+
 pub struct AuthMiddleware {
-    auth_service: Arc<AuthService<AuthRepositoryImpl>>,
+    auth_service: Arc<AuthService<AuthRepositoryImpl, LocalPasswordResetNotifier>>,
 }
 
 impl AuthMiddleware {
-    pub fn new(auth_service: Arc<AuthService<AuthRepositoryImpl>>) -> Self {
+    pub fn new(
+        auth_service: Arc<AuthService<AuthRepositoryImpl, LocalPasswordResetNotifier>>,
+    ) -> Self {
         AuthMiddleware { auth_service }
     }
 }
@@ -34,15 +80,15 @@ where
 
     fn new_transform(&self, service: S) -> Self::Future {
         ready(Ok(AuthMiddlewareMiddleware {
-            service,
+            service: Rc::new(service),
             auth_service: self.auth_service.clone(),
         }))
     }
 }
 
 pub struct AuthMiddlewareMiddleware<S> {
-    service: S,
-    auth_service: Arc<AuthService<AuthRepositoryImpl>>,
+    service: Rc<S>,
+    auth_service: Arc<AuthService<AuthRepositoryImpl, LocalPasswordResetNotifier>>,
 }
 
 impl<S, B> Service<ServiceRequest> for AuthMiddlewareMiddleware<S>
@@ -57,28 +103,43 @@ where
     forward_ready!(service);
 
     fn call(&self, req: ServiceRequest) -> Self::Future {
-        let auth_header = req
-            .headers()
-            .get("Authorization")
-            .and_then(|h| h.to_str().ok())
-            .map(|s| s.to_string());
-        let auth_header = Arc::new(auth_header);
+        let auth_header = extract_session_token(req.request());
 
         let auth_service = self.auth_service.clone();
-        let fut = self.service.call(req);
+        let service = self.service.clone();
 
         Box::pin(async move {
-            let is_valid_token = match &*auth_header {
-                Some(token) => auth_service.validate_session(token).await.is_ok(),
-                None => false,
+            // 後続のロール判定やハンドラーのために Principal をリクエストに持たせる
+            // DB の障害などは認証の失敗にせず、そのままエラーとして返す
+            let principal = match &auth_header {
+                Some(token) => match auth_service.validate_session(token).await {
+                    Ok(principal) => Some(principal),
+                    Err(AppError::Unauthorized) => None,
+                    Err(err) => return Err(err.into()),
+                },
+                None => None,
             };
 
-            if is_valid_token {
-                fut.await
-            } else {
-                Err(actix_web::error::ErrorUnauthorized(
+            match principal {
+                Some(principal) => {
+                    // なりすまし中の更新系リクエストは、監査ログに残せなければ通さない
+                    if principal.impersonator_id.is_some()
+                        && !matches!(*req.method(), Method::GET | Method::HEAD | Method::OPTIONS)
+                    {
+                        auth_service
+                            .record_impersonated_request(
+                                &principal,
+                                req.method().as_str(),
+                                req.path(),
+                            )
+                            .await?;
+                    }
+                    req.extensions_mut().insert(principal);
+                    service.call(req).await
+                }
+                None => Err(actix_web::error::ErrorUnauthorized(
                     "Invalid or missing token",
-                ))
+                )),
             }
         })
     }
@@ -0,0 +1,13 @@
+use chrono::{DateTime, Utc};
+use sqlx::FromRow;
+
+#[derive(FromRow, Clone, Debug)]
+pub struct GeofenceEvent {
+    pub id: i32,
+    pub tow_truck_id: i32,
+    pub area_id: i32,
+    pub node_id: i32,
+    pub node_area_id: i32,
+    pub event_type: String,
+    pub created_at: DateTime<Utc>,
+}
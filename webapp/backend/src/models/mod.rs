@@ -1,4 +1,7 @@
+pub mod geofence;
 pub mod graph;
 pub mod order;
+pub mod shift;
 pub mod tow_truck;
 pub mod user;
+pub mod webhook;
@@ -1,6 +1,8 @@
 use chrono::{DateTime, Utc};
 use sqlx::FromRow;
 
+use super::tow_truck::VehicleClass;
+
 #[derive(FromRow, Clone, Debug)]
 pub struct Order {
     pub id: i32,
@@ -10,10 +12,27 @@ pub struct Order {
     pub status: String,
     pub node_id: i32,
     pub car_value: f64,
+    pub required_vehicle_class: Option<String>,
+    pub sponsor: Option<String>,
     pub order_time: DateTime<Utc>,
     pub completed_time: Option<DateTime<Utc>>,
 }
 
+impl Order {
+    // 明示的な指定がなければ、車の価格が閾値以上の依頼に flatbed を要求する
+    pub fn required_vehicle_class(&self, flatbed_car_value_threshold: f64) -> VehicleClass {
+        match self
+            .required_vehicle_class
+            .as_deref()
+            .and_then(|class| class.parse().ok())
+        {
+            Some(class) => class,
+            None if self.car_value >= flatbed_car_value_threshold => VehicleClass::Flatbed,
+            None => VehicleClass::WheelLift,
+        }
+    }
+}
+
 #[derive(FromRow, Clone, Debug)]
 pub struct CompletedOrder {
     pub id: i32,
@@ -23,3 +42,16 @@ pub struct CompletedOrder {
     pub completed_time: DateTime<Utc>,
     pub car_value: f64,
 }
+
+// status: pending | accepted | declined | expired
+#[derive(FromRow, Clone, Debug)]
+pub struct OrderOffer {
+    pub id: i32,
+    pub order_id: i32,
+    pub tow_truck_id: i32,
+    pub dispatcher_id: i32,
+    pub status: String,
+    pub created_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+    pub responded_at: Option<DateTime<Utc>>,
+}
@@ -0,0 +1,22 @@
+use chrono::{DateTime, Utc};
+use sqlx::FromRow;
+
+#[derive(FromRow, Clone, Debug)]
+pub struct Shift {
+    pub id: i32,
+    pub driver_id: i32,
+    pub tow_truck_id: i32,
+    pub started_at: DateTime<Utc>,
+    pub ended_at: Option<DateTime<Utc>>,
+}
+
+#[derive(FromRow, Clone, Debug)]
+pub struct OnDutyDriver {
+    pub shift_id: i32,
+    pub driver_id: i32,
+    pub driver_username: String,
+    pub tow_truck_id: i32,
+    pub tow_truck_status: String,
+    pub area_id: i32,
+    pub started_at: DateTime<Utc>,
+}
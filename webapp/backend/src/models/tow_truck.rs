@@ -1,11 +1,81 @@
+use std::str::FromStr;
+
+use chrono::{DateTime, Utc};
 use sqlx::FromRow;
 
+// 上位のクラスは下位のクラスの依頼にも対応できる
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum VehicleClass {
+    WheelLift,
+    Flatbed,
+    Heavy,
+}
+
+impl VehicleClass {
+    pub const ALL: [VehicleClass; 3] = [
+        VehicleClass::WheelLift,
+        VehicleClass::Flatbed,
+        VehicleClass::Heavy,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            VehicleClass::WheelLift => "wheel_lift",
+            VehicleClass::Flatbed => "flatbed",
+            VehicleClass::Heavy => "heavy",
+        }
+    }
+
+    // このクラスを要求する依頼に対応できる車両クラス
+    pub fn compatible_classes(&self) -> Vec<VehicleClass> {
+        VehicleClass::ALL
+            .into_iter()
+            .filter(|class| class >= self)
+            .collect()
+    }
+}
+
+impl FromStr for VehicleClass {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "wheel_lift" => Ok(VehicleClass::WheelLift),
+            "flatbed" => Ok(VehicleClass::Flatbed),
+            "heavy" => Ok(VehicleClass::Heavy),
+            _ => Err(()),
+        }
+    }
+}
+
 #[derive(FromRow, Clone, Debug)]
 pub struct TowTruck {
+    pub id: i32,
+    pub driver_id: i32,
+    pub driver_username: Option<String>,
+    pub status: String,
+    pub vehicle_class: String,
+    pub area_id: i32,
+    pub node_id: i32,
+}
+
+// 位置情報を更新するレッカー車と、ジオフェンスの判定に使うエリア
+#[derive(FromRow, Clone, Debug)]
+pub struct LocationUpdateTarget {
+    #[sqlx(flatten)]
+    pub tow_truck: TowTruck,
+    pub previous_area_id: i32,
+    // 存在しないノードなら NULL
+    pub node_area_id: Option<i32>,
+}
+
+#[derive(FromRow, Clone, Debug)]
+pub struct StaleTowTruck {
     pub id: i32,
     pub driver_id: i32,
     pub driver_username: Option<String>,
     pub status: String,
     pub area_id: i32,
     pub node_id: i32,
+    pub last_located_at: DateTime<Utc>,
 }
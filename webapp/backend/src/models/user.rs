@@ -1,5 +1,72 @@
+use std::str::FromStr;
+
+use chrono::{DateTime, Utc};
 use sqlx::FromRow;
 
+use crate::errors::AppError;
+
+// users.role の値 (admin はすべての操作を行える)
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Role {
+    Client,
+    Dispatcher,
+    Driver,
+    Admin,
+}
+
+impl FromStr for Role {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "client" => Ok(Role::Client),
+            "dispatcher" => Ok(Role::Dispatcher),
+            "driver" => Ok(Role::Driver),
+            "admin" => Ok(Role::Admin),
+            _ => Err(()),
+        }
+    }
+}
+
+// セッションから解決したリクエストの主体
+#[derive(Clone, Debug)]
+pub struct Principal {
+    pub user_id: i32,
+    pub role: Role,
+    pub dispatcher_id: Option<i32>,
+    // ディスパッチャーの担当エリア (複数のエリアを受け持つこともある)
+    pub area_ids: Vec<i32>,
+    pub tow_truck_id: Option<i32>,
+    // admin がなりすましているセッションなら、その admin のユーザー ID
+    pub impersonator_id: Option<i32>,
+}
+
+impl Principal {
+    // ディスパッチャーは担当エリアのデータだけを扱える (それ以外のロールはエリア単位では扱えない)
+    pub fn can_access_area(&self, area_id: i32) -> bool {
+        match self.role {
+            Role::Admin => true,
+            Role::Dispatcher => self.area_ids.contains(&area_id),
+            Role::Client | Role::Driver => false,
+        }
+    }
+
+    // エリアの指定がなければディスパッチャーは担当エリアすべてに絞り込む
+    // None ならエリアで絞り込まない
+    pub fn scope_areas(&self, area_id: Option<i32>) -> Result<Option<Vec<i32>>, AppError> {
+        match (self.role, area_id) {
+            (Role::Admin, area_id) => Ok(area_id.map(|area_id| vec![area_id])),
+            (Role::Dispatcher, Some(area_id)) if self.can_access_area(area_id) => {
+                Ok(Some(vec![area_id]))
+            }
+            (Role::Dispatcher, None) if !self.area_ids.is_empty() => {
+                Ok(Some(self.area_ids.clone()))
+            }
+            _ => Err(AppError::Forbidden),
+        }
+    }
+}
+
 #[derive(FromRow, Clone, Debug)]
 pub struct User {
     pub id: i32,
@@ -7,6 +74,13 @@ pub struct User {
     pub password: String,
     pub profile_image: String,
     pub role: String,
+    // 退会済みなら匿名化した日時
+    pub deleted_at: Option<DateTime<Utc>>,
+    // admin に無効にされていればその日時
+    pub disabled_at: Option<DateTime<Utc>>,
+    // 2 段階認証の共有鍵 (16進)。totp_enabled_at が入るまでは登録の確認待ち
+    pub totp_secret: Option<String>,
+    pub totp_enabled_at: Option<DateTime<Utc>>,
 }
 
 #[derive(FromRow, Clone, Debug)]
@@ -15,6 +89,24 @@ pub struct Session {
     pub user_id: i32,
     pub session_token: String,
     pub is_valid: bool,
+    pub created_at: DateTime<Utc>,
+    pub last_used_at: DateTime<Utc>,
+    // admin がなりすましで発行したセッション
+    pub impersonator_id: Option<i32>,
+}
+
+// セッションの検証でユーザーとロールごとの担当を 1 回のクエリで読む
+#[derive(FromRow, Clone, Debug)]
+pub struct SessionPrincipalRow {
+    pub session_id: i32,
+    pub last_used_at: DateTime<Utc>,
+    pub user_id: i32,
+    pub role: String,
+    pub dispatcher_id: Option<i32>,
+    // 担当エリアの id のカンマ区切り
+    pub area_ids: Option<String>,
+    pub tow_truck_id: Option<i32>,
+    pub impersonator_id: Option<i32>,
 }
 
 #[derive(FromRow, Clone, Debug)]
@@ -25,9 +117,93 @@ pub struct Driver {
     pub is_valid: bool,
 }
 
+// admin の操作と同じトランザクションで書き込む監査ログ
+#[derive(Clone, Debug)]
+pub struct AdminAuditEntry {
+    pub admin_user_id: i32,
+    pub action: &'static str,
+    pub target_user_id: Option<i32>,
+    // JSON
+    pub details: String,
+}
+
+#[derive(FromRow, Clone, Debug)]
+pub struct AdminAuditLog {
+    pub id: i32,
+    pub admin_user_id: i32,
+    pub action: String,
+    pub target_user_id: Option<i32>,
+    // JSON
+    pub details: String,
+    pub created_at: DateTime<Utc>,
+}
+
 #[derive(FromRow, Clone, Debug)]
 pub struct Dispatcher {
     pub id: i32,
     pub user_id: i32,
-    pub area_id: i32,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Principal, Role};
+    use crate::errors::AppError;
+
+    fn principal(role: Role, area_ids: Vec<i32>) -> Principal {
+        Principal {
+            user_id: 1,
+            role,
+            dispatcher_id: (role == Role::Dispatcher).then_some(1),
+            area_ids,
+            tow_truck_id: (role == Role::Driver).then_some(1),
+            impersonator_id: None,
+        }
+    }
+
+    #[test]
+    fn admin_can_access_every_area() {
+        let admin = principal(Role::Admin, vec![]);
+        assert!(admin.can_access_area(1));
+        assert_eq!(admin.scope_areas(None).unwrap(), None);
+        assert_eq!(admin.scope_areas(Some(3)).unwrap(), Some(vec![3]));
+    }
+
+    #[test]
+    fn dispatcher_is_scoped_to_assigned_areas() {
+        let dispatcher = principal(Role::Dispatcher, vec![1, 2]);
+        assert!(dispatcher.can_access_area(2));
+        assert!(!dispatcher.can_access_area(3));
+        assert_eq!(dispatcher.scope_areas(None).unwrap(), Some(vec![1, 2]));
+        assert_eq!(dispatcher.scope_areas(Some(1)).unwrap(), Some(vec![1]));
+        assert!(matches!(
+            dispatcher.scope_areas(Some(3)),
+            Err(AppError::Forbidden)
+        ));
+    }
+
+    #[test]
+    fn dispatcher_without_areas_is_denied() {
+        let dispatcher = principal(Role::Dispatcher, vec![]);
+        assert!(!dispatcher.can_access_area(1));
+        assert!(matches!(
+            dispatcher.scope_areas(None),
+            Err(AppError::Forbidden)
+        ));
+    }
+
+    #[test]
+    fn client_and_driver_are_denied_every_area() {
+        for role in [Role::Client, Role::Driver] {
+            let principal = principal(role, vec![]);
+            assert!(!principal.can_access_area(1));
+            assert!(matches!(
+                principal.scope_areas(None),
+                Err(AppError::Forbidden)
+            ));
+            assert!(matches!(
+                principal.scope_areas(Some(1)),
+                Err(AppError::Forbidden)
+            ));
+        }
+    }
 }
@@ -0,0 +1,39 @@
+use chrono::{DateTime, Utc};
+use sqlx::FromRow;
+
+#[derive(FromRow, Clone, Debug)]
+pub struct WebhookSubscription {
+    pub id: i32,
+    pub url: String,
+    pub secret: String,
+    // カンマ区切りのイベント種別
+    pub event_types: String,
+    pub sponsor: Option<String>,
+    pub is_active: bool,
+    pub created_at: DateTime<Utc>,
+}
+
+impl WebhookSubscription {
+    pub fn event_types(&self) -> Vec<String> {
+        self.event_types
+            .split(',')
+            .filter(|event_type| !event_type.is_empty())
+            .map(str::to_string)
+            .collect()
+    }
+}
+
+#[derive(FromRow, Clone, Debug)]
+pub struct WebhookDelivery {
+    pub id: i32,
+    pub subscription_id: i32,
+    pub event_type: String,
+    pub payload: String,
+    pub status: String,
+    pub attempts: i32,
+    pub response_status: Option<i32>,
+    pub last_error: Option<String>,
+    pub next_attempt_at: DateTime<Utc>,
+    pub created_at: DateTime<Utc>,
+    pub delivered_at: Option<DateTime<Utc>>,
+}
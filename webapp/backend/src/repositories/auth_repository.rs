@@ -1,7 +1,8 @@
 use crate::errors::AppError;
-use crate::models::user::{Dispatcher, User};
+use crate::models::user::{AdminAuditEntry, AdminAuditLog, Dispatcher, SessionPrincipalRow, User};
 use crate::{domains::auth_service::AuthRepository, models::user::Session};
 use sqlx::mysql::MySqlPool;
+use sqlx::{MySql, Transaction};
 
 #[derive(Debug)]
 pub struct AuthRepositoryImpl {
@@ -69,18 +70,6 @@ impl AuthRepository for AuthRepositoryImpl {
         Ok(user)
     }
 
-    async fn find_profile_image_name_by_user_id(
-        &self,
-        user_id: i32,
-    ) -> Result<Option<String>, AppError> {
-        let profile_image_name = sqlx::query_scalar("SELECT profile_image FROM users WHERE id = ?")
-            .bind(user_id)
-            .fetch_optional(&self.pool)
-            .await?;
-
-        Ok(profile_image_name)
-    }
-
     async fn authenticate_user(&self, username: &str, password: &str) -> Result<User, AppError> {
         let user =
             sqlx::query_as::<_, User>("SELECT * FROM users WHERE username = ? AND password = ?")
@@ -92,6 +81,22 @@ impl AuthRepository for AuthRepositoryImpl {
         Ok(user)
     }
 
+    async fn update_user_password_hash(
+        &self,
+        user_id: i32,
+        current_hash: &str,
+        new_hash: &str,
+    ) -> Result<(), AppError> {
+        sqlx::query("UPDATE users SET password = ? WHERE id = ? AND password = ?")
+            .bind(new_hash)
+            .bind(user_id)
+            .bind(current_hash)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
     async fn create_user(
         &self,
         username: &str,
@@ -127,19 +132,163 @@ impl AuthRepository for AuthRepositoryImpl {
         Ok(())
     }
 
-    async fn find_session_by_session_token(
+    async fn find_active_session_by_session_token(
         &self,
         session_token: &str,
-    ) -> Result<Session, AppError> {
-        let session =
-            sqlx::query_as::<_, Session>("SELECT * FROM sessions WHERE session_token = ?")
-                .bind(session_token)
-                .fetch_one(&self.pool)
-                .await?;
+        absolute_ttl_secs: i64,
+        idle_ttl_secs: i64,
+        impersonation_ttl_secs: i64,
+    ) -> Result<Option<Session>, AppError> {
+        let session = sqlx::query_as::<_, Session>(
+            "SELECT
+                *
+            FROM
+                sessions
+            WHERE
+                session_token = ?
+                AND is_valid = TRUE
+                AND created_at > NOW() - INTERVAL IF(impersonator_id IS NULL, ?, ?) SECOND
+                AND last_used_at > NOW() - INTERVAL ? SECOND",
+        )
+        .bind(session_token)
+        .bind(absolute_ttl_secs)
+        .bind(impersonation_ttl_secs)
+        .bind(idle_ttl_secs)
+        .fetch_optional(&self.pool)
+        .await?;
 
         Ok(session)
     }
 
+    async fn find_session_principal(
+        &self,
+        session_token: &str,
+        absolute_ttl_secs: i64,
+        idle_ttl_secs: i64,
+        impersonation_ttl_secs: i64,
+    ) -> Result<Option<SessionPrincipalRow>, AppError> {
+        let row = sqlx::query_as::<_, SessionPrincipalRow>(
+            "SELECT
+                s.id AS session_id,
+                s.last_used_at,
+                u.id AS user_id,
+                u.role,
+                d.id AS dispatcher_id,
+                (
+                    SELECT CAST(GROUP_CONCAT(da.area_id ORDER BY da.area_id) AS CHAR)
+                    FROM dispatcher_areas da
+                    WHERE da.dispatcher_id = d.id
+                ) AS area_ids,
+                (
+                    SELECT tt.id
+                    FROM tow_trucks tt
+                    WHERE tt.driver_id = u.id AND tt.retired_at IS NULL
+                    LIMIT 1
+                ) AS tow_truck_id,
+                s.impersonator_id
+            FROM
+                sessions s
+                JOIN users u ON u.id = s.user_id
+                LEFT JOIN dispatchers d ON d.user_id = u.id
+            WHERE
+                s.session_token = ?
+                AND s.is_valid = TRUE
+                AND s.created_at > NOW() - INTERVAL IF(s.impersonator_id IS NULL, ?, ?) SECOND
+                AND s.last_used_at > NOW() - INTERVAL ? SECOND
+                AND u.deleted_at IS NULL
+                AND u.disabled_at IS NULL",
+        )
+        .bind(session_token)
+        .bind(absolute_ttl_secs)
+        .bind(impersonation_ttl_secs)
+        .bind(idle_ttl_secs)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(row)
+    }
+
+    async fn touch_session(&self, session_id: i32) -> Result<(), AppError> {
+        sqlx::query("UPDATE sessions SET last_used_at = NOW() WHERE id = ?")
+            .bind(session_id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn get_active_sessions_by_user_id(
+        &self,
+        user_id: i32,
+        absolute_ttl_secs: i64,
+        idle_ttl_secs: i64,
+        impersonation_ttl_secs: i64,
+    ) -> Result<Vec<Session>, AppError> {
+        let sessions = sqlx::query_as::<_, Session>(
+            "SELECT
+                *
+            FROM
+                sessions
+            WHERE
+                user_id = ?
+                AND is_valid = TRUE
+                AND created_at > NOW() - INTERVAL IF(impersonator_id IS NULL, ?, ?) SECOND
+                AND last_used_at > NOW() - INTERVAL ? SECOND
+            ORDER BY
+                last_used_at DESC",
+        )
+        .bind(user_id)
+        .bind(absolute_ttl_secs)
+        .bind(impersonation_ttl_secs)
+        .bind(idle_ttl_secs)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(sessions)
+    }
+
+    async fn delete_session_by_id(&self, user_id: i32, session_id: i32) -> Result<bool, AppError> {
+        let result = sqlx::query("DELETE FROM sessions WHERE id = ? AND user_id = ?")
+            .bind(session_id)
+            .bind(user_id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(result.rows_affected() == 1)
+    }
+
+    async fn delete_sessions_by_user_id(&self, user_id: i32) -> Result<u64, AppError> {
+        let result = sqlx::query("DELETE FROM sessions WHERE user_id = ?")
+            .bind(user_id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(result.rows_affected())
+    }
+
+    async fn delete_expired_sessions(
+        &self,
+        absolute_ttl_secs: i64,
+        idle_ttl_secs: i64,
+        impersonation_ttl_secs: i64,
+    ) -> Result<u64, AppError> {
+        let result = sqlx::query(
+            "DELETE FROM
+                sessions
+            WHERE
+                is_valid = FALSE
+                OR created_at <= NOW() - INTERVAL IF(impersonator_id IS NULL, ?, ?) SECOND
+                OR last_used_at <= NOW() - INTERVAL ? SECOND",
+        )
+        .bind(absolute_ttl_secs)
+        .bind(impersonation_ttl_secs)
+        .bind(idle_ttl_secs)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected())
+    }
+
     async fn find_dispatcher_by_id(&self, id: i32) -> Result<Option<Dispatcher>, AppError> {
         let dispatcher = sqlx::query_as::<_, Dispatcher>("SELECT * FROM dispatchers WHERE id = ?")
             .bind(id)
@@ -162,13 +311,602 @@ impl AuthRepository for AuthRepositoryImpl {
         Ok(dispatcher)
     }
 
-    async fn create_dispatcher(&self, user_id: i32, area_id: i32) -> Result<(), AppError> {
-        sqlx::query("INSERT INTO dispatchers (user_id, area_id) VALUES (?, ?)")
+    async fn create_dispatcher(&self, user_id: i32, area_ids: &[i32]) -> Result<(), AppError> {
+        let mut tx = self.pool.begin().await?;
+
+        let dispatcher_id = sqlx::query("INSERT INTO dispatchers (user_id) VALUES (?)")
+            .bind(user_id)
+            .execute(&mut tx)
+            .await?
+            .last_insert_id() as i32;
+
+        for area_id in area_ids {
+            sqlx::query("INSERT INTO dispatcher_areas (dispatcher_id, area_id) VALUES (?, ?)")
+                .bind(dispatcher_id)
+                .bind(area_id)
+                .execute(&mut tx)
+                .await?;
+        }
+
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    async fn find_area_ids_by_dispatcher_id(
+        &self,
+        dispatcher_id: i32,
+    ) -> Result<Vec<i32>, AppError> {
+        let area_ids = sqlx::query_scalar::<_, i32>(
+            "SELECT area_id FROM dispatcher_areas WHERE dispatcher_id = ? ORDER BY area_id",
+        )
+        .bind(dispatcher_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(area_ids)
+    }
+
+    async fn find_tow_truck_id_by_driver_id(
+        &self,
+        driver_id: i32,
+    ) -> Result<Option<i32>, AppError> {
+        let tow_truck_id = sqlx::query_scalar::<_, i32>(
+            "SELECT id FROM tow_trucks WHERE driver_id = ? AND retired_at IS NULL LIMIT 1",
+        )
+        .bind(driver_id)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(tow_truck_id)
+    }
+
+    async fn update_user_password(&self, user_id: i32, password: &str) -> Result<(), AppError> {
+        sqlx::query("UPDATE users SET password = ? WHERE id = ?")
+            .bind(password)
+            .bind(user_id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn delete_other_sessions_by_user_id(
+        &self,
+        user_id: i32,
+        keep_session_token: &str,
+    ) -> Result<u64, AppError> {
+        let result = sqlx::query("DELETE FROM sessions WHERE user_id = ? AND session_token <> ?")
+            .bind(user_id)
+            .bind(keep_session_token)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(result.rows_affected())
+    }
+
+    async fn create_password_reset_token(
+        &self,
+        username: &str,
+        token_hash: &str,
+    ) -> Result<bool, AppError> {
+        let result = sqlx::query(
+            "INSERT INTO password_reset_tokens (user_id, token_hash)
+            SELECT id, ? FROM users WHERE username = ?",
+        )
+        .bind(token_hash)
+        .bind(username)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected() == 1)
+    }
+
+    async fn reset_password_with_token(
+        &self,
+        token_hash: &str,
+        ttl_secs: i64,
+        hashed_password: &str,
+    ) -> Result<Option<i32>, AppError> {
+        let mut tx = self.pool.begin().await?;
+
+        let user_id = sqlx::query_scalar::<_, i32>(
+            "SELECT
+                user_id
+            FROM
+                password_reset_tokens
+            WHERE
+                token_hash = ?
+                AND used_at IS NULL
+                AND created_at > NOW() - INTERVAL ? SECOND
+            FOR UPDATE",
+        )
+        .bind(token_hash)
+        .bind(ttl_secs)
+        .fetch_optional(&mut tx)
+        .await?;
+        let Some(user_id) = user_id else {
+            return Ok(None);
+        };
+
+        sqlx::query(
+            "UPDATE password_reset_tokens SET used_at = NOW() WHERE user_id = ? AND used_at IS NULL",
+        )
+        .bind(user_id)
+        .execute(&mut tx)
+        .await?;
+
+        sqlx::query("UPDATE users SET password = ? WHERE id = ?")
+            .bind(hashed_password)
+            .bind(user_id)
+            .execute(&mut tx)
+            .await?;
+
+        tx.commit().await?;
+
+        Ok(Some(user_id))
+    }
+
+    async fn delete_expired_password_reset_tokens(&self, ttl_secs: i64) -> Result<u64, AppError> {
+        let result = sqlx::query(
+            "DELETE FROM
+                password_reset_tokens
+            WHERE
+                used_at IS NOT NULL
+                OR created_at <= NOW() - INTERVAL ? SECOND",
+        )
+        .bind(ttl_secs)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected())
+    }
+
+    async fn update_profile_image_name(
+        &self,
+        user_id: i32,
+        profile_image_name: &str,
+    ) -> Result<Option<String>, AppError> {
+        let mut tx = self.pool.begin().await?;
+
+        let previous = sqlx::query_scalar::<_, String>(
+            "SELECT profile_image FROM users WHERE id = ? FOR UPDATE",
+        )
+        .bind(user_id)
+        .fetch_optional(&mut tx)
+        .await?;
+        let Some(previous) = previous else {
+            return Ok(None);
+        };
+
+        sqlx::query("UPDATE users SET profile_image = ? WHERE id = ?")
+            .bind(profile_image_name)
+            .bind(user_id)
+            .execute(&mut tx)
+            .await?;
+
+        tx.commit().await?;
+
+        Ok(Some(previous))
+    }
+
+    async fn count_users_by_profile_image(
+        &self,
+        profile_image_name: &str,
+    ) -> Result<i64, AppError> {
+        let count =
+            sqlx::query_scalar::<_, i64>("SELECT COUNT(*) FROM users WHERE profile_image = ?")
+                .bind(profile_image_name)
+                .fetch_one(&self.pool)
+                .await?;
+
+        Ok(count)
+    }
+
+    async fn update_username(&self, user_id: i32, username: &str) -> Result<(), AppError> {
+        sqlx::query("UPDATE users SET username = ? WHERE id = ?")
+            .bind(username)
             .bind(user_id)
-            .bind(area_id)
             .execute(&self.pool)
+            .await
+            .map_err(AppError::conflict_on_duplicate)?;
+
+        Ok(())
+    }
+
+    async fn anonymize_user(
+        &self,
+        user_id: i32,
+        username: &str,
+        password: &str,
+    ) -> Result<bool, AppError> {
+        let mut tx = self.pool.begin().await?;
+
+        let open_orders = sqlx::query_scalar::<_, i64>(
+            "SELECT COUNT(*) FROM orders WHERE client_id = ? AND status NOT IN ('completed', 'cancelled') FOR UPDATE",
+        )
+        .bind(user_id)
+        .fetch_one(&mut tx)
+        .await?;
+        if open_orders > 0 {
+            return Ok(false);
+        }
+
+        // orders は client_id で users を参照している (ON DELETE CASCADE) ので行は残す
+        sqlx::query(
+            "UPDATE users SET username = ?, password = ?, profile_image = 'default.png', deleted_at = CURRENT_TIMESTAMP, totp_secret = NULL, totp_enabled_at = NULL, totp_last_used_step = NULL WHERE id = ?",
+        )
+        .bind(username)
+        .bind(password)
+        .bind(user_id)
+        .execute(&mut tx)
+        .await?;
+
+        // 依頼の履歴は残すが、車の価格と紹介元は消す
+        sqlx::query("UPDATE orders SET car_value = 0, sponsor = NULL WHERE client_id = ?")
+            .bind(user_id)
+            .execute(&mut tx)
+            .await?;
+
+        sqlx::query("DELETE FROM sessions WHERE user_id = ?")
+            .bind(user_id)
+            .execute(&mut tx)
+            .await?;
+
+        sqlx::query("DELETE FROM password_reset_tokens WHERE user_id = ?")
+            .bind(user_id)
+            .execute(&mut tx)
+            .await?;
+
+        sqlx::query("DELETE FROM totp_recovery_codes WHERE user_id = ?")
+            .bind(user_id)
+            .execute(&mut tx)
+            .await?;
+
+        tx.commit().await?;
+
+        Ok(true)
+    }
+
+    async fn search_users(
+        &self,
+        username_prefix: Option<&str>,
+        role: Option<&str>,
+        page: i32,
+        page_size: i32,
+    ) -> Result<Vec<User>, AppError> {
+        let mut qb = sqlx::QueryBuilder::new("SELECT * FROM users WHERE deleted_at IS NULL");
+        if let Some(username_prefix) = username_prefix {
+            // LIKE のワイルドカードはそのままの文字として扱う
+            let escaped = username_prefix
+                .replace('\\', "\\\\")
+                .replace('%', "\\%")
+                .replace('_', "\\_");
+            qb.push(" AND username LIKE ")
+                .push_bind(format!("{}%", escaped));
+        }
+        if let Some(role) = role {
+            qb.push(" AND role = ").push_bind(role);
+        }
+        qb.push(" ORDER BY id LIMIT ")
+            .push_bind(page_size)
+            .push(" OFFSET ")
+            .push_bind(page * page_size);
+
+        Ok(qb.build_query_as::<User>().fetch_all(&self.pool).await?)
+    }
+
+    async fn update_user_role(
+        &self,
+        user_id: i32,
+        role: &str,
+        area_ids: Option<&[i32]>,
+        audit: &AdminAuditEntry,
+    ) -> Result<(), AppError> {
+        let mut tx = self.pool.begin().await?;
+
+        if let Some(area_ids) = area_ids {
+            replace_dispatcher_areas(&mut tx, user_id, area_ids).await?;
+        }
+
+        sqlx::query("UPDATE users SET role = ? WHERE id = ?")
+            .bind(role)
+            .bind(user_id)
+            .execute(&mut tx)
             .await?;
 
+        insert_admin_audit_log(&mut tx, audit).await?;
+
+        tx.commit().await?;
+
         Ok(())
     }
+
+    async fn replace_dispatcher_areas(
+        &self,
+        user_id: i32,
+        area_ids: &[i32],
+        audit: &AdminAuditEntry,
+    ) -> Result<(), AppError> {
+        let mut tx = self.pool.begin().await?;
+
+        replace_dispatcher_areas(&mut tx, user_id, area_ids).await?;
+        insert_admin_audit_log(&mut tx, audit).await?;
+
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    async fn count_areas_by_ids(&self, area_ids: &[i32]) -> Result<i64, AppError> {
+        if area_ids.is_empty() {
+            return Ok(0);
+        }
+
+        let mut qb = sqlx::QueryBuilder::new("SELECT COUNT(*) FROM areas WHERE id IN (");
+        let mut sep = qb.separated(", ");
+        for id in area_ids {
+            sep.push_bind(id);
+        }
+        sep.push_unseparated(")");
+
+        let (count,) = qb.build_query_as::<(i64,)>().fetch_one(&self.pool).await?;
+
+        Ok(count)
+    }
+
+    async fn set_user_disabled(
+        &self,
+        user_id: i32,
+        disabled: bool,
+        audit: &AdminAuditEntry,
+    ) -> Result<(), AppError> {
+        let mut tx = self.pool.begin().await?;
+
+        let sql = if disabled {
+            "UPDATE users SET disabled_at = CURRENT_TIMESTAMP WHERE id = ? AND disabled_at IS NULL"
+        } else {
+            "UPDATE users SET disabled_at = NULL WHERE id = ?"
+        };
+        sqlx::query(sql).bind(user_id).execute(&mut tx).await?;
+
+        if disabled {
+            sqlx::query("DELETE FROM sessions WHERE user_id = ?")
+                .bind(user_id)
+                .execute(&mut tx)
+                .await?;
+        }
+
+        insert_admin_audit_log(&mut tx, audit).await?;
+
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    async fn create_impersonation_session(
+        &self,
+        user_id: i32,
+        session_token: &str,
+        audit: &AdminAuditEntry,
+    ) -> Result<(), AppError> {
+        let mut tx = self.pool.begin().await?;
+
+        sqlx::query(
+            "INSERT INTO sessions (user_id, session_token, impersonator_id) VALUES (?, ?, ?)",
+        )
+        .bind(user_id)
+        .bind(session_token)
+        .bind(audit.admin_user_id)
+        .execute(&mut tx)
+        .await?;
+
+        insert_admin_audit_log(&mut tx, audit).await?;
+
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    async fn create_admin_audit_log(&self, audit: &AdminAuditEntry) -> Result<(), AppError> {
+        let mut tx = self.pool.begin().await?;
+        insert_admin_audit_log(&mut tx, audit).await?;
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    async fn get_admin_audit_logs(
+        &self,
+        target_user_id: Option<i32>,
+        page: i32,
+        page_size: i32,
+    ) -> Result<Vec<AdminAuditLog>, AppError> {
+        let mut qb = sqlx::QueryBuilder::new("SELECT * FROM admin_audit_logs");
+        if let Some(target_user_id) = target_user_id {
+            qb.push(" WHERE target_user_id = ")
+                .push_bind(target_user_id);
+        }
+        qb.push(" ORDER BY id DESC LIMIT ")
+            .push_bind(page_size)
+            .push(" OFFSET ")
+            .push_bind(page * page_size);
+
+        Ok(qb
+            .build_query_as::<AdminAuditLog>()
+            .fetch_all(&self.pool)
+            .await?)
+    }
+
+    async fn set_pending_totp_secret(&self, user_id: i32, secret: &str) -> Result<bool, AppError> {
+        let result = sqlx::query(
+            "UPDATE users SET totp_secret = ?, totp_last_used_step = NULL WHERE id = ? AND totp_enabled_at IS NULL",
+        )
+        .bind(secret)
+        .bind(user_id)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected() == 1)
+    }
+
+    async fn enable_totp(
+        &self,
+        user_id: i32,
+        secret: &str,
+        used_step: i64,
+        recovery_code_hashes: &[String],
+    ) -> Result<bool, AppError> {
+        let mut tx = self.pool.begin().await?;
+
+        let result = sqlx::query(
+            "UPDATE users SET totp_enabled_at = CURRENT_TIMESTAMP, totp_last_used_step = ? WHERE id = ? AND totp_secret = ? AND totp_enabled_at IS NULL",
+        )
+        .bind(used_step)
+        .bind(user_id)
+        .bind(secret)
+        .execute(&mut tx)
+        .await?;
+        if result.rows_affected() != 1 {
+            return Ok(false);
+        }
+
+        sqlx::query("DELETE FROM totp_recovery_codes WHERE user_id = ?")
+            .bind(user_id)
+            .execute(&mut tx)
+            .await?;
+
+        for code_hash in recovery_code_hashes {
+            sqlx::query("INSERT INTO totp_recovery_codes (user_id, code_hash) VALUES (?, ?)")
+                .bind(user_id)
+                .bind(code_hash)
+                .execute(&mut tx)
+                .await?;
+        }
+
+        tx.commit().await?;
+
+        Ok(true)
+    }
+
+    async fn disable_totp(
+        &self,
+        user_id: i32,
+        audit: Option<&AdminAuditEntry>,
+    ) -> Result<(), AppError> {
+        let mut tx = self.pool.begin().await?;
+
+        sqlx::query(
+            "UPDATE users SET totp_secret = NULL, totp_enabled_at = NULL, totp_last_used_step = NULL WHERE id = ?",
+        )
+        .bind(user_id)
+        .execute(&mut tx)
+        .await?;
+
+        sqlx::query("DELETE FROM totp_recovery_codes WHERE user_id = ?")
+            .bind(user_id)
+            .execute(&mut tx)
+            .await?;
+
+        if let Some(audit) = audit {
+            insert_admin_audit_log(&mut tx, audit).await?;
+        }
+
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    async fn update_totp_last_used_step(&self, user_id: i32, step: i64) -> Result<bool, AppError> {
+        let result = sqlx::query(
+            "UPDATE users SET totp_last_used_step = ? WHERE id = ? AND (totp_last_used_step IS NULL OR totp_last_used_step < ?)",
+        )
+        .bind(step)
+        .bind(user_id)
+        .bind(step)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected() == 1)
+    }
+
+    async fn consume_totp_recovery_code(
+        &self,
+        user_id: i32,
+        code_hash: &str,
+    ) -> Result<bool, AppError> {
+        let result = sqlx::query(
+            "UPDATE totp_recovery_codes SET used_at = CURRENT_TIMESTAMP WHERE user_id = ? AND code_hash = ? AND used_at IS NULL LIMIT 1",
+        )
+        .bind(user_id)
+        .bind(code_hash)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected() == 1)
+    }
+
+    async fn count_unused_totp_recovery_codes(&self, user_id: i32) -> Result<i64, AppError> {
+        let count = sqlx::query_scalar::<_, i64>(
+            "SELECT COUNT(*) FROM totp_recovery_codes WHERE user_id = ? AND used_at IS NULL",
+        )
+        .bind(user_id)
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(count)
+    }
+}
+
+// dispatchers の行がなければ作り、担当エリアを丸ごと入れ替える
+async fn replace_dispatcher_areas(
+    tx: &mut Transaction<'_, MySql>,
+    user_id: i32,
+    area_ids: &[i32],
+) -> Result<(), sqlx::Error> {
+    let dispatcher_id =
+        sqlx::query_scalar::<_, i32>("SELECT id FROM dispatchers WHERE user_id = ? FOR UPDATE")
+            .bind(user_id)
+            .fetch_optional(&mut *tx)
+            .await?;
+    let dispatcher_id = match dispatcher_id {
+        Some(dispatcher_id) => dispatcher_id,
+        None => sqlx::query("INSERT INTO dispatchers (user_id) VALUES (?)")
+            .bind(user_id)
+            .execute(&mut *tx)
+            .await?
+            .last_insert_id() as i32,
+    };
+
+    sqlx::query("DELETE FROM dispatcher_areas WHERE dispatcher_id = ?")
+        .bind(dispatcher_id)
+        .execute(&mut *tx)
+        .await?;
+
+    for area_id in area_ids {
+        sqlx::query("INSERT INTO dispatcher_areas (dispatcher_id, area_id) VALUES (?, ?)")
+            .bind(dispatcher_id)
+            .bind(area_id)
+            .execute(&mut *tx)
+            .await?;
+    }
+
+    Ok(())
+}
+
+async fn insert_admin_audit_log(
+    tx: &mut Transaction<'_, MySql>,
+    audit: &AdminAuditEntry,
+) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        "INSERT INTO admin_audit_logs (admin_user_id, action, target_user_id, details) VALUES (?, ?, ?, ?)",
+    )
+    .bind(audit.admin_user_id)
+    .bind(audit.action)
+    .bind(audit.target_user_id)
+    .bind(&audit.details)
+    .execute(&mut *tx)
+    .await?;
+
+    Ok(())
 }
@@ -1,4 +1,6 @@
 pub mod auth_repository;
 pub mod map_repository;
 pub mod order_repository;
+pub mod shift_repository;
 pub mod tow_truck_repository;
+pub mod webhook_repository;
@@ -1,6 +1,6 @@
 use crate::domains::order_service::OrderRepository;
 use crate::errors::AppError;
-use crate::models::order::{CompletedOrder, Order};
+use crate::models::order::{CompletedOrder, Order, OrderOffer};
 use chrono::{DateTime, Utc};
 use sqlx::mysql::MySqlPool;
 
@@ -49,8 +49,11 @@ impl OrderRepository for OrderRepositoryImpl {
         sort_by: Option<String>,
         sort_order: Option<String>,
         status: Option<String>,
-        area: Option<i32>,
+        area_ids: Option<&[i32]>,
     ) -> Result<Vec<Order>, AppError> {
+        if area_ids.is_some_and(|area_ids| area_ids.is_empty()) {
+            return Ok(vec![]);
+        }
         let offset = page * page_size;
         let order_clause = format!(
             "ORDER BY {} {}",
@@ -67,11 +70,20 @@ impl OrderRepository for OrderRepositoryImpl {
             }
         );
 
-        let where_clause = match (status.clone(), area) {
-            (Some(_), Some(_)) => "WHERE o.status = ? AND n.area_id = ?".to_string(),
-            (None, Some(_)) => "WHERE n.area_id = ?".to_string(),
-            (Some(_), None) => "WHERE o.status = ?".to_string(),
-            _ => "".to_string(),
+        let mut where_conditions = vec![];
+        if status.is_some() {
+            where_conditions.push("o.status = ?".to_string());
+        }
+        if let Some(area_ids) = area_ids {
+            where_conditions.push(format!(
+                "n.area_id IN ({})",
+                vec!["?"; area_ids.len()].join(", ")
+            ));
+        }
+        let where_clause = if where_conditions.is_empty() {
+            "".to_string()
+        } else {
+            format!("WHERE {}", where_conditions.join(" AND "))
         };
 
         let sql = format!(
@@ -83,6 +95,8 @@ impl OrderRepository for OrderRepositoryImpl {
                 o.status,
                 o.node_id,
                 o.car_value,
+                o.required_vehicle_class,
+                o.sponsor,
                 o.order_time,
                 o.completed_time
             FROM
@@ -98,40 +112,18 @@ impl OrderRepository for OrderRepositoryImpl {
             where_clause, order_clause
         );
 
-        let orders = match (status, area) {
-            (Some(status), Some(area)) => {
-                sqlx::query_as::<_, Order>(&sql)
-                    .bind(status)
-                    .bind(area)
-                    .bind(page_size)
-                    .bind(offset)
-                    .fetch_all(&self.pool)
-                    .await?
-            }
-            (None, Some(area)) => {
-                sqlx::query_as::<_, Order>(&sql)
-                    .bind(area)
-                    .bind(page_size)
-                    .bind(offset)
-                    .fetch_all(&self.pool)
-                    .await?
-            }
-            (Some(status), None) => {
-                sqlx::query_as::<_, Order>(&sql)
-                    .bind(status)
-                    .bind(page_size)
-                    .bind(offset)
-                    .fetch_all(&self.pool)
-                    .await?
-            }
-            _ => {
-                sqlx::query_as::<_, Order>(&sql)
-                    .bind(page_size)
-                    .bind(offset)
-                    .fetch_all(&self.pool)
-                    .await?
-            }
-        };
+        let mut query = sqlx::query_as::<_, Order>(&sql);
+        if let Some(status) = status {
+            query = query.bind(status);
+        }
+        for area_id in area_ids.unwrap_or_default() {
+            query = query.bind(area_id);
+        }
+        let orders = query
+            .bind(page_size)
+            .bind(offset)
+            .fetch_all(&self.pool)
+            .await?;
 
         Ok(orders)
     }
@@ -141,60 +133,213 @@ impl OrderRepository for OrderRepositoryImpl {
         client_id: i32,
         node_id: i32,
         car_value: f64,
+        required_vehicle_class: Option<&str>,
+        sponsor: Option<&str>,
     ) -> Result<(), AppError> {
-        sqlx::query("INSERT INTO orders (client_id, node_id, status, car_value) VALUES (?, ?, 'pending', ?)")
+        sqlx::query("INSERT INTO orders (client_id, node_id, status, car_value, required_vehicle_class, sponsor) VALUES (?, ?, 'pending', ?, ?, ?)")
             .bind(client_id)
             .bind(node_id)
             .bind(car_value)
+            .bind(required_vehicle_class)
+            .bind(sponsor)
             .execute(&self.pool)
             .await?;
 
         Ok(())
     }
 
-    async fn update_order_dispatched(
+    async fn get_all_completed_orders(&self) -> Result<Vec<CompletedOrder>, AppError> {
+        let orders = sqlx::query_as::<_, CompletedOrder>(
+            "SELECT co.id, co.order_id, co.tow_truck_id, co.order_time, co.completed_time, o.car_value
+                    FROM completed_orders co
+                    JOIN orders o ON co.order_id = o.id"
+            )
+            .fetch_all(&self.pool)
+            .await?;
+
+        Ok(orders)
+    }
+
+    async fn create_order_offer(
         &self,
-        id: i32,
-        dispatcher_id: i32,
+        order_id: i32,
         tow_truck_id: i32,
-    ) -> Result<(), AppError> {
-        sqlx::query(
-            "UPDATE orders SET dispatcher_id = ?, tow_truck_id = ?, status = 'dispatched' WHERE id = ?",
+        dispatcher_id: i32,
+        timeout_secs: i64,
+        order_status: &str,
+    ) -> Result<Option<i32>, AppError> {
+        let mut tx = self.pool.begin().await?;
+
+        // 同じ依頼・同じレッカー車への打診が同時に作られないよう、状態を条件に更新する
+        let order_result =
+            sqlx::query("UPDATE orders SET status = 'offered' WHERE id = ? AND status = ?")
+                .bind(order_id)
+                .bind(order_status)
+                .execute(&mut tx)
+                .await?;
+        if order_result.rows_affected() != 1 {
+            return Ok(None);
+        }
+
+        let tow_truck_result = sqlx::query(
+            "UPDATE tow_trucks SET status = 'offered' WHERE id = ? AND status = 'available'",
         )
-        .bind(dispatcher_id)
         .bind(tow_truck_id)
-        .bind(id)
-        .execute(&self.pool)
+        .execute(&mut tx)
         .await?;
+        if tow_truck_result.rows_affected() != 1 {
+            return Ok(None);
+        }
 
-        Ok(())
+        let result = sqlx::query(
+            "INSERT INTO order_offers (order_id, tow_truck_id, dispatcher_id, expires_at) VALUES (?, ?, ?, NOW() + INTERVAL ? SECOND)",
+        )
+        .bind(order_id)
+        .bind(tow_truck_id)
+        .bind(dispatcher_id)
+        .bind(timeout_secs)
+        .execute(&mut tx)
+        .await?;
+
+        tx.commit().await?;
+
+        Ok(Some(result.last_insert_id() as i32))
+    }
+
+    async fn find_order_offer_by_id(&self, id: i32) -> Result<Option<OrderOffer>, AppError> {
+        let offer = sqlx::query_as::<_, OrderOffer>("SELECT * FROM order_offers WHERE id = ?")
+            .bind(id)
+            .fetch_optional(&self.pool)
+            .await?;
+
+        Ok(offer)
     }
 
-    async fn create_completed_order(
+    async fn get_order_offers_by_order_id(
         &self,
         order_id: i32,
+    ) -> Result<Vec<OrderOffer>, AppError> {
+        let offers = sqlx::query_as::<_, OrderOffer>(
+            "SELECT * FROM order_offers WHERE order_id = ? ORDER BY created_at ASC, id ASC",
+        )
+        .bind(order_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(offers)
+    }
+
+    async fn get_pending_order_offers_by_tow_truck_id(
+        &self,
         tow_truck_id: i32,
+    ) -> Result<Vec<OrderOffer>, AppError> {
+        let offers = sqlx::query_as::<_, OrderOffer>(
+            "SELECT
+                *
+            FROM
+                order_offers
+            WHERE
+                tow_truck_id = ?
+                AND status = 'pending'
+                AND expires_at > NOW()
+            ORDER BY
+                created_at ASC, id ASC",
+        )
+        .bind(tow_truck_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(offers)
+    }
+
+    async fn get_expired_order_offers(&self) -> Result<Vec<OrderOffer>, AppError> {
+        let offers = sqlx::query_as::<_, OrderOffer>(
+            "SELECT
+                *
+            FROM
+                order_offers
+            WHERE
+                status = 'pending'
+                AND expires_at <= NOW()
+            ORDER BY
+                expires_at ASC, id ASC",
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(offers)
+    }
+
+    async fn accept_order_offer(
+        &self,
+        offer: &OrderOffer,
         completed_time: DateTime<Utc>,
-    ) -> Result<(), AppError> {
+    ) -> Result<bool, AppError> {
+        let mut tx = self.pool.begin().await?;
+
+        let result = sqlx::query(
+            "UPDATE
+                order_offers
+            SET
+                status = 'accepted',
+                responded_at = NOW()
+            WHERE
+                id = ?
+                AND status = 'pending'
+                AND expires_at > NOW()",
+        )
+        .bind(offer.id)
+        .execute(&mut tx)
+        .await?;
+        if result.rows_affected() != 1 {
+            return Ok(false);
+        }
+
         sqlx::query("INSERT INTO completed_orders (order_id, tow_truck_id, completed_time) VALUES (?, ?, ?)")
-            .bind(order_id)
-            .bind(tow_truck_id)
+            .bind(offer.order_id)
+            .bind(offer.tow_truck_id)
             .bind(completed_time)
-            .execute(&self.pool)
-            .await?;
+            .execute(&mut tx)
+            .await
+            .map_err(|_| AppError::BadRequest)?;
 
-        Ok(())
+        // 打診中にキャンセルされた依頼や、引退したレッカー車は配車しない (tx を破棄して巻き戻す)
+        let result = sqlx::query(
+            "UPDATE orders SET dispatcher_id = ?, tow_truck_id = ?, status = 'dispatched' WHERE id = ? AND status = 'offered'",
+        )
+        .bind(offer.dispatcher_id)
+        .bind(offer.tow_truck_id)
+        .bind(offer.order_id)
+        .execute(&mut tx)
+        .await?;
+        if result.rows_affected() != 1 {
+            return Ok(false);
+        }
+
+        let result = sqlx::query(
+            "UPDATE tow_trucks SET status = 'busy' WHERE id = ? AND status = 'offered'",
+        )
+        .bind(offer.tow_truck_id)
+        .execute(&mut tx)
+        .await?;
+        if result.rows_affected() != 1 {
+            return Ok(false);
+        }
+
+        tx.commit().await?;
+
+        Ok(true)
     }
 
-    async fn get_all_completed_orders(&self) -> Result<Vec<CompletedOrder>, AppError> {
-        let orders = sqlx::query_as::<_, CompletedOrder>(
-            "SELECT co.id, co.order_id, co.tow_truck_id, co.order_time, co.completed_time, o.car_value
-                    FROM completed_orders co
-                    JOIN orders o ON co.order_id = o.id"
-            )
-            .fetch_all(&self.pool)
-            .await?;
+    async fn close_order_offer(&self, id: i32, status: &str) -> Result<bool, AppError> {
+        let result = sqlx::query(
+            "UPDATE order_offers SET status = ?, responded_at = NOW() WHERE id = ? AND status = 'pending'",
+        )
+        .bind(status)
+        .bind(id)
+        .execute(&self.pool)
+        .await?;
 
-        Ok(orders)
+        Ok(result.rows_affected() == 1)
     }
 }
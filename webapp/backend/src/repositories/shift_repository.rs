@@ -0,0 +1,127 @@
+use crate::domains::shift_service::ShiftRepository;
+use crate::errors::AppError;
+use crate::models::shift::{OnDutyDriver, Shift};
+use sqlx::mysql::MySqlPool;
+
+#[derive(Debug)]
+pub struct ShiftRepositoryImpl {
+    pool: MySqlPool,
+}
+
+impl ShiftRepositoryImpl {
+    pub fn new(pool: MySqlPool) -> Self {
+        ShiftRepositoryImpl { pool }
+    }
+}
+
+impl ShiftRepository for ShiftRepositoryImpl {
+    async fn find_active_shift_by_driver_id(
+        &self,
+        driver_id: i32,
+    ) -> Result<Option<Shift>, AppError> {
+        let shift = sqlx::query_as::<_, Shift>(
+            "SELECT * FROM shifts WHERE driver_id = ? AND ended_at IS NULL LIMIT 1",
+        )
+        .bind(driver_id)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(shift)
+    }
+
+    async fn start_shift(&self, driver_id: i32, tow_truck_id: i32) -> Result<(), AppError> {
+        sqlx::query("INSERT INTO shifts (driver_id, tow_truck_id) VALUES (?, ?)")
+            .bind(driver_id)
+            .bind(tow_truck_id)
+            .execute(&self.pool)
+            .await
+            .map_err(AppError::conflict_on_duplicate)?;
+
+        Ok(())
+    }
+
+    async fn end_shift(&self, shift_id: i32) -> Result<(), AppError> {
+        sqlx::query("UPDATE shifts SET ended_at = CURRENT_TIMESTAMP WHERE id = ?")
+            .bind(shift_id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn get_paginated_shifts_by_driver_id(
+        &self,
+        driver_id: i32,
+        page: i32,
+        page_size: i32,
+    ) -> Result<Vec<Shift>, AppError> {
+        let shifts = sqlx::query_as::<_, Shift>(
+            "SELECT
+                *
+            FROM
+                shifts
+            WHERE
+                driver_id = ?
+            ORDER BY
+                started_at DESC
+            LIMIT ?
+            OFFSET ?",
+        )
+        .bind(driver_id)
+        .bind(page_size)
+        .bind(page * page_size)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(shifts)
+    }
+
+    async fn get_on_duty_drivers(
+        &self,
+        area_ids: Option<&[i32]>,
+    ) -> Result<Vec<OnDutyDriver>, AppError> {
+        let where_clause = match area_ids {
+            Some([]) => return Ok(vec![]),
+            Some(area_ids) => format!(
+                "AND tt.area_id IN ({})",
+                vec!["?"; area_ids.len()].join(", ")
+            ),
+            None => "".to_string(),
+        };
+
+        let sql = format!(
+            "SELECT
+                s.id AS shift_id,
+                s.driver_id,
+                u.username AS driver_username,
+                tt.id AS tow_truck_id,
+                tt.status AS tow_truck_status,
+                tt.area_id,
+                s.started_at
+            FROM
+                shifts s
+            JOIN
+                tow_trucks tt
+            ON
+                s.tow_truck_id = tt.id
+            JOIN
+                users u
+            ON
+                s.driver_id = u.id
+            WHERE
+                s.ended_at IS NULL
+                {}
+            ORDER BY
+                s.started_at ASC",
+            where_clause
+        );
+
+        let mut query = sqlx::query_as::<_, OnDutyDriver>(&sql);
+        for area_id in area_ids.unwrap_or_default() {
+            query = query.bind(area_id);
+        }
+        let drivers = query.fetch_all(&self.pool).await?;
+
+        Ok(drivers)
+    }
+}
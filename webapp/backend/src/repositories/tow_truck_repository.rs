@@ -1,8 +1,31 @@
 use crate::domains::tow_truck_service::TowTruckRepository;
 use crate::errors::AppError;
-use crate::models::tow_truck::TowTruck;
+use crate::models::geofence::GeofenceEvent;
+use crate::models::tow_truck::{LocationUpdateTarget, StaleTowTruck, TowTruck, VehicleClass};
 use sqlx::mysql::MySqlPool;
 
+// 配車候補になれるレッカー車の条件 (vehicle_classes の数だけプレースホルダを含む)
+fn dispatchable_condition(vehicle_classes: &[VehicleClass], include_out_of_area: bool) -> String {
+    let mut conditions = vec![
+        "tt.status = 'available'".to_string(),
+        "tt.retired_at IS NULL".to_string(),
+        "EXISTS (SELECT 1 FROM shifts s WHERE s.driver_id = tt.driver_id AND s.ended_at IS NULL)"
+            .to_string(),
+        format!(
+            "tt.vehicle_class IN ({})",
+            vec!["?"; vehicle_classes.len()].join(", ")
+        ),
+    ];
+    if !include_out_of_area {
+        conditions.push(
+            "(SELECT n.area_id FROM locations l JOIN nodes n ON l.node_id = n.id WHERE l.tow_truck_id = tt.id ORDER BY l.timestamp DESC LIMIT 1) = tt.area_id"
+                .to_string(),
+        );
+    }
+
+    conditions.join(" AND ")
+}
+
 #[derive(Debug)]
 pub struct TowTruckRepositoryImpl {
     pool: MySqlPool,
@@ -20,21 +43,21 @@ impl TowTruckRepository for TowTruckRepositoryImpl {
         page: i32,
         page_size: i32,
         status: Option<String>,
-        area_id: Option<i32>,
+        area_ids: Option<&[i32]>,
     ) -> Result<Vec<TowTruck>, AppError> {
-        let mut where_conditions = vec![];
+        let mut where_conditions = vec!["tt.retired_at IS NULL".to_string()];
         if let Some(status) = status {
             where_conditions.push(format!("tt.status = '{}'", status));
         }
-        if let Some(area_id) = area_id {
-            where_conditions.push(format!("tt.area_id = {}", area_id));
+        if let Some(area_ids) = area_ids {
+            if area_ids.is_empty() {
+                return Ok(vec![]);
+            }
+            let area_ids: Vec<String> = area_ids.iter().map(|id| id.to_string()).collect();
+            where_conditions.push(format!("tt.area_id IN ({})", area_ids.join(", ")));
         }
 
-        let where_clause = if where_conditions.is_empty() {
-            "".to_string()
-        } else {
-            format!("WHERE {}", where_conditions.join(" AND "))
-        };
+        let where_clause = format!("WHERE {}", where_conditions.join(" AND "));
 
         let (limit_clause, offset_clause) = if page_size == -1 {
             ("".to_string(), "".to_string())
@@ -52,6 +75,7 @@ impl TowTruckRepository for TowTruckRepositoryImpl {
                 tt.driver_id,
                 u.username AS driver_username,
                 tt.status,
+                tt.vehicle_class,
                 tt.area_id,
                 (SELECT node_id FROM locations WHERE tow_truck_id = tt.id ORDER BY timestamp DESC LIMIT 1) AS node_id
             FROM
@@ -84,6 +108,85 @@ impl TowTruckRepository for TowTruckRepositoryImpl {
         Ok(())
     }
 
+    async fn record_location(&self, tow_truck_id: i32, node_id: i32) -> Result<TowTruck, AppError> {
+        let mut tx = self.pool.begin().await?;
+
+        // 移動前の位置と移動先ノードのエリアを同時に読み、同じレッカー車の更新は直列にする
+        let target = sqlx::query_as::<_, LocationUpdateTarget>(
+                "SELECT
+                    tt.id, tt.driver_id, u.username AS driver_username, tt.status, tt.vehicle_class, tt.area_id,
+                    (SELECT node_id FROM locations WHERE tow_truck_id = tt.id ORDER BY timestamp DESC LIMIT 1) AS node_id,
+                    (SELECT n.area_id FROM locations l JOIN nodes n ON l.node_id = n.id WHERE l.tow_truck_id = tt.id ORDER BY l.timestamp DESC LIMIT 1) AS previous_area_id,
+                    (SELECT area_id FROM nodes WHERE id = ?) AS node_area_id
+                FROM
+                    tow_trucks tt
+                JOIN
+                    users u
+                ON
+                    tt.driver_id = u.id
+                WHERE
+                    tt.id = ?
+                    AND tt.status <> 'retired'
+                FOR UPDATE",
+            )
+            .bind(node_id)
+            .bind(tow_truck_id)
+            .fetch_optional(&mut tx)
+            .await?;
+        let LocationUpdateTarget {
+            tow_truck,
+            previous_area_id,
+            node_area_id,
+        } = target.ok_or(AppError::NotFound)?;
+        let node_area_id = node_area_id.ok_or(AppError::BadRequest)?;
+        let area_id = tow_truck.area_id;
+
+        sqlx::query("INSERT INTO locations (tow_truck_id, node_id) VALUES (?, ?)")
+            .bind(tow_truck_id)
+            .bind(node_id)
+            .execute(&mut tx)
+            .await?;
+
+        // 位置情報が届いたので offline から復帰させる
+        let status = if tow_truck.status == "offline" {
+            sqlx::query("UPDATE tow_trucks SET status = 'available' WHERE id = ?")
+                .bind(tow_truck_id)
+                .execute(&mut tx)
+                .await?;
+            "available".to_string()
+        } else {
+            tow_truck.status.clone()
+        };
+
+        let was_in_area = previous_area_id == area_id;
+        let is_in_area = node_area_id == area_id;
+        let event_type = match (was_in_area, is_in_area) {
+            (true, false) => Some("exit"),
+            (false, true) => Some("enter"),
+            _ => None,
+        };
+        if let Some(event_type) = event_type {
+            sqlx::query(
+                "INSERT INTO geofence_events (tow_truck_id, area_id, node_id, node_area_id, event_type) VALUES (?, ?, ?, ?, ?)",
+            )
+            .bind(tow_truck_id)
+            .bind(area_id)
+            .bind(node_id)
+            .bind(node_area_id)
+            .bind(event_type)
+            .execute(&mut tx)
+            .await?;
+        }
+
+        tx.commit().await?;
+
+        Ok(TowTruck {
+            status,
+            node_id,
+            ..tow_truck
+        })
+    }
+
     async fn update_status(&self, tow_truck_id: i32, status: &str) -> Result<(), AppError> {
         sqlx::query("UPDATE tow_trucks SET status = ? WHERE id = ?")
             .bind(status)
@@ -97,7 +200,7 @@ impl TowTruckRepository for TowTruckRepositoryImpl {
     async fn find_tow_truck_by_id(&self, id: i32) -> Result<Option<TowTruck>, AppError> {
         let tow_truck = sqlx::query_as::<_, TowTruck>(
             "SELECT
-                tt.id, tt.driver_id, u.username AS driver_username, tt.status, tt.area_id,
+                tt.id, tt.driver_id, u.username AS driver_username, tt.status, tt.vehicle_class, tt.area_id,
                 (SELECT node_id FROM locations WHERE tow_truck_id = tt.id ORDER BY timestamp DESC LIMIT 1) AS node_id
             FROM
                 tow_trucks tt
@@ -115,4 +218,282 @@ impl TowTruckRepository for TowTruckRepositoryImpl {
 
         Ok(tow_truck)
     }
+
+    async fn mark_stale_tow_trucks_offline(
+        &self,
+        truck_ids: &[i32],
+        threshold_secs: i64,
+    ) -> Result<u64, AppError> {
+        if truck_ids.is_empty() {
+            return Ok(0);
+        }
+
+        let mut qb = sqlx::QueryBuilder::new(
+            "UPDATE
+                tow_trucks tt
+            SET
+                tt.status = 'offline'
+            WHERE
+                tt.status = 'available'
+                AND (SELECT MAX(timestamp) FROM locations WHERE tow_truck_id = tt.id) < NOW() - INTERVAL ",
+        );
+        qb.push_bind(threshold_secs).push(" SECOND AND tt.id IN (");
+        let mut sep = qb.separated(", ");
+        for id in truck_ids {
+            sep.push_bind(id);
+        }
+        sep.push_unseparated(")");
+
+        let result = qb.build().execute(&self.pool).await?;
+
+        Ok(result.rows_affected())
+    }
+
+    async fn get_stale_tow_trucks(
+        &self,
+        threshold_secs: i64,
+        area_ids: Option<&[i32]>,
+    ) -> Result<Vec<StaleTowTruck>, AppError> {
+        let area_clause = match area_ids {
+            Some([]) => return Ok(vec![]),
+            Some(area_ids) => format!(
+                "AND tt.area_id IN ({})",
+                vec!["?"; area_ids.len()].join(", ")
+            ),
+            None => "".to_string(),
+        };
+
+        let sql = format!(
+            "SELECT
+                tt.id,
+                tt.driver_id,
+                u.username AS driver_username,
+                tt.status,
+                tt.area_id,
+                l.node_id,
+                l.timestamp AS last_located_at
+            FROM
+                tow_trucks tt
+            JOIN
+                users u
+            ON
+                tt.driver_id = u.id
+            JOIN
+                locations l
+            ON
+                l.id = (SELECT id FROM locations WHERE tow_truck_id = tt.id ORDER BY timestamp DESC LIMIT 1)
+            WHERE
+                tt.retired_at IS NULL
+                AND l.timestamp < NOW() - INTERVAL ? SECOND
+                {}
+            ORDER BY
+                l.timestamp ASC",
+            area_clause
+        );
+
+        let mut query = sqlx::query_as::<_, StaleTowTruck>(&sql).bind(threshold_secs);
+        for area_id in area_ids.unwrap_or_default() {
+            query = query.bind(area_id);
+        }
+        let tow_trucks = query.fetch_all(&self.pool).await?;
+
+        Ok(tow_trucks)
+    }
+
+    async fn find_tow_truck_by_driver_id(
+        &self,
+        driver_id: i32,
+    ) -> Result<Option<TowTruck>, AppError> {
+        let tow_truck = sqlx::query_as::<_, TowTruck>(
+            "SELECT
+                tt.id, tt.driver_id, u.username AS driver_username, tt.status, tt.vehicle_class, tt.area_id,
+                (SELECT node_id FROM locations WHERE tow_truck_id = tt.id ORDER BY timestamp DESC LIMIT 1) AS node_id
+            FROM
+                tow_trucks tt
+            JOIN
+                users u
+            ON
+                tt.driver_id = u.id
+            WHERE
+                tt.driver_id = ?
+                AND tt.retired_at IS NULL
+            ",
+        )
+        .bind(driver_id)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(tow_truck)
+    }
+
+    async fn get_dispatchable_tow_trucks(
+        &self,
+        area_id: i32,
+        vehicle_classes: &[VehicleClass],
+        include_out_of_area: bool,
+    ) -> Result<Vec<TowTruck>, AppError> {
+        let sql = format!(
+            "SELECT
+                tt.id,
+                tt.driver_id,
+                u.username AS driver_username,
+                tt.status,
+                tt.vehicle_class,
+                tt.area_id,
+                (SELECT node_id FROM locations WHERE tow_truck_id = tt.id ORDER BY timestamp DESC LIMIT 1) AS node_id
+            FROM
+                tow_trucks tt
+            JOIN
+                users u
+            ON
+                tt.driver_id = u.id
+            WHERE
+                tt.area_id = ?
+                AND {}
+            ORDER BY
+                tt.id ASC",
+            dispatchable_condition(vehicle_classes, include_out_of_area)
+        );
+
+        let mut query = sqlx::query_as::<_, TowTruck>(&sql).bind(area_id);
+        for vehicle_class in vehicle_classes {
+            query = query.bind(vehicle_class.as_str());
+        }
+        let tow_trucks = query.fetch_all(&self.pool).await?;
+
+        Ok(tow_trucks)
+    }
+
+    async fn is_tow_truck_dispatchable(
+        &self,
+        tow_truck_id: i32,
+        vehicle_classes: &[VehicleClass],
+        include_out_of_area: bool,
+    ) -> Result<bool, AppError> {
+        let sql = format!(
+            "SELECT EXISTS (SELECT 1 FROM tow_trucks tt WHERE tt.id = ? AND {})",
+            dispatchable_condition(vehicle_classes, include_out_of_area)
+        );
+
+        let mut query = sqlx::query_scalar(&sql).bind(tow_truck_id);
+        for vehicle_class in vehicle_classes {
+            query = query.bind(vehicle_class.as_str());
+        }
+        let dispatchable: bool = query.fetch_one(&self.pool).await?;
+
+        Ok(dispatchable)
+    }
+
+    async fn create_tow_truck(
+        &self,
+        driver_id: i32,
+        area_id: i32,
+        node_id: i32,
+        vehicle_class: VehicleClass,
+    ) -> Result<i32, AppError> {
+        // 位置情報のないレッカー車は node_id を読めないので、同じトランザクションで登録する
+        let mut tx = self.pool.begin().await?;
+
+        let result = sqlx::query(
+            "INSERT INTO tow_trucks (driver_id, area_id, vehicle_class) VALUES (?, ?, ?)",
+        )
+        .bind(driver_id)
+        .bind(area_id)
+        .bind(vehicle_class.as_str())
+        .execute(&mut tx)
+        .await?;
+        let tow_truck_id = result.last_insert_id() as i32;
+
+        sqlx::query("INSERT INTO locations (tow_truck_id, node_id) VALUES (?, ?)")
+            .bind(tow_truck_id)
+            .bind(node_id)
+            .execute(&mut tx)
+            .await?;
+
+        tx.commit().await?;
+
+        Ok(tow_truck_id)
+    }
+
+    async fn update_tow_truck(
+        &self,
+        tow_truck_id: i32,
+        driver_id: i32,
+        area_id: i32,
+        vehicle_class: VehicleClass,
+    ) -> Result<(), AppError> {
+        let mut tx = self.pool.begin().await?;
+
+        // ドライバーが変わる場合、前のドライバーのシフトは終了させる
+        sqlx::query(
+            "UPDATE shifts SET ended_at = CURRENT_TIMESTAMP WHERE tow_truck_id = ? AND driver_id <> ? AND ended_at IS NULL",
+        )
+        .bind(tow_truck_id)
+        .bind(driver_id)
+        .execute(&mut tx)
+        .await?;
+
+        sqlx::query(
+            "UPDATE tow_trucks SET driver_id = ?, area_id = ?, vehicle_class = ? WHERE id = ?",
+        )
+        .bind(driver_id)
+        .bind(area_id)
+        .bind(vehicle_class.as_str())
+        .bind(tow_truck_id)
+        .execute(&mut tx)
+        .await?;
+
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    async fn retire_tow_truck(&self, tow_truck_id: i32) -> Result<(), AppError> {
+        let mut tx = self.pool.begin().await?;
+
+        sqlx::query(
+            "UPDATE shifts SET ended_at = CURRENT_TIMESTAMP WHERE tow_truck_id = ? AND ended_at IS NULL",
+        )
+        .bind(tow_truck_id)
+        .execute(&mut tx)
+        .await?;
+
+        sqlx::query(
+            "UPDATE tow_trucks SET status = 'retired', retired_at = CURRENT_TIMESTAMP WHERE id = ?",
+        )
+        .bind(tow_truck_id)
+        .execute(&mut tx)
+        .await?;
+
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    async fn get_paginated_geofence_events(
+        &self,
+        area_id: i32,
+        page: i32,
+        page_size: i32,
+    ) -> Result<Vec<GeofenceEvent>, AppError> {
+        let events = sqlx::query_as::<_, GeofenceEvent>(
+            "SELECT
+                *
+            FROM
+                geofence_events
+            WHERE
+                area_id = ?
+            ORDER BY
+                created_at DESC, id DESC
+            LIMIT ?
+            OFFSET ?",
+        )
+        .bind(area_id)
+        .bind(page_size)
+        .bind(page * page_size)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(events)
+    }
 }
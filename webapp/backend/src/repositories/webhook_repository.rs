@@ -0,0 +1,222 @@
+use crate::domains::webhook_service::WebhookRepository;
+use crate::errors::AppError;
+use crate::models::webhook::{WebhookDelivery, WebhookSubscription};
+use sqlx::mysql::MySqlPool;
+
+#[derive(Debug)]
+pub struct WebhookRepositoryImpl {
+    pool: MySqlPool,
+}
+
+impl WebhookRepositoryImpl {
+    pub fn new(pool: MySqlPool) -> Self {
+        WebhookRepositoryImpl { pool }
+    }
+}
+
+impl WebhookRepository for WebhookRepositoryImpl {
+    async fn create_subscription(
+        &self,
+        url: &str,
+        secret: &str,
+        event_types: &str,
+        sponsor: Option<&str>,
+    ) -> Result<i32, AppError> {
+        let result = sqlx::query(
+            "INSERT INTO webhook_subscriptions (url, secret, event_types, sponsor) VALUES (?, ?, ?, ?)",
+        )
+        .bind(url)
+        .bind(secret)
+        .bind(event_types)
+        .bind(sponsor)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.last_insert_id() as i32)
+    }
+
+    async fn find_subscription_by_id(
+        &self,
+        id: i32,
+    ) -> Result<Option<WebhookSubscription>, AppError> {
+        let subscription = sqlx::query_as::<_, WebhookSubscription>(
+            "SELECT * FROM webhook_subscriptions WHERE id = ?",
+        )
+        .bind(id)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(subscription)
+    }
+
+    async fn get_active_subscriptions(&self) -> Result<Vec<WebhookSubscription>, AppError> {
+        let subscriptions = sqlx::query_as::<_, WebhookSubscription>(
+            "SELECT * FROM webhook_subscriptions WHERE is_active = TRUE ORDER BY id ASC",
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(subscriptions)
+    }
+
+    async fn get_active_subscriptions_for_event(
+        &self,
+        event_type: &str,
+        sponsor: Option<&str>,
+    ) -> Result<Vec<WebhookSubscription>, AppError> {
+        // sponsor が NULL のイベントは運営側の登録先にだけ届く
+        let subscriptions = sqlx::query_as::<_, WebhookSubscription>(
+            "SELECT
+                *
+            FROM
+                webhook_subscriptions
+            WHERE
+                is_active = TRUE
+                AND FIND_IN_SET(?, event_types) > 0
+                AND (sponsor IS NULL OR sponsor = ?)
+            ORDER BY
+                id ASC",
+        )
+        .bind(event_type)
+        .bind(sponsor)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(subscriptions)
+    }
+
+    async fn deactivate_subscription(&self, id: i32) -> Result<(), AppError> {
+        sqlx::query("UPDATE webhook_subscriptions SET is_active = FALSE WHERE id = ?")
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn create_delivery(
+        &self,
+        subscription_id: i32,
+        event_type: &str,
+        payload: &str,
+    ) -> Result<i32, AppError> {
+        let result = sqlx::query(
+            "INSERT INTO webhook_deliveries (subscription_id, event_type, payload) VALUES (?, ?, ?)",
+        )
+        .bind(subscription_id)
+        .bind(event_type)
+        .bind(payload)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.last_insert_id() as i32)
+    }
+
+    async fn find_delivery_by_id(&self, id: i32) -> Result<Option<WebhookDelivery>, AppError> {
+        let delivery =
+            sqlx::query_as::<_, WebhookDelivery>("SELECT * FROM webhook_deliveries WHERE id = ?")
+                .bind(id)
+                .fetch_optional(&self.pool)
+                .await?;
+
+        Ok(delivery)
+    }
+
+    async fn get_due_deliveries(&self, limit: i32) -> Result<Vec<WebhookDelivery>, AppError> {
+        let deliveries = sqlx::query_as::<_, WebhookDelivery>(
+            "SELECT
+                *
+            FROM
+                webhook_deliveries
+            WHERE
+                status = 'pending'
+                AND next_attempt_at <= NOW()
+            ORDER BY
+                next_attempt_at ASC, id ASC
+            LIMIT ?",
+        )
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(deliveries)
+    }
+
+    async fn get_paginated_deliveries(
+        &self,
+        subscription_id: i32,
+        page: i32,
+        page_size: i32,
+    ) -> Result<Vec<WebhookDelivery>, AppError> {
+        let deliveries = sqlx::query_as::<_, WebhookDelivery>(
+            "SELECT
+                *
+            FROM
+                webhook_deliveries
+            WHERE
+                subscription_id = ?
+            ORDER BY
+                created_at DESC, id DESC
+            LIMIT ?
+            OFFSET ?",
+        )
+        .bind(subscription_id)
+        .bind(page_size)
+        .bind(page * page_size)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(deliveries)
+    }
+
+    async fn mark_delivery_succeeded(&self, id: i32, response_status: i32) -> Result<(), AppError> {
+        sqlx::query(
+            "UPDATE
+                webhook_deliveries
+            SET
+                status = 'succeeded',
+                attempts = attempts + 1,
+                response_status = ?,
+                last_error = NULL,
+                delivered_at = NOW()
+            WHERE
+                id = ?",
+        )
+        .bind(response_status)
+        .bind(id)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn mark_delivery_attempt_failed(
+        &self,
+        id: i32,
+        response_status: Option<i32>,
+        error: &str,
+        retry_after_secs: Option<i64>,
+    ) -> Result<(), AppError> {
+        sqlx::query(
+            "UPDATE
+                webhook_deliveries
+            SET
+                status = IF(? IS NULL, 'failed', 'pending'),
+                attempts = attempts + 1,
+                response_status = ?,
+                last_error = ?,
+                next_attempt_at = NOW() + INTERVAL COALESCE(?, 0) SECOND
+            WHERE
+                id = ?",
+        )
+        .bind(retry_after_secs)
+        .bind(response_status)
+        .bind(error)
+        .bind(retry_after_secs)
+        .bind(id)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+}
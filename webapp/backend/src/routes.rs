@@ -0,0 +1,362 @@
+use std::sync::Arc;
+
+use actix_web::web;
+
+use crate::api::{
+    admin_handler, auth_handler, health_check_handler, map_handler, metrics_handler, order_handler,
+    result_handler, shift_handler, tow_truck_handler, user_handler, webhook_handler,
+};
+use crate::domains::auth_service::AuthService;
+use crate::infrastructure::password_reset_notifier::LocalPasswordResetNotifier;
+use crate::middlewares::auth_middleware::AuthMiddleware;
+use crate::middlewares::role_middleware::RequireRole;
+use crate::models::user::Role;
+use crate::repositories::auth_repository::AuthRepositoryImpl;
+
+// ルートごとに必要なロールを宣言する (ロールの判定は RequireRole、ログインの確認は AuthMiddleware)
+pub fn configure(
+    cfg: &mut web::ServiceConfig,
+    auth_service: Arc<AuthService<AuthRepositoryImpl, LocalPasswordResetNotifier>>,
+) {
+    cfg.service(
+        web::scope("/api")
+            .service(
+                web::resource("/health_check")
+                    .route(web::get().to(health_check_handler::health_check_handler)),
+            )
+            .service(
+                web::resource("/metrics").route(web::get().to(metrics_handler::metrics_handler)),
+            )
+            .service(web::resource("/result").route(web::get().to(result_handler::result_handler)))
+            .service(
+                web::resource("/register").route(web::post().to(auth_handler::register_handler)),
+            )
+            .service(web::resource("/login").route(web::post().to(auth_handler::login_handler)))
+            .service(
+                web::resource("/login/totp")
+                    .route(web::post().to(auth_handler::totp_login_handler)),
+            )
+            .service(
+                web::resource("/login/totp/enroll")
+                    .route(web::post().to(auth_handler::totp_login_enrollment_handler)),
+            )
+            .service(web::resource("/logout").route(web::post().to(auth_handler::logout_handler)))
+            .service(
+                web::resource("/user_image/{user_id}")
+                    .route(web::get().to(auth_handler::user_profile_image_handler)),
+            )
+            .service(
+                web::resource("/me")
+                    .wrap(AuthMiddleware::new(auth_service.clone()))
+                    .route(web::get().to(user_handler::get_me_handler))
+                    .route(web::patch().to(user_handler::update_me_handler))
+                    .route(web::delete().to(user_handler::delete_me_handler)),
+            )
+            .service(
+                web::resource("/me/totp")
+                    .wrap(AuthMiddleware::new(auth_service.clone()))
+                    .route(web::get().to(user_handler::get_totp_status_handler))
+                    .route(web::post().to(user_handler::begin_totp_enrollment_handler))
+                    .route(web::delete().to(user_handler::disable_totp_handler)),
+            )
+            .service(
+                web::resource("/me/totp/confirm")
+                    .wrap(AuthMiddleware::new(auth_service.clone()))
+                    .route(web::post().to(user_handler::confirm_totp_enrollment_handler)),
+            )
+            .service(
+                web::resource("/user/{id}/image")
+                    .wrap(AuthMiddleware::new(auth_service.clone()))
+                    .route(web::put().to(auth_handler::update_profile_image_handler)),
+            )
+            .service(
+                web::scope("/password")
+                    .service(
+                        web::resource("/change")
+                            .wrap(AuthMiddleware::new(auth_service.clone()))
+                            .route(web::post().to(auth_handler::change_password_handler)),
+                    )
+                    .service(
+                        web::resource("/reset/request")
+                            .route(web::post().to(auth_handler::request_password_reset_handler)),
+                    )
+                    .service(
+                        web::resource("/reset")
+                            .route(web::post().to(auth_handler::reset_password_handler)),
+                    ),
+            )
+            .service(
+                web::scope("/session")
+                    .wrap(AuthMiddleware::new(auth_service.clone()))
+                    .service(
+                        web::resource("")
+                            .route(web::get().to(auth_handler::get_sessions_handler))
+                            .route(web::delete().to(auth_handler::revoke_all_sessions_handler)),
+                    )
+                    .service(
+                        web::resource("/refresh")
+                            .route(web::post().to(auth_handler::refresh_session_handler)),
+                    )
+                    .service(
+                        web::resource("/{id}")
+                            .route(web::delete().to(auth_handler::revoke_session_handler)),
+                    ),
+            )
+            .service(
+                web::scope("/tow_truck")
+                    .wrap(AuthMiddleware::new(auth_service.clone()))
+                    .service(
+                        web::resource("").route(
+                            web::post()
+                                .to(tow_truck_handler::create_tow_truck_handler)
+                                .wrap(RequireRole::new(&[Role::Admin])),
+                        ),
+                    )
+                    .service(
+                        web::resource("/list").route(
+                            web::get()
+                                .to(tow_truck_handler::get_paginated_tow_trucks_handler)
+                                .wrap(RequireRole::new(&[Role::Dispatcher])),
+                        ),
+                    )
+                    .service(
+                        web::resource("/location").route(
+                            web::post()
+                                .to(tow_truck_handler::update_location_handler)
+                                .wrap(RequireRole::new(&[Role::Driver])),
+                        ),
+                    )
+                    .service(
+                        web::resource("/nearest").route(
+                            web::get()
+                                .to(tow_truck_handler::get_nearest_available_tow_trucks_handler)
+                                .wrap(RequireRole::new(&[Role::Dispatcher])),
+                        ),
+                    )
+                    .service(
+                        web::resource("/stale").route(
+                            web::get()
+                                .to(tow_truck_handler::get_stale_tow_trucks_handler)
+                                .wrap(RequireRole::new(&[Role::Dispatcher])),
+                        ),
+                    )
+                    .service(
+                        web::resource("/stream").route(
+                            web::get()
+                                .to(tow_truck_handler::stream_tow_trucks_handler)
+                                .wrap(RequireRole::new(&[Role::Dispatcher])),
+                        ),
+                    )
+                    .service(
+                        web::resource("/geofence_events").route(
+                            web::get()
+                                .to(tow_truck_handler::get_geofence_events_handler)
+                                .wrap(RequireRole::new(&[Role::Dispatcher])),
+                        ),
+                    )
+                    .service(
+                        web::resource("/{id}")
+                            .route(
+                                web::get()
+                                    .to(tow_truck_handler::get_tow_truck_handler)
+                                    .wrap(RequireRole::new(&[Role::Dispatcher, Role::Driver])),
+                            )
+                            .route(
+                                web::put()
+                                    .to(tow_truck_handler::update_tow_truck_handler)
+                                    .wrap(RequireRole::new(&[Role::Admin])),
+                            )
+                            .route(
+                                web::delete()
+                                    .to(tow_truck_handler::retire_tow_truck_handler)
+                                    .wrap(RequireRole::new(&[Role::Admin])),
+                            ),
+                    ),
+            )
+            .service(
+                web::scope("/order")
+                    .wrap(AuthMiddleware::new(auth_service.clone()))
+                    .service(
+                        web::resource("/list").route(
+                            web::get()
+                                .to(order_handler::get_paginated_orders_handler)
+                                .wrap(RequireRole::new(&[Role::Dispatcher])),
+                        ),
+                    )
+                    .service(
+                        web::resource("/status").route(
+                            web::post()
+                                .to(order_handler::update_order_status_handler)
+                                .wrap(RequireRole::new(&[Role::Dispatcher, Role::Driver])),
+                        ),
+                    )
+                    .service(
+                        web::resource("/stream").route(
+                            web::get()
+                                .to(order_handler::stream_orders_handler)
+                                .wrap(RequireRole::new(&[Role::Client])),
+                        ),
+                    )
+                    .service(
+                        web::resource("/client").route(
+                            web::post()
+                                .to(order_handler::create_client_order_handler)
+                                .wrap(RequireRole::new(&[Role::Client])),
+                        ),
+                    )
+                    .service(
+                        web::resource("/dispatcher").route(
+                            web::post()
+                                .to(order_handler::create_dispatcher_order_handler)
+                                .wrap(RequireRole::new(&[Role::Dispatcher])),
+                        ),
+                    )
+                    .service(
+                        web::resource("/offer/inbox").route(
+                            web::get()
+                                .to(order_handler::get_order_offer_inbox_handler)
+                                .wrap(RequireRole::new(&[Role::Driver])),
+                        ),
+                    )
+                    .service(
+                        web::resource("/offer/{id}/accept").route(
+                            web::post()
+                                .to(order_handler::accept_order_offer_handler)
+                                .wrap(RequireRole::new(&[Role::Driver])),
+                        ),
+                    )
+                    .service(
+                        web::resource("/offer/{id}/decline").route(
+                            web::post()
+                                .to(order_handler::decline_order_offer_handler)
+                                .wrap(RequireRole::new(&[Role::Driver])),
+                        ),
+                    )
+                    .service(
+                        web::resource("/{id}/offers").route(
+                            web::get()
+                                .to(order_handler::get_order_offers_handler)
+                                .wrap(RequireRole::new(&[Role::Dispatcher])),
+                        ),
+                    )
+                    .service(
+                        web::resource("/{id}").route(
+                            web::get()
+                                .to(order_handler::get_order_handler)
+                                .wrap(RequireRole::new(&[
+                                    Role::Client,
+                                    Role::Dispatcher,
+                                    Role::Driver,
+                                ])),
+                        ),
+                    ),
+            )
+            .service(
+                web::scope("/shift")
+                    .wrap(AuthMiddleware::new(auth_service.clone()))
+                    .service(
+                        web::resource("/start").route(
+                            web::post()
+                                .to(shift_handler::start_shift_handler)
+                                .wrap(RequireRole::new(&[Role::Driver])),
+                        ),
+                    )
+                    .service(
+                        web::resource("/end").route(
+                            web::post()
+                                .to(shift_handler::end_shift_handler)
+                                .wrap(RequireRole::new(&[Role::Driver])),
+                        ),
+                    )
+                    .service(
+                        web::resource("/history").route(
+                            web::get()
+                                .to(shift_handler::get_shift_history_handler)
+                                .wrap(RequireRole::new(&[Role::Dispatcher, Role::Driver])),
+                        ),
+                    )
+                    .service(
+                        web::resource("/on_duty").route(
+                            web::get()
+                                .to(shift_handler::get_on_duty_drivers_handler)
+                                .wrap(RequireRole::new(&[Role::Dispatcher])),
+                        ),
+                    ),
+            )
+            .service(
+                web::scope("/webhook")
+                    .wrap(RequireRole::new(&[Role::Admin]))
+                    .wrap(AuthMiddleware::new(auth_service.clone()))
+                    .service(
+                        web::resource("")
+                            .route(web::post().to(webhook_handler::register_webhook_handler))
+                            .route(web::get().to(webhook_handler::get_webhooks_handler)),
+                    )
+                    .service(
+                        web::resource("/deliveries/{id}/replay").route(
+                            web::post().to(webhook_handler::replay_webhook_delivery_handler),
+                        ),
+                    )
+                    .service(
+                        web::resource("/{id}")
+                            .route(web::delete().to(webhook_handler::delete_webhook_handler)),
+                    )
+                    .service(
+                        web::resource("/{id}/deliveries")
+                            .route(web::get().to(webhook_handler::get_webhook_deliveries_handler)),
+                    ),
+            )
+            .service(
+                web::scope("/admin")
+                    .wrap(RequireRole::new(&[Role::Admin]))
+                    .wrap(AuthMiddleware::new(auth_service.clone()))
+                    .service(
+                        web::resource("/login_lockouts")
+                            .route(web::get().to(auth_handler::get_login_lockouts_handler))
+                            .route(web::delete().to(auth_handler::clear_login_lockout_handler)),
+                    )
+                    .service(
+                        web::resource("/users")
+                            .route(web::get().to(admin_handler::search_users_handler)),
+                    )
+                    .service(
+                        web::resource("/users/{id}/role")
+                            .route(web::put().to(admin_handler::change_user_role_handler)),
+                    )
+                    .service(
+                        web::resource("/users/{id}/areas")
+                            .route(web::put().to(admin_handler::assign_dispatcher_areas_handler)),
+                    )
+                    .service(
+                        web::resource("/users/{id}/disable")
+                            .route(web::post().to(admin_handler::disable_user_handler)),
+                    )
+                    .service(
+                        web::resource("/users/{id}/enable")
+                            .route(web::post().to(admin_handler::enable_user_handler)),
+                    )
+                    .service(
+                        web::resource("/users/{id}/totp")
+                            .route(web::delete().to(admin_handler::reset_user_totp_handler)),
+                    )
+                    .service(
+                        web::resource("/users/{id}/impersonate")
+                            .route(web::post().to(admin_handler::impersonate_user_handler)),
+                    )
+                    .service(
+                        web::resource("/audit_logs")
+                            .route(web::get().to(admin_handler::get_audit_logs_handler)),
+                    ),
+            )
+            .service(
+                web::scope("/map")
+                    // 通行止めなどに合わせてディスパッチャーが担当エリアの道路の重みを変える
+                    .wrap(RequireRole::new(&[Role::Dispatcher]))
+                    .wrap(AuthMiddleware::new(auth_service.clone()))
+                    .service(
+                        web::resource("/update_edge")
+                            .route(web::put().to(map_handler::update_edge_handler)),
+                    ),
+            ),
+    );
+}
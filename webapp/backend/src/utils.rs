@@ -1,9 +1,14 @@
+use std::net::IpAddr;
+
 use argon2::{
     password_hash::{rand_core::OsRng, PasswordHash, PasswordHasher, PasswordVerifier, SaltString},
-    Argon2,
+    Algorithm, Argon2, Params, Version,
 };
+use hmac::{Hmac, Mac};
 use rand::{distributions::Alphanumeric, Rng};
+use sha2::{Digest, Sha256};
 
+use crate::config::Argon2Config;
 use crate::errors::AppError;
 
 pub fn generate_session_token() -> String {
@@ -14,12 +19,22 @@ pub fn generate_session_token() -> String {
         .collect()
 }
 
-pub fn hash_password(password: &str) -> Result<String, AppError> {
+fn argon2_with(config: &Argon2Config) -> Result<Argon2<'static>, AppError> {
+    let params = Params::new(
+        config.memory_kib,
+        config.iterations,
+        config.parallelism,
+        None,
+    )
+    .map_err(|_| AppError::InternalServerError)?;
+    Ok(Argon2::new(Algorithm::Argon2id, Version::V0x13, params))
+}
+
+pub fn hash_password(password: &str, config: &Argon2Config) -> Result<String, AppError> {
     let password_bytes = password.as_bytes();
     let salt = SaltString::generate(&mut OsRng);
 
-    // Argon2 with default params (Argon2id v19)
-    let argon2 = Argon2::default();
+    let argon2 = argon2_with(config)?;
 
     // Hash password to PHC string ($argon2id$v=19$...)
     match argon2.hash_password(password_bytes, &salt) {
@@ -34,8 +49,118 @@ pub fn verify_password(hashed_password: &str, input_password: &str) -> Result<bo
         Ok(hash) => hash,
         Err(_) => return Err(AppError::InternalServerError),
     };
+    // コストはハッシュ文字列に埋め込まれたものが使われる
     match Argon2::default().verify_password(input_password_bytes, &parsed_hash) {
         Ok(_) => Ok(true),
         Err(_) => Ok(false),
     }
 }
+
+// 現在の設定と異なるアルゴリズム・コストで作られたハッシュか
+pub fn password_needs_rehash(hashed_password: &str, config: &Argon2Config) -> bool {
+    let Ok(parsed_hash) = PasswordHash::new(hashed_password) else {
+        return true;
+    };
+    if parsed_hash.algorithm != Algorithm::Argon2id.ident()
+        || parsed_hash.version != Some(Version::V0x13.into())
+    {
+        return true;
+    }
+    match Params::try_from(&parsed_hash) {
+        Ok(params) => {
+            params.m_cost() != config.memory_kib
+                || params.t_cost() != config.iterations
+                || params.p_cost() != config.parallelism
+        }
+        Err(_) => true,
+    }
+}
+
+pub fn generate_secret() -> String {
+    rand::thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(40)
+        .map(char::from)
+        .collect()
+}
+
+pub fn sha256_hex(data: &[u8]) -> String {
+    hex::encode(Sha256::digest(data))
+}
+
+// HMAC-SHA256 (RFC 2104) の16進表記
+pub fn hmac_sha256_hex(key: &[u8], message: &[u8]) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC accepts keys of any length");
+    mac.update(message);
+    hex::encode(mac.finalize().into_bytes())
+}
+
+// 外部から到達できるアドレスか (プライベート・ループバック・リンクローカルなどは除く)
+pub fn is_public_ip(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            !(ip.is_private()
+                || ip.is_loopback()
+                || ip.is_link_local()
+                || ip.is_unspecified()
+                || ip.is_broadcast())
+        }
+        IpAddr::V6(ip) => match ip.to_ipv4_mapped() {
+            Some(ip) => is_public_ip(IpAddr::V4(ip)),
+            None => {
+                !(ip.is_loopback()
+                    || ip.is_unspecified()
+                    || ip.is_unique_local()
+                    || ip.is_unicast_link_local())
+            }
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::IpAddr;
+
+    use super::{hmac_sha256_hex, is_public_ip};
+
+    // RFC 4231 のテストケース 1, 2, 6
+    #[test]
+    fn hmac_sha256_matches_rfc4231_vectors() {
+        assert_eq!(
+            hmac_sha256_hex(&[0x0b; 20], b"Hi There"),
+            "b0344c61d8db38535ca8afceaf0bf12b881dc200c9833da726e9376c2e32cff7"
+        );
+        assert_eq!(
+            hmac_sha256_hex(b"Jefe", b"what do ya want for nothing?"),
+            "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+        assert_eq!(
+            hmac_sha256_hex(
+                &[0xaa; 131],
+                b"Test Using Larger Than Block-Size Key - Hash Key First"
+            ),
+            "60e431591ee0b67f0d8a26aacbf5b77f8e0bc6213728c5140546040f0ee37f54"
+        );
+    }
+
+    #[test]
+    fn internal_addresses_are_not_public() {
+        for ip in [
+            "127.0.0.1",
+            "10.0.0.1",
+            "172.16.0.1",
+            "192.168.1.1",
+            "169.254.169.254",
+            "0.0.0.0",
+            "::1",
+            "fc00::1",
+            "fe80::1",
+            "::ffff:127.0.0.1",
+        ] {
+            assert!(!is_public_ip(ip.parse::<IpAddr>().unwrap()), "{}", ip);
+        }
+        for ip in ["8.8.8.8", "2001:4860:4860::8888"] {
+            assert!(is_public_ip(ip.parse::<IpAddr>().unwrap()), "{}", ip);
+        }
+    }
+}
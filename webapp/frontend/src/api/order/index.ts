@@ -19,6 +19,17 @@ export type Order = {
   completed_time: string;
 };
 
+export type OrderOffer = {
+  id: number;
+  order_id: number;
+  tow_truck_id: number;
+  dispatcher_id: number;
+  status: string;
+  created_at: string;
+  expires_at: string;
+  responded_at: string | null;
+};
+
 export type OrdersQueryParams = {
   status: string;
   sort_by: string;
@@ -58,7 +69,7 @@ export const arrangeTowTruck = async (
   order_time: string,
   session_token: string
 ) => {
-  await AxiosInstance.post(
+  const { data } = await AxiosInstance.post<OrderOffer>(
     "/api/order/dispatcher",
     {
       dispatcher_id,
@@ -68,4 +79,5 @@ export const arrangeTowTruck = async (
     },
     { timeout: 5000, headers: { Authorization: session_token } }
   );
+  return data;
 };
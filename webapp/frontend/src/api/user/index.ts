@@ -10,7 +10,7 @@ export type User = {
   | {
       role: "dispatcher";
       dispatcher_id: number;
-      area_id: number;
+      area_ids: number[];
     }
   | {
       role: "client";
@@ -24,13 +24,49 @@ export type User = {
     }
 );
 
+// 2 段階認証が必要なときにパスワードの代わりに返る
+export type TotpChallenge = {
+  challenge_token: string;
+  totp_enrollment_required: boolean;
+  expires_at: string;
+};
+
+// ログイン時に登録する共有鍵 (確認コードを送るまでは有効にならない)
+export type TotpEnrollment = {
+  secret: string;
+  provisioning_uri: string;
+};
+
 const AxiosInstance = Axios.getInstance();
 
 export const login = async (username: string, password: string) => {
-  const { data } = await AxiosInstance.post<User>("/api/login", {
+  const { data } = await AxiosInstance.post<User | TotpChallenge>("/api/login", {
     username,
     password
   });
+  if ("challenge_token" in data) {
+    return data;
+  }
+
+  // セッション情報をサーバーサイドに保存
+  await AxiosInstance.post("/session", data);
+
+  return data;
+};
+
+export const beginTotpEnrollmentForLogin = async (challenge_token: string) => {
+  const { data } = await AxiosInstance.post<TotpEnrollment>("/api/login/totp/enroll", {
+    challenge_token
+  });
+  return data;
+};
+
+// 登録を済ませたときだけ recovery_codes が返る
+export const loginWithTotp = async (challenge_token: string, code: string) => {
+  const { data } = await AxiosInstance.post<User & { recovery_codes?: string[] }>("/api/login/totp", {
+    challenge_token,
+    code
+  });
 
   // セッション情報をサーバーサイドに保存
   await AxiosInstance.post("/session", data);
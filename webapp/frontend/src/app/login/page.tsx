@@ -5,23 +5,71 @@ import { TextField, Button, Container, Typography } from "@mui/material";
 import { useAuth } from "@/context/AuthContext";
 import { useRouter } from "next/navigation";
 import { NextPage } from "next";
-import { login } from "@/api/user";
+import { beginTotpEnrollmentForLogin, login, loginWithTotp, TotpChallenge, TotpEnrollment, User } from "@/api/user";
 
 const Login: NextPage = () => {
   const [username, setUsername] = useState("");
   const [password, setPassword] = useState("");
+  const [challengeToken, setChallengeToken] = useState<string | null>(null);
+  const [enrollment, setEnrollment] = useState<TotpEnrollment | null>(null);
+  const [code, setCode] = useState("");
+  // 登録直後のリカバリーコードは一度しか表示できないので、確認してから画面を移る
+  const [recoveryCodes, setRecoveryCodes] = useState<string[] | null>(null);
+  const [loggedInUser, setLoggedInUser] = useState<User | null>(null);
   const [error, setError] = useState("");
   const router = useRouter();
   const { isAuthenticated, setUserInfo } = useAuth();
 
   const handleSubmit = async (event: React.FormEvent) => {
     event.preventDefault();
+    let data: User | TotpChallenge;
     try {
-      const data = await login(username, password);
+      data = await login(username, password);
+    } catch {
+      setError("ユーザ名もしくはパスワードが違います");
+      return;
+    }
+    if (!("challenge_token" in data)) {
+      setUserInfo(data);
+      router.push("/");
+      return;
+    }
+    if (data.totp_enrollment_required) {
+      try {
+        setEnrollment(await beginTotpEnrollmentForLogin(data.challenge_token));
+      } catch {
+        setError("2段階認証の登録を開始できませんでした");
+        return;
+      }
+    }
+    setError("");
+    setChallengeToken(data.challenge_token);
+  };
+
+  const handleTotpSubmit = async (event: React.FormEvent) => {
+    event.preventDefault();
+    if (!challengeToken) {
+      return;
+    }
+    try {
+      const data = await loginWithTotp(challengeToken, code);
+      if (data.recovery_codes) {
+        setError("");
+        setLoggedInUser(data);
+        setRecoveryCodes(data.recovery_codes);
+        return;
+      }
       setUserInfo(data);
       router.push("/");
     } catch {
-      setError("ユーザ名もしくはパスワードが違います");
+      setError("確認コードが違います");
+    }
+  };
+
+  const handleRecoveryCodesConfirmed = () => {
+    if (loggedInUser) {
+      setUserInfo(loggedInUser);
+      router.push("/");
     }
   };
 
@@ -41,37 +89,96 @@ const Login: NextPage = () => {
         ログイン
       </Typography>
       {error && <Typography color="error">{error}</Typography>}
-      <form onSubmit={handleSubmit}>
-        <TextField
-          fullWidth
-          id="input-username"
-          label="Username"
-          margin="normal"
-          value={username}
-          onChange={(e) => setUsername(e.target.value)}
-          required
-        />
-        <TextField
-          fullWidth
-          id="input-password"
-          label="Password"
-          type="password"
-          margin="normal"
-          value={password}
-          onChange={(e) => setPassword(e.target.value)}
-          required
-        />
-        <Button
-          fullWidth
-          id="button-login"
-          variant="contained"
-          color="primary"
-          type="submit"
-          style={{ marginTop: "16px" }}
-        >
-          Login
-        </Button>
-      </form>
+      {recoveryCodes ? (
+        <>
+          <Typography gutterBottom>
+            端末をなくしたときに使うリカバリーコードです。再表示できないので、安全な場所に控えてください。
+          </Typography>
+          <Typography component="pre" id="totp-recovery-codes" fontFamily="monospace">
+            {recoveryCodes.join("\n")}
+          </Typography>
+          <Button
+            fullWidth
+            id="button-recovery-codes-confirmed"
+            variant="contained"
+            color="primary"
+            onClick={handleRecoveryCodesConfirmed}
+            style={{ marginTop: "16px" }}
+          >
+            Continue
+          </Button>
+        </>
+      ) : challengeToken ? (
+        <form onSubmit={handleTotpSubmit}>
+          {enrollment && (
+            <>
+              <Typography gutterBottom>
+                2段階認証の登録が必要です。認証アプリに次の URI を読み込ませるか、共有鍵を入力してください。
+              </Typography>
+              <Typography id="totp-provisioning-uri" sx={{ wordBreak: "break-all" }}>
+                <a href={enrollment.provisioning_uri}>{enrollment.provisioning_uri}</a>
+              </Typography>
+              <Typography id="totp-secret" fontFamily="monospace" gutterBottom>
+                {enrollment.secret}
+              </Typography>
+            </>
+          )}
+          <TextField
+            fullWidth
+            id="input-totp-code"
+            label={
+              enrollment ? "認証アプリの確認コード" : "認証アプリの確認コード (またはリカバリーコード)"
+            }
+            margin="normal"
+            value={code}
+            onChange={(e) => setCode(e.target.value)}
+            autoComplete="one-time-code"
+            required
+          />
+          <Button
+            fullWidth
+            id="button-totp"
+            variant="contained"
+            color="primary"
+            type="submit"
+            style={{ marginTop: "16px" }}
+          >
+            Verify
+          </Button>
+        </form>
+      ) : (
+        <form onSubmit={handleSubmit}>
+          <TextField
+            fullWidth
+            id="input-username"
+            label="Username"
+            margin="normal"
+            value={username}
+            onChange={(e) => setUsername(e.target.value)}
+            required
+          />
+          <TextField
+            fullWidth
+            id="input-password"
+            label="Password"
+            type="password"
+            margin="normal"
+            value={password}
+            onChange={(e) => setPassword(e.target.value)}
+            required
+          />
+          <Button
+            fullWidth
+            id="button-login"
+            variant="contained"
+            color="primary"
+            type="submit"
+            style={{ marginTop: "16px" }}
+          >
+            Login
+          </Button>
+        </form>
+      )}
     </Container>
   );
 };
@@ -13,20 +13,16 @@ type Props = {
 const Orders: NextPage<Props> = async ({ searchParams }) => {
   const session = cookies().get("session");
 
-  let area: number | null = null;
   let sessionToken = "";
   if (session) {
     const user: User = JSON.parse(session.value);
     sessionToken = user.session_token;
-
-    if (user.role === "dispatcher") {
-      area = user.area_id;
-    }
   } else {
     redirect("/login");
   }
 
-  const orders = await fetchOrders(searchParams, area, sessionToken);
+  // ディスパッチャーはエリアを指定しなければ担当エリアすべての依頼が返る
+  const orders = await fetchOrders(searchParams, null, sessionToken);
 
   return (
     <Container>
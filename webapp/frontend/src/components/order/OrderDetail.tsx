@@ -67,11 +67,12 @@ const OrderDetail: React.FC<Props> = ({ order }) => {
       }
 
       const orderTime = new Date().toISOString();
+      // ドライバーが承諾するまでは打診中 (offered) になる
       await arrangeTowTruck(dispatcherId, order.id, nearestTowTruckId, orderTime, sessionToken);
-      order.status = "dispatched";
+      order.status = "offered";
 
       handleDialogClose();
-      setSnackbarMessage("レッカー車が正常に手配されました。");
+      setSnackbarMessage("ドライバーにレッカー車の手配を打診しました。");
       setSnackbarOpen(true);
     } catch {
       setError("レッカー車の手配に失敗しました。もう一度やりなおしてください。");
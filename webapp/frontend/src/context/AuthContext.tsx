@@ -9,7 +9,7 @@ interface AuthContextType {
   role: Role | null;
   userId: number | null;
   dispatcherId: number | null;
-  areaId: number | null;
+  areaIds: number[] | null;
   setUserInfo: (user: User) => void;
   removeUserInfo: () => void;
 }
@@ -22,7 +22,7 @@ export const AuthProvider: React.FC<{ children: ReactNode }> = ({ children }) =>
   const [role, setRole] = useState<Role | null>(null);
   const [userId, setUserId] = useState<number | null>(null);
   const [dispatcherId, setDispatcherId] = useState<number | null>(null);
-  const [areaId, setAreaId] = useState<number | null>(null);
+  const [areaIds, setAreaIds] = useState<number[] | null>(null);
 
   const setUserInfoState = (user: User) => {
     setSessionToken(user.session_token);
@@ -30,7 +30,7 @@ export const AuthProvider: React.FC<{ children: ReactNode }> = ({ children }) =>
     setUserId(user.user_id);
     if (user.role === "dispatcher") {
       setDispatcherId(user.dispatcher_id);
-      setAreaId(user.area_id);
+      setAreaIds(user.area_ids);
     }
   };
 
@@ -45,7 +45,7 @@ export const AuthProvider: React.FC<{ children: ReactNode }> = ({ children }) =>
     setRole(null);
     setUserId(null);
     setDispatcherId(null);
-    setAreaId(null);
+    setAreaIds(null);
   };
 
   const verifyUser = async () => {
@@ -74,7 +74,7 @@ export const AuthProvider: React.FC<{ children: ReactNode }> = ({ children }) =>
 
   return (
     <AuthContext.Provider
-      value={{ isAuthenticated, sessionToken, role, userId, dispatcherId, areaId, setUserInfo, removeUserInfo }}
+      value={{ isAuthenticated, sessionToken, role, userId, dispatcherId, areaIds, setUserInfo, removeUserInfo }}
     >
       {children}
     </AuthContext.Provider>
@@ -0,0 +1,2 @@
+-- 退会したユーザー (依頼の履歴を残すため行は消さずに匿名化する)
+ALTER TABLE users ADD COLUMN deleted_at DATETIME;
@@ -0,0 +1,21 @@
+-- admin が無効にしたアカウント
+ALTER TABLE users ADD COLUMN disabled_at DATETIME;
+
+-- admin がなりすましのために発行したセッションなら、その admin のユーザー ID
+ALTER TABLE sessions ADD COLUMN impersonator_id INT;
+
+-- admin の操作履歴
+CREATE TABLE IF NOT EXISTS admin_audit_logs (
+    id INT AUTO_INCREMENT PRIMARY KEY,
+    admin_user_id INT NOT NULL,
+    action VARCHAR(50) NOT NULL,
+    target_user_id INT,
+    details TEXT NOT NULL,
+    created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CALL DropIndexIfExists ('admin_audit_logs', 'idx_created_at');
+CREATE INDEX `idx_created_at` ON `admin_audit_logs` (`created_at`);
+
+CALL DropIndexIfExists ('admin_audit_logs', 'idx_target_user_id');
+CREATE INDEX `idx_target_user_id` ON `admin_audit_logs` (`target_user_id`);
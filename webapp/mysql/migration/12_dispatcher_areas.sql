@@ -0,0 +1,14 @@
+-- ディスパッチャーと担当エリアの対応 (夜間は 1 人で複数のエリアを受け持つ)
+CREATE TABLE IF NOT EXISTS dispatcher_areas (
+    dispatcher_id INT NOT NULL,
+    area_id INT NOT NULL,
+    PRIMARY KEY (dispatcher_id, area_id)
+);
+
+CALL DropIndexIfExists ('dispatcher_areas', 'idx_area_id');
+CREATE INDEX `idx_area_id` ON `dispatcher_areas` (`area_id`);
+
+INSERT IGNORE INTO dispatcher_areas (dispatcher_id, area_id)
+SELECT id, area_id FROM dispatchers;
+
+ALTER TABLE dispatchers DROP COLUMN area_id;
@@ -0,0 +1,16 @@
+-- 2 段階認証 (TOTP) の共有鍵 (16進)。totp_enabled_at が NULL なら登録の確認待ち
+ALTER TABLE users ADD COLUMN totp_secret VARCHAR(64);
+ALTER TABLE users ADD COLUMN totp_enabled_at DATETIME;
+-- 最後に受け付けたコードのタイムステップ (同じコードの使い回しを防ぐ)
+ALTER TABLE users ADD COLUMN totp_last_used_step BIGINT;
+
+-- 端末をなくしたとき用の使い捨てコード (SHA-256 のみ保存する)
+CREATE TABLE IF NOT EXISTS totp_recovery_codes (
+    id INT AUTO_INCREMENT PRIMARY KEY,
+    user_id INT NOT NULL,
+    code_hash CHAR(64) NOT NULL,
+    used_at DATETIME
+);
+
+CALL DropIndexIfExists ('totp_recovery_codes', 'idx_user_id_code_hash');
+CREATE INDEX `idx_user_id_code_hash` ON `totp_recovery_codes` (`user_id`, `code_hash`);
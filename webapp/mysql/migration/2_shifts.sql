@@ -0,0 +1,28 @@
+-- ドライバーの勤務 (シフト) 履歴
+CREATE TABLE IF NOT EXISTS shifts (
+    id INT AUTO_INCREMENT PRIMARY KEY,
+    driver_id INT NOT NULL,
+    tow_truck_id INT NOT NULL,
+    started_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    ended_at DATETIME,
+    -- 勤務中のシフトだけ driver_id が入る (ユニーク制約で同時に 2 つ開始できないようにする)
+    open_driver_id INT AS (IF(ended_at IS NULL, driver_id, NULL)) STORED,
+    FOREIGN KEY (driver_id) REFERENCES users(id) ON DELETE CASCADE,
+    FOREIGN KEY (tow_truck_id) REFERENCES tow_trucks(id) ON DELETE CASCADE
+);
+
+-- shifts の (driver_id, ended_at) にインデックス
+CALL DropIndexIfExists ('shifts', 'idx_driver_id_ended_at');
+CREATE INDEX `idx_driver_id_ended_at` ON `shifts` (`driver_id`, `ended_at`);
+
+CALL DropIndexIfExists ('shifts', 'uk_open_driver_id');
+CREATE UNIQUE INDEX `uk_open_driver_id` ON `shifts` (`open_driver_id`);
+
+-- 既存のドライバーは勤務中として扱う
+INSERT INTO shifts (driver_id, tow_truck_id)
+SELECT tt.driver_id, MIN(tt.id)
+FROM tow_trucks tt
+WHERE NOT EXISTS (
+    SELECT 1 FROM shifts s WHERE s.driver_id = tt.driver_id AND s.ended_at IS NULL
+)
+GROUP BY tt.driver_id;
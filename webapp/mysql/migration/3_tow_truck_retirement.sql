@@ -0,0 +1,2 @@
+-- 廃車したレッカー車は履歴のために残し、retired_at を記録する
+ALTER TABLE tow_trucks ADD COLUMN retired_at DATETIME;
@@ -0,0 +1,8 @@
+-- レッカー車の車両クラス (wheel_lift < flatbed < heavy)
+ALTER TABLE tow_trucks ADD COLUMN vehicle_class VARCHAR(50) NOT NULL DEFAULT 'wheel_lift';
+
+-- 既存のレッカー車は一律 flatbed として扱い、これまで通り全ての依頼に対応できるようにする
+UPDATE tow_trucks SET vehicle_class = 'flatbed';
+
+-- 依頼ごとに明示的に指定された車両クラス (NULL の場合は car_value から決める)
+ALTER TABLE orders ADD COLUMN required_vehicle_class VARCHAR(50);
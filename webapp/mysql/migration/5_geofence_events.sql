@@ -0,0 +1,16 @@
+-- レッカー車が担当エリアから出た / 戻ったことの記録
+CREATE TABLE IF NOT EXISTS geofence_events (
+    id INT AUTO_INCREMENT PRIMARY KEY,
+    tow_truck_id INT NOT NULL,
+    area_id INT NOT NULL,
+    node_id INT NOT NULL,
+    node_area_id INT NOT NULL,
+    event_type VARCHAR(50) NOT NULL,
+    created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY (tow_truck_id) REFERENCES tow_trucks(id) ON DELETE CASCADE,
+    FOREIGN KEY (node_id) REFERENCES nodes(id) ON DELETE CASCADE
+);
+
+-- geofence_events の (area_id, created_at) にインデックス
+CALL DropIndexIfExists ('geofence_events', 'idx_area_id_created_at');
+CREATE INDEX `idx_area_id_created_at` ON `geofence_events` (`area_id`, `created_at` DESC);
@@ -0,0 +1,38 @@
+-- 依頼の費用を負担する保険会社など (Webhook の通知先を絞り込むのに使う)
+ALTER TABLE orders ADD COLUMN sponsor VARCHAR(50);
+
+-- 外部システムへイベントを通知する Webhook の登録先
+CREATE TABLE IF NOT EXISTS webhook_subscriptions (
+    id INT AUTO_INCREMENT PRIMARY KEY,
+    url VARCHAR(2048) NOT NULL,
+    secret VARCHAR(255) NOT NULL,
+    event_types VARCHAR(1024) NOT NULL,
+    -- NULL なら運営側の登録先ですべてのイベントを受け取る。それ以外はこの sponsor の依頼のイベントだけを受け取る
+    sponsor VARCHAR(50),
+    is_active BOOLEAN NOT NULL DEFAULT TRUE,
+    created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+-- Webhook の配信記録 (再送と再実行のため payload をそのまま残す)
+CREATE TABLE IF NOT EXISTS webhook_deliveries (
+    id INT AUTO_INCREMENT PRIMARY KEY,
+    subscription_id INT NOT NULL,
+    event_type VARCHAR(50) NOT NULL,
+    payload TEXT NOT NULL,
+    status VARCHAR(20) NOT NULL DEFAULT 'pending',
+    attempts INT NOT NULL DEFAULT 0,
+    response_status INT,
+    last_error TEXT,
+    next_attempt_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    delivered_at DATETIME,
+    FOREIGN KEY (subscription_id) REFERENCES webhook_subscriptions(id) ON DELETE CASCADE
+);
+
+-- webhook_deliveries の (status, next_attempt_at) にインデックス
+CALL DropIndexIfExists ('webhook_deliveries', 'idx_status_next_attempt_at');
+CREATE INDEX `idx_status_next_attempt_at` ON `webhook_deliveries` (`status`, `next_attempt_at`);
+
+-- webhook_deliveries の (subscription_id, created_at) にインデックス
+CALL DropIndexIfExists ('webhook_deliveries', 'idx_subscription_id_created_at');
+CREATE INDEX `idx_subscription_id_created_at` ON `webhook_deliveries` (`subscription_id`, `created_at` DESC);
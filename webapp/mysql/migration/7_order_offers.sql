@@ -0,0 +1,26 @@
+-- 配車時にドライバーへ出した打診の記録 (依頼ごとの打診の履歴になる)
+CREATE TABLE IF NOT EXISTS order_offers (
+    id INT AUTO_INCREMENT PRIMARY KEY,
+    order_id INT NOT NULL,
+    tow_truck_id INT NOT NULL,
+    dispatcher_id INT NOT NULL,
+    status VARCHAR(20) NOT NULL DEFAULT 'pending',
+    created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    expires_at DATETIME NOT NULL,
+    responded_at DATETIME,
+    FOREIGN KEY (order_id) REFERENCES orders(id) ON DELETE CASCADE,
+    FOREIGN KEY (tow_truck_id) REFERENCES tow_trucks(id) ON DELETE CASCADE,
+    FOREIGN KEY (dispatcher_id) REFERENCES dispatchers(id) ON DELETE CASCADE
+);
+
+-- order_offers の (order_id, created_at) にインデックス
+CALL DropIndexIfExists ('order_offers', 'idx_order_id_created_at');
+CREATE INDEX `idx_order_id_created_at` ON `order_offers` (`order_id`, `created_at`);
+
+-- order_offers の (tow_truck_id, status) にインデックス
+CALL DropIndexIfExists ('order_offers', 'idx_tow_truck_id_status');
+CREATE INDEX `idx_tow_truck_id_status` ON `order_offers` (`tow_truck_id`, `status`);
+
+-- order_offers の (status, expires_at) にインデックス
+CALL DropIndexIfExists ('order_offers', 'idx_status_expires_at');
+CREATE INDEX `idx_status_expires_at` ON `order_offers` (`status`, `expires_at`);
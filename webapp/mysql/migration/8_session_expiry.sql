@@ -0,0 +1,7 @@
+-- セッションの絶対期限と無操作期限の判定に使う
+ALTER TABLE sessions ADD COLUMN created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP;
+ALTER TABLE sessions ADD COLUMN last_used_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP;
+
+-- sessions の user_id にインデックス
+CALL DropIndexIfExists ('sessions', 'idx_user_id');
+CREATE INDEX `idx_user_id` ON `sessions` (`user_id`);
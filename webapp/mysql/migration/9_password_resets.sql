@@ -0,0 +1,14 @@
+-- パスワード再設定トークン (トークン自体ではなく SHA-256 を保存する)
+CREATE TABLE IF NOT EXISTS password_reset_tokens (
+    id INT AUTO_INCREMENT PRIMARY KEY,
+    user_id INT NOT NULL,
+    token_hash CHAR(64) NOT NULL,
+    created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    used_at DATETIME
+);
+
+CALL DropIndexIfExists ('password_reset_tokens', 'idx_token_hash');
+CREATE UNIQUE INDEX `idx_token_hash` ON `password_reset_tokens` (`token_hash`);
+
+CALL DropIndexIfExists ('password_reset_tokens', 'idx_user_id');
+CREATE INDEX `idx_user_id` ON `password_reset_tokens` (`user_id`);